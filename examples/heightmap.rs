@@ -5,17 +5,80 @@ use std::{
     time::{SystemTime, UNIX_EPOCH},
 };
 
+use bytemuck::{Pod, Zeroable};
 use glfw::Key;
 use magiebleue::{
     Application, WindowContext,
     functions::{gen_patches, get_error, set_clear_color},
-    gl_objects::{Primitive, VertexArray},
-    shaders::ShaderProgram,
+    gl_objects::{Primitive, VertexArray, VertexLayout},
+    hot_reload::WatchedProgram,
     textures::{self, TexScaleOp, TexWrapBehaviour, Texture, TextureType},
+    uniform_blocks::{ShaderBlock, UniformBuffer},
     wavefront_parser::Vertex,
 };
-use noise::{core::open_simplex::open_simplex_2d, permutationtable::PermutationTable, utils::PlaneMapBuilder};
-use ultraviolet::{IVec2, Vec3, Vec4};
+use noise::{
+    core::open_simplex::open_simplex_2d,
+    permutationtable::PermutationTable,
+    utils::{NoiseMap, PlaneMapBuilder},
+};
+use ultraviolet::{IVec2, Mat4, Vec3, Vec4};
+
+/// The `Camera` uniform block shared by both programs, uploaded once per frame
+#[derive(ShaderBlock)]
+struct Camera {
+    view: Mat4,
+    proj: Mat4,
+}
+
+/// One tree drawn by the instanced tree draw
+#[derive(Clone, Copy, Pod, Zeroable, VertexLayout)]
+#[repr(C)]
+struct Tree {
+    #[location(3)]
+    model: Mat4,
+}
+
+/// A cone standing on the origin, 2 units tall
+fn tree_mesh() -> Vec<Vertex> {
+    const SIDES: usize = 8;
+    let apex = Vec3::new(0.0, 2.0, 0.0);
+    let mut vertices = Vec::with_capacity(SIDES * 3);
+    for side in 0..SIDES {
+        let corner = |i: usize| {
+            let angle = i as f32 / SIDES as f32 * 2.0 * PI;
+            Vec3::new(angle.cos() * 0.6, 0.0, angle.sin() * 0.6)
+        };
+        let (a, b) = (corner(side), corner(side + 1));
+        let normal = (apex - a).cross(b - a).normalized();
+        // wound so the outside faces counter-clockwise
+        for p in [a, apex, b] {
+            vertices.push([p.x, p.y, p.z, 0.0, 0.0, normal.x, normal.y, normal.z]);
+        }
+    }
+    vertices
+}
+
+/// Places a tree every few units on the heightmap, at the height the tessellation shaders give the terrain there
+fn plant_trees(map: &NoiseMap) -> Vec<Tree> {
+    let (width, height) = map.size();
+    let mut trees = Vec::new();
+    for i in 0..32 {
+        for j in 0..32 {
+            // deterministic jitter, so the trees don't stand in rows
+            let jitter = ((i * 31 + j * 17) as f32).sin() * 3.0;
+            let x = -124.0 + i as f32 * 8.0 + jitter;
+            let z = -124.0 + j as f32 * 8.0 - jitter;
+            let u = (((x + 128.0) / 256.0 * width as f32) as usize).min(width - 1);
+            let v = (((z + 128.0) / 256.0 * height as f32) as usize).min(height - 1);
+            // matches `fill_noise` and the displacement in heightmap.tese
+            let y = (map.get_value(u, v) as f32 + 0.5) * 24.0;
+            trees.push(Tree {
+                model: Mat4::from_translation(Vec3::new(x, y - 0.2, z)),
+            });
+        }
+    }
+    trees
+}
 
 ///
 /// Heightmap example
@@ -23,6 +86,9 @@ use ultraviolet::{IVec2, Vec3, Vec4};
 /// Vertex, tessellation control & evaluation, and fragment shaders
 /// Mouse & keyboard input
 /// Basic noise generation
+/// Instanced trees, one draw call for all of them
+/// Screenshots with F12
+/// Shaders reload when their files are saved
 /// 
 
 fn main() {
@@ -42,20 +108,24 @@ fn main() {
     let mut view = Default::default();
     let proj;
 
-    let heightmap_shader;
-    let base_shader;
+    // declared before the GL objects so it drops after them, while its context can still delete them
+    let mut application = Application::start(WindowContext {
+        size: IVec2::new(1920, 1080),
+        window_title: "Magiebleue - Heightmap".to_owned(),
+        window_mode: glfw::WindowMode::Windowed,
+    })
+    .expect("window should open");
+
+    let mut heightmap_shader;
+    let mut base_shader;
 
     let mut heightmap_vao;
     let mut plane_vao;
 
     let heightmap_texture;
 
-    let mut application = Application::start(WindowContext {
-        size: IVec2::new(1920, 1080),
-        window_title: "Magiebleue - Heightmap".to_owned(),
-        window_mode: glfw::WindowMode::Windowed,
-    });
-    heightmap_shader = ShaderProgram::from_filepath(
+    application.set_screenshot_hotkey(Key::F12, ".");
+    heightmap_shader = WatchedProgram::from_filepath(
         "shaders/heightmap.vert",
         Some("shaders/heightmap.tesc"),
         Some("shaders/heightmap.tese"),
@@ -65,9 +135,24 @@ fn main() {
     .unwrap();
 
     base_shader =
-        ShaderProgram::from_filepath("shaders/base.vert", None, None, None, "shaders/base.frag")
+        WatchedProgram::from_filepath("shaders/base.vert", None, None, None, "shaders/base.frag")
             .unwrap();
 
+    let camera = UniformBuffer::shared(
+        "Camera",
+        &Camera {
+            view: Mat4::identity(),
+            proj: Mat4::identity(),
+        },
+    )
+    .expect("camera buffer should create");
+    let mut tree_shader =
+        WatchedProgram::from_filepath("shaders/tree.vert", None, None, None, "shaders/tree.frag")
+            .unwrap();
+    heightmap_shader.bind_shared_blocks().unwrap();
+    base_shader.bind_shared_blocks().unwrap();
+    tree_shader.bind_shared_blocks().unwrap();
+
     set_clear_color(Vec4::new(0.2, 0.3, 0.3, 1.0));
 
     let mut vertices: Option<Vec<Vertex>> = Some(Vec::new());
@@ -90,10 +175,14 @@ fn main() {
     ];
 
     heightmap_vao = VertexArray::new().expect("VAO should create");
-    heightmap_vao.attach_vertex(vertices.unwrap());
+    heightmap_vao
+        .attach_vertex(vertices.unwrap())
+        .expect("vertices should attach");
 
     plane_vao = VertexArray::new().expect("VAO should create");
-    plane_vao.attach_vertex(plane_data.to_vec());
+    plane_vao
+        .attach_vertex(plane_data.to_vec())
+        .expect("vertices should attach");
 
     unsafe {
         gl::PatchParameteri(gl::PATCH_VERTICES, 4);
@@ -118,11 +207,20 @@ fn main() {
             .set_y_bounds(0.0, 5.0)
             .build();
     
-    Texture::fill_noise(128, map);
+    let trees = plant_trees(&map);
+    Texture::fill_noise(128, map).unwrap();
     Texture::gen_mipmap(TextureType::Tex2d);
     Texture::set_dual_wrap_behaviour(TextureType::Tex2d, TexWrapBehaviour::ClampToBorder);
     Texture::set_border_colour(TextureType::Tex2d, Vec4::zero());
-    Texture::set_dual_scale_behaviour(TextureType::Tex2d, TexScaleOp::Linear);
+    Texture::set_dual_scale_behaviour(TextureType::Tex2d, TexScaleOp::Linear).unwrap();
+
+    let mut tree_vao = VertexArray::new().expect("VAO should create");
+    tree_vao
+        .attach_vertex(tree_mesh())
+        .expect("vertices should attach");
+    tree_vao
+        .attach_instances(&trees)
+        .expect("trees should attach");
 
     let yaw_cb = Rc::clone(&yaw);
     let pitch_cb = Rc::clone(&pitch);
@@ -172,23 +270,33 @@ fn main() {
             model = ultraviolet::Mat4::identity();
 
             view = ultraviolet::Mat4::look_at(camera_pos, camera_pos + camera_front, -camera_up);
+            camera.update(&Camera { view, proj });
+
+            heightmap_shader.reload_if_changed();
+            base_shader.reload_if_changed();
+            tree_shader.reload_if_changed();
 
             unsafe {
                 gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
 
                 base_shader.use_program();
-                base_shader.set_matrix_uniforms(&model, &view, &proj);
+                base_shader.set_uniform("model", model);
 
-                plane_vao.draw(Primitive::TriangleFan);
+                plane_vao.draw(Primitive::TriangleFan).unwrap();
 
                 textures::set_texture_slot(0);
                 heightmap_texture.bind(TextureType::Tex2d);
 
                 heightmap_shader.use_program();
-                heightmap_shader.set_matrix_uniforms(&model, &view, &proj);
+                heightmap_shader.set_uniform("model", model);
+
+                heightmap_vao.draw(Primitive::Patches).unwrap();
 
-                heightmap_vao.draw(Primitive::Patches);
-                get_error(Some("end of render"));
+                tree_shader.use_program();
+                tree_vao
+                    .draw_instanced(Primitive::Triangles, trees.len())
+                    .unwrap();
+                get_error("end of render").unwrap();
             }
 
             for item in keys_pressed.iter() {
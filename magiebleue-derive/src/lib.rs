@@ -0,0 +1,201 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Fields, LitInt, parse_macro_input, spanned::Spanned};
+
+/// Implements `magiebleue::gl_objects::VertexLayout` for a `#[repr(C)]` struct.
+///
+/// Every field with `#[location(n)]` becomes an attribute at that location, named after the field, with its component
+/// count and type taken from the field's `VertexComponent` implementation. Fields without a location are left out, for padding.
+/// `#[normalized]` maps integer components to 0-1 or -1-1, `#[integer]` keeps them integers for `int`/`uint` inputs,
+/// and `#[divisor(n)]` makes the attribute advance every n instances.
+/// Matrix fields take one location per column, starting at the given one.
+///
+/// Fails to compile without `#[repr(C)]`, for enums, tuple structs and unions, for options on a field without a location,
+/// for a field both `#[normalized]` and `#[integer]`, and for two fields at the same location
+#[proc_macro_derive(VertexLayout, attributes(location, normalized, integer, divisor))]
+pub fn derive_vertex_layout(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_vertex_layout(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand_vertex_layout(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let mut repr_c = false;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("repr")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("C") {
+                repr_c = true;
+            }
+            Ok(())
+        })?;
+    }
+    if !repr_c {
+        return Err(Error::new(
+            input.ident.span(),
+            "VertexLayout needs #[repr(C)] so field offsets match what GL reads",
+        ));
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    input.ident.span(),
+                    "VertexLayout can only be derived for structs with named fields",
+                ));
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.ident.span(),
+                "VertexLayout can only be derived for structs",
+            ));
+        }
+    };
+
+    let mut attributes = Vec::new();
+    let mut locations = Vec::new();
+    for field in fields {
+        let mut location = None;
+        let mut normalized = false;
+        let mut integer = false;
+        let mut divisor = None;
+        for attr in &field.attrs {
+            if attr.path().is_ident("location") {
+                let lit: LitInt = attr.parse_args()?;
+                location = Some(lit.base10_parse::<u32>()?);
+            } else if attr.path().is_ident("normalized") {
+                attr.meta.require_path_only()?;
+                normalized = true;
+            } else if attr.path().is_ident("integer") {
+                attr.meta.require_path_only()?;
+                integer = true;
+            } else if attr.path().is_ident("divisor") {
+                let lit: LitInt = attr.parse_args()?;
+                divisor = Some(lit.base10_parse::<u32>()?);
+            }
+        }
+
+        let Some(location) = location else {
+            if normalized || integer || divisor.is_some() {
+                return Err(Error::new(
+                    field.span(),
+                    "attribute options need a #[location(n)] on the same field",
+                ));
+            }
+            continue;
+        };
+        if normalized && integer {
+            return Err(Error::new(
+                field.span(),
+                "a field can't be both #[normalized] and #[integer]",
+            ));
+        }
+        if locations.contains(&location) {
+            return Err(Error::new(
+                field.span(),
+                format!("location {} is used by more than one field", location),
+            ));
+        }
+        locations.push(location);
+
+        let ident = field.ident.as_ref().expect("named fields have names");
+        let name = ident.to_string();
+        let ty = &field.ty;
+        let mut attribute = quote! {
+            ::magiebleue::gl_objects::VertexAttribute::new(
+                #name,
+                #location,
+                <#ty as ::magiebleue::gl_objects::VertexComponent>::COMPONENTS,
+                <#ty as ::magiebleue::gl_objects::VertexComponent>::TYPE,
+                ::core::mem::offset_of!(Self, #ident),
+            )
+        };
+        if normalized {
+            attribute = quote! { #attribute.normalized() };
+        }
+        if integer {
+            attribute = quote! { #attribute.integer() };
+        }
+        if let Some(divisor) = divisor {
+            attribute = quote! { #attribute.per_instance(#divisor) };
+        }
+        attributes.push(quote! {
+            attributes.extend(#attribute.columns(
+                <#ty as ::magiebleue::gl_objects::VertexComponent>::LOCATIONS,
+                ::core::mem::size_of::<#ty>()
+                    / <#ty as ::magiebleue::gl_objects::VertexComponent>::LOCATIONS as usize,
+            ));
+        });
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::magiebleue::gl_objects::VertexLayout for #ident #ty_generics #where_clause {
+            fn attributes() -> ::std::vec::Vec<::magiebleue::gl_objects::VertexAttribute> {
+                let mut attributes = ::std::vec::Vec::new();
+                #(#attributes)*
+                attributes
+            }
+        }
+    })
+}
+
+/// Implements `magiebleue::uniform_blocks::ShaderBlock` for a struct, so it can fill a uniform or shader storage block.
+///
+/// Fields are written in declaration order, each at the offset std140 or std430 gives it, so they must be declared
+/// in the same order as the members of the GLSL block or struct. No `#[repr(C)]` is needed, padding is added as it's encoded
+#[proc_macro_derive(ShaderBlock)]
+pub fn derive_shader_block(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_shader_block(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand_shader_block(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    input.ident.span(),
+                    "ShaderBlock can only be derived for structs with named fields",
+                ));
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.ident.span(),
+                "ShaderBlock can only be derived for structs",
+            ));
+        }
+    };
+
+    let types: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+    let idents: Vec<_> = fields
+        .iter()
+        .map(|field| field.ident.as_ref().expect("named fields have names"))
+        .collect();
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::magiebleue::uniform_blocks::ShaderBlock for #ident #ty_generics #where_clause {
+            fn align(layout: ::magiebleue::uniform_blocks::BlockLayout) -> usize {
+                let align = 1usize
+                    #(.max(<#types as ::magiebleue::uniform_blocks::ShaderBlock>::align(layout)))*;
+                layout.struct_align(align)
+            }
+
+            fn write(&self, writer: &mut ::magiebleue::uniform_blocks::BlockWriter) {
+                #(writer.push(&self.#idents);)*
+                writer.align_to(<Self as ::magiebleue::uniform_blocks::ShaderBlock>::align(writer.layout()));
+            }
+        }
+    })
+}
@@ -6,9 +6,7 @@ layout(location = 2) in vec3 aNormal;
 
 out vec2 vTexCoords;
 
-uniform mat4 model;
-uniform mat4 view;
-uniform mat4 proj;
+#include "include/camera.glsl"
 
 void main() {
     vTexCoords = aTexCoords;
@@ -0,0 +1,18 @@
+// bilinear interpolation across a quad patch, corners ordered 00, 01, 10, 11
+vec2 bilerp(vec2 c00, vec2 c01, vec2 c10, vec2 c11, vec2 t) {
+    vec2 c0 = (c01 - c00) * t.x + c00;
+    vec2 c1 = (c11 - c10) * t.x + c10;
+    return (c1 - c0) * t.y + c0;
+}
+
+vec3 bilerp(vec3 c00, vec3 c01, vec3 c10, vec3 c11, vec2 t) {
+    vec3 c0 = (c01 - c00) * t.x + c00;
+    vec3 c1 = (c11 - c10) * t.x + c10;
+    return (c1 - c0) * t.y + c0;
+}
+
+vec4 bilerp(vec4 c00, vec4 c01, vec4 c10, vec4 c11, vec2 t) {
+    vec4 c0 = (c01 - c00) * t.x + c00;
+    vec4 c1 = (c11 - c10) * t.x + c10;
+    return (c1 - c0) * t.y + c0;
+}
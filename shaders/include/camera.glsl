@@ -0,0 +1,7 @@
+// model transform, and the camera shared by every program drawing the scene, see `UniformBuffer::shared`
+uniform mat4 model;
+
+layout(std140) uniform Camera {
+    mat4 view;
+    mat4 proj;
+};
@@ -0,0 +1,84 @@
+#version 330 core
+
+// set by Material::apply
+struct Material {
+    vec3 ambient;
+    vec3 diffuse;
+    vec3 specular;
+    vec3 emissive;
+    float shininess;
+    float dissolve;
+    int illumination;
+
+    sampler2D diffuse_map;
+    bool has_diffuse_map;
+    vec3 diffuse_map_offset;
+    vec3 diffuse_map_scale;
+
+    sampler2D specular_map;
+    bool has_specular_map;
+    vec3 specular_map_offset;
+    vec3 specular_map_scale;
+
+    sampler2D bump_map;
+    bool has_bump_map;
+    vec3 bump_map_offset;
+    vec3 bump_map_scale;
+    float bump_multiplier;
+
+    sampler2D emissive_map;
+    bool has_emissive_map;
+    vec3 emissive_map_offset;
+    vec3 emissive_map_scale;
+};
+
+uniform Material material;
+uniform vec3 light_dir;
+uniform vec3 camera_pos;
+
+in vec3 vPos;
+in vec2 vTexCoords;
+in vec3 vNormal;
+
+out vec4 final_color;
+
+vec2 map_coords(vec3 offset, vec3 scale) {
+    return vTexCoords * scale.xy + offset.xy;
+}
+
+void main() {
+    vec3 diffuse = material.diffuse;
+    float alpha = material.dissolve;
+    if (material.has_diffuse_map) {
+        vec4 texel = texture(material.diffuse_map, map_coords(material.diffuse_map_offset, material.diffuse_map_scale));
+        diffuse *= texel.rgb;
+        alpha *= texel.a;
+    }
+    vec3 emissive = material.emissive;
+    if (material.has_emissive_map) {
+        emissive *= texture(material.emissive_map, map_coords(material.emissive_map_offset, material.emissive_map_scale)).rgb;
+    }
+
+    // illum 0: colour only
+    if (material.illumination == 0) {
+        final_color = vec4(diffuse + emissive, alpha);
+        return;
+    }
+
+    vec3 normal = normalize(vNormal);
+    vec3 to_light = normalize(-light_dir);
+    vec3 color = material.ambient * diffuse + emissive;
+    color += diffuse * max(dot(normal, to_light), 0.0);
+
+    // illum 2 and up: add Blinn-Phong highlights
+    if (material.illumination >= 2) {
+        vec3 specular = material.specular;
+        if (material.has_specular_map) {
+            specular *= texture(material.specular_map, map_coords(material.specular_map_offset, material.specular_map_scale)).rgb;
+        }
+        vec3 halfway = normalize(to_light + normalize(camera_pos - vPos));
+        color += specular * pow(max(dot(normal, halfway), 0.0), max(material.shininess, 1.0));
+    }
+
+    final_color = vec4(color, alpha);
+}
@@ -0,0 +1,21 @@
+#version 330 core
+
+layout(location = 0) in vec3 aPos;
+layout(location = 1) in vec2 aTexCoords;
+layout(location = 2) in vec3 aNormal;
+
+out vec3 vPos;
+out vec2 vTexCoords;
+out vec3 vNormal;
+
+uniform mat4 model;
+uniform mat4 view;
+uniform mat4 proj;
+
+void main() {
+    vec4 world_pos = model * vec4(aPos, 1.0);
+    vPos = world_pos.xyz;
+    vTexCoords = aTexCoords;
+    vNormal = mat3(transpose(inverse(model))) * aNormal;
+    gl_Position = proj * view * world_pos;
+}
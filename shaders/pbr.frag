@@ -0,0 +1,127 @@
+#version 330 core
+
+// set by PbrMaterial::apply
+struct Material {
+    vec4 base_color;
+    float metallic;
+    float roughness;
+    float normal_scale;
+    float occlusion_strength;
+    vec3 emissive;
+    // 0 opaque, 1 mask, 2 blend
+    int alpha_mode;
+    float alpha_cutoff;
+
+    sampler2D base_color_map;
+    bool has_base_color_map;
+    sampler2D metallic_roughness_map;
+    bool has_metallic_roughness_map;
+    sampler2D normal_map;
+    bool has_normal_map;
+    sampler2D occlusion_map;
+    bool has_occlusion_map;
+    sampler2D emissive_map;
+    bool has_emissive_map;
+};
+
+uniform Material material;
+uniform vec3 light_dir;
+uniform vec3 light_color = vec3(3.0);
+uniform vec3 camera_pos;
+
+in vec3 vPos;
+in vec2 vTexCoords;
+in vec3 vNormal;
+in vec4 vTangent;
+
+out vec4 final_color;
+
+const float PI = 3.14159265359;
+
+// tangent frame from the vertex tangents, or from screen space derivatives for meshes without them
+vec3 mapped_normal(vec3 normal) {
+    vec3 sampled = texture(material.normal_map, vTexCoords).xyz * 2.0 - 1.0;
+    sampled.xy *= material.normal_scale;
+    // back faces turn the whole frame over, so they show the same surface from behind
+    float side = gl_FrontFacing ? 1.0 : -1.0;
+    if (dot(vTangent.xyz, vTangent.xyz) > 0.0) {
+        vec3 tangent = normalize(vTangent.xyz - normal * dot(normal, vTangent.xyz));
+        // normal is already turned over, and the bitangent with it
+        vec3 bitangent = cross(normal, tangent) * vTangent.w;
+        return normalize(mat3(tangent * side, bitangent, normal) * sampled);
+    }
+    vec3 dp1 = dFdx(vPos);
+    vec3 dp2 = dFdy(vPos);
+    vec2 duv1 = dFdx(vTexCoords);
+    vec2 duv2 = dFdy(vTexCoords);
+    vec3 dp2perp = cross(dp2, normal);
+    vec3 dp1perp = cross(normal, dp1);
+    vec3 tangent = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 bitangent = dp2perp * duv1.y + dp1perp * duv2.y;
+    float scale = side * inversesqrt(max(dot(tangent, tangent), dot(bitangent, bitangent)));
+    return normalize(mat3(tangent * scale, bitangent * scale, normal) * sampled);
+}
+
+void main() {
+    vec4 base_color = material.base_color;
+    if (material.has_base_color_map) {
+        base_color *= texture(material.base_color_map, vTexCoords);
+    }
+    if (material.alpha_mode == 1 && base_color.a < material.alpha_cutoff) {
+        discard;
+    }
+    float alpha = material.alpha_mode == 2 ? base_color.a : 1.0;
+
+    float metallic = material.metallic;
+    float roughness = material.roughness;
+    if (material.has_metallic_roughness_map) {
+        vec4 mr = texture(material.metallic_roughness_map, vTexCoords);
+        roughness *= mr.g;
+        metallic *= mr.b;
+    }
+    roughness = clamp(roughness, 0.04, 1.0);
+
+    vec3 normal = normalize(vNormal);
+    if (!gl_FrontFacing) {
+        normal = -normal;
+    }
+    if (material.has_normal_map) {
+        normal = mapped_normal(normal);
+    }
+
+    vec3 to_view = normalize(camera_pos - vPos);
+    vec3 to_light = normalize(-light_dir);
+    vec3 halfway = normalize(to_view + to_light);
+    float n_dot_l = max(dot(normal, to_light), 0.0);
+    float n_dot_v = max(dot(normal, to_view), 1e-4);
+    float n_dot_h = max(dot(normal, halfway), 0.0);
+    float v_dot_h = max(dot(to_view, halfway), 0.0);
+
+    // Cook-Torrance with GGX distribution, Smith-Schlick geometry and Schlick fresnel
+    float a2 = pow(roughness, 4.0);
+    float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    float distribution = a2 / (PI * d * d);
+    float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    float geometry = n_dot_l / (n_dot_l * (1.0 - k) + k) * n_dot_v / (n_dot_v * (1.0 - k) + k);
+    vec3 f0 = mix(vec3(0.04), base_color.rgb, metallic);
+    vec3 fresnel = f0 + (1.0 - f0) * pow(1.0 - v_dot_h, 5.0);
+
+    vec3 specular = distribution * geometry * fresnel / max(4.0 * n_dot_l * n_dot_v, 1e-4);
+    vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * base_color.rgb / PI;
+    vec3 color = (diffuse + specular) * light_color * n_dot_l;
+
+    vec3 ambient = 0.03 * base_color.rgb;
+    if (material.has_occlusion_map) {
+        ambient *= mix(1.0, texture(material.occlusion_map, vTexCoords).r, material.occlusion_strength);
+    }
+    color += ambient;
+
+    vec3 emissive = material.emissive;
+    if (material.has_emissive_map) {
+        emissive *= texture(material.emissive_map, vTexCoords).rgb;
+    }
+    color += emissive;
+
+    // linear to sRGB
+    final_color = vec4(pow(color, vec3(1.0 / 2.2)), alpha);
+}
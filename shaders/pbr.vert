@@ -0,0 +1,25 @@
+#version 330 core
+
+layout(location = 0) in vec3 aPos;
+layout(location = 1) in vec2 aTexCoords;
+layout(location = 2) in vec3 aNormal;
+// (0, 0, 0, 1) when the mesh has no tangents
+layout(location = 3) in vec4 aTangent;
+
+out vec3 vPos;
+out vec2 vTexCoords;
+out vec3 vNormal;
+out vec4 vTangent;
+
+uniform mat4 model;
+uniform mat4 view;
+uniform mat4 proj;
+
+void main() {
+    vec4 world_pos = model * vec4(aPos, 1.0);
+    vPos = world_pos.xyz;
+    vTexCoords = aTexCoords;
+    vNormal = mat3(transpose(inverse(model))) * aNormal;
+    vTangent = vec4(mat3(model) * aTangent.xyz, aTangent.w);
+    gl_Position = proj * view * world_pos;
+}
@@ -0,0 +1,14 @@
+#version 330 core
+
+out vec4 final_color;
+in vec2 vTexCoords;
+
+uniform sampler2D input_image;
+uniform sampler2D bloom_image;
+uniform float intensity;
+
+void main() {
+    vec4 color = texture(input_image, vTexCoords);
+    vec3 bloom = texture(bloom_image, vTexCoords).rgb;
+    final_color = vec4(color.rgb + bloom * intensity, color.a);
+}
@@ -0,0 +1,15 @@
+#version 330 core
+
+out vec4 final_color;
+in vec2 vTexCoords;
+
+uniform sampler2D input_image;
+uniform float threshold;
+
+void main() {
+    vec3 color = texture(input_image, vTexCoords).rgb;
+    float brightness = max(color.r, max(color.g, color.b));
+    // scales the colour rather than subtracting from each channel, which keeps its hue
+    float contribution = max(brightness - threshold, 0.0) / max(brightness, 0.0001);
+    final_color = vec4(color * contribution, 1.0);
+}
@@ -0,0 +1,23 @@
+#version 330 core
+
+// one axis of a 9-tap gaussian, folded into 5 bilinear samples
+out vec4 final_color;
+in vec2 vTexCoords;
+
+uniform sampler2D input_image;
+uniform vec2 texel_size;
+// (1, 0) for horizontal, (0, 1) for vertical
+uniform vec2 direction;
+
+const float OFFSETS[3] = float[](0.0, 1.3846153846, 3.2307692308);
+const float WEIGHTS[3] = float[](0.2270270270, 0.3162162162, 0.0702702703);
+
+void main() {
+    vec2 step = direction * texel_size;
+    vec3 color = texture(input_image, vTexCoords).rgb * WEIGHTS[0];
+    for (int i = 1; i < 3; i++) {
+        color += texture(input_image, vTexCoords + step * OFFSETS[i]).rgb * WEIGHTS[i];
+        color += texture(input_image, vTexCoords - step * OFFSETS[i]).rgb * WEIGHTS[i];
+    }
+    final_color = vec4(color, 1.0);
+}
@@ -0,0 +1,34 @@
+#version 330 core
+
+// the LUT is a horizontal strip of `lut_size` slices, each lut_size x lut_size.
+// red increases to the right within a slice, green downwards, and blue from slice to slice
+out vec4 final_color;
+in vec2 vTexCoords;
+
+uniform sampler2D input_image;
+uniform sampler2D lut;
+uniform float lut_size;
+// 0 leaves the image untouched, 1 applies the LUT fully
+uniform float amount;
+
+vec3 sample_slice(vec3 color, float slice) {
+    // sample texel centres so neighbouring slices don't bleed in
+    vec2 uv = (color.rg * (lut_size - 1.0) + 0.5) / lut_size;
+    uv.x = (uv.x + slice) / lut_size;
+    // textures are uploaded bottom row first
+    uv.y = 1.0 - uv.y;
+    return texture(lut, uv).rgb;
+}
+
+void main() {
+    vec4 color = texture(input_image, vTexCoords);
+    vec3 c = clamp(color.rgb, 0.0, 1.0);
+    float blue = c.b * (lut_size - 1.0);
+    float slice = floor(blue);
+    vec3 graded = mix(
+        sample_slice(c, slice),
+        sample_slice(c, min(slice + 1.0, lut_size - 1.0)),
+        blue - slice
+    );
+    final_color = vec4(mix(color.rgb, graded, amount), color.a);
+}
@@ -0,0 +1,10 @@
+#version 330 core
+
+out vec4 final_color;
+in vec2 vTexCoords;
+
+uniform sampler2D input_image;
+
+void main() {
+    final_color = texture(input_image, vTexCoords);
+}
@@ -0,0 +1,10 @@
+#version 330 core
+
+// covers the screen with one triangle, drawn without any vertex buffer
+out vec2 vTexCoords;
+
+void main() {
+    vec2 pos = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    vTexCoords = pos;
+    gl_Position = vec4(pos * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,51 @@
+#version 330 core
+
+// FXAA 3.11 console-quality variant, run on tone mapped colour
+out vec4 final_color;
+in vec2 vTexCoords;
+
+uniform sampler2D input_image;
+uniform vec2 texel_size;
+
+const float SPAN_MAX = 8.0;
+const float REDUCE_MUL = 1.0 / 8.0;
+const float REDUCE_MIN = 1.0 / 128.0;
+
+float luma(vec3 color) {
+    return dot(color, vec3(0.299, 0.587, 0.114));
+}
+
+void main() {
+    vec3 nw = texture(input_image, vTexCoords + vec2(-1.0, -1.0) * texel_size).rgb;
+    vec3 ne = texture(input_image, vTexCoords + vec2(1.0, -1.0) * texel_size).rgb;
+    vec3 sw = texture(input_image, vTexCoords + vec2(-1.0, 1.0) * texel_size).rgb;
+    vec3 se = texture(input_image, vTexCoords + vec2(1.0, 1.0) * texel_size).rgb;
+    vec4 m = texture(input_image, vTexCoords);
+
+    float luma_nw = luma(nw);
+    float luma_ne = luma(ne);
+    float luma_sw = luma(sw);
+    float luma_se = luma(se);
+    float luma_m = luma(m.rgb);
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    vec2 dir = vec2(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se)
+    );
+    float dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    float rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * rcp_dir_min, vec2(-SPAN_MAX), vec2(SPAN_MAX)) * texel_size;
+
+    vec3 a = 0.5 * (
+        texture(input_image, vTexCoords + dir * (1.0 / 3.0 - 0.5)).rgb +
+        texture(input_image, vTexCoords + dir * (2.0 / 3.0 - 0.5)).rgb
+    );
+    vec3 b = a * 0.5 + 0.25 * (
+        texture(input_image, vTexCoords + dir * -0.5).rgb +
+        texture(input_image, vTexCoords + dir * 0.5).rgb
+    );
+    float luma_b = luma(b);
+    final_color = vec4(luma_b < luma_min || luma_b > luma_max ? a : b, m.a);
+}
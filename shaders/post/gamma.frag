@@ -0,0 +1,12 @@
+#version 330 core
+
+out vec4 final_color;
+in vec2 vTexCoords;
+
+uniform sampler2D input_image;
+uniform float gamma;
+
+void main() {
+    vec4 color = texture(input_image, vTexCoords);
+    final_color = vec4(pow(max(color.rgb, 0.0), vec3(1.0 / gamma)), color.a);
+}
@@ -0,0 +1,21 @@
+#version 330 core
+
+out vec4 final_color;
+in vec2 vTexCoords;
+
+uniform sampler2D input_image;
+uniform float exposure;
+// 0 = Reinhard, 1 = ACES
+uniform int operator;
+
+// Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 x) {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
+void main() {
+    vec4 color = texture(input_image, vTexCoords);
+    vec3 hdr = color.rgb * exposure;
+    vec3 ldr = operator == 1 ? aces(hdr) : hdr / (hdr + 1.0);
+    final_color = vec4(ldr, color.a);
+}
@@ -0,0 +1,19 @@
+#version 330 core
+
+out vec4 final_color;
+in vec2 vTexCoords;
+
+uniform sampler2D input_image;
+// how dark the corners get, 0 to 1
+uniform float strength;
+// distance from the centre where darkening starts, 0.5 reaching the edge midpoints
+uniform float radius;
+// distance over which it fades in
+uniform float softness;
+
+void main() {
+    vec4 color = texture(input_image, vTexCoords);
+    float dist = distance(vTexCoords, vec2(0.5));
+    float shade = smoothstep(radius, radius + softness, dist);
+    final_color = vec4(color.rgb * (1.0 - shade * strength), color.a);
+}
@@ -0,0 +1,10 @@
+#version 330 core
+
+in vec3 vNormal;
+
+out vec4 final_color;
+
+void main() {
+    float light = max(dot(normalize(vNormal), normalize(vec3(0.3, 1.0, 0.2))), 0.0);
+    final_color = vec4(vec3(0.1, 0.4, 0.15) * (0.3 + 0.7 * light), 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,16 @@
+#version 330 core
+
+layout(location = 0) in vec3 aPos;
+layout(location = 1) in vec2 aTexCoords;
+layout(location = 2) in vec3 aNormal;
+// per instance, one column per location
+layout(location = 3) in mat4 aModel;
+
+out vec3 vNormal;
+
+#include "include/camera.glsl"
+
+void main() {
+    vNormal = mat3(aModel) * aNormal;
+    gl_Position = proj * view * aModel * vec4(aPos, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,407 @@
+use std::{
+    ffi::{CStr, c_void},
+    sync::{Mutex, TryLockError},
+};
+
+use gl::types::{GLchar, GLenum, GLsizei, GLuint};
+
+use crate::{
+    error::{Error, Result},
+    resources::{self, ResourceKind},
+};
+
+/// Name of a `glGetError` code
+pub fn error_name(code: GLenum) -> &'static str {
+    match code {
+        gl::NO_ERROR => "GL_NO_ERROR",
+        gl::INVALID_ENUM => "GL_INVALID_ENUM",
+        gl::INVALID_VALUE => "GL_INVALID_VALUE",
+        gl::INVALID_OPERATION => "GL_INVALID_OPERATION",
+        gl::INVALID_FRAMEBUFFER_OPERATION => "GL_INVALID_FRAMEBUFFER_OPERATION",
+        gl::OUT_OF_MEMORY => "GL_OUT_OF_MEMORY",
+        gl::STACK_UNDERFLOW => "GL_STACK_UNDERFLOW",
+        gl::STACK_OVERFLOW => "GL_STACK_OVERFLOW",
+        gl::CONTEXT_LOST => "GL_CONTEXT_LOST",
+        _ => "unknown GL error",
+    }
+}
+
+/// Name of a `glCheckFramebufferStatus` result
+pub fn framebuffer_status_name(status: GLenum) -> &'static str {
+    match status {
+        gl::FRAMEBUFFER_COMPLETE => "GL_FRAMEBUFFER_COMPLETE",
+        gl::FRAMEBUFFER_UNDEFINED => "GL_FRAMEBUFFER_UNDEFINED",
+        gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => "GL_FRAMEBUFFER_INCOMPLETE_ATTACHMENT",
+        gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => {
+            "GL_FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT"
+        }
+        gl::FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => "GL_FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER",
+        gl::FRAMEBUFFER_INCOMPLETE_READ_BUFFER => "GL_FRAMEBUFFER_INCOMPLETE_READ_BUFFER",
+        gl::FRAMEBUFFER_UNSUPPORTED => "GL_FRAMEBUFFER_UNSUPPORTED",
+        gl::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => "GL_FRAMEBUFFER_INCOMPLETE_MULTISAMPLE",
+        gl::FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS => "GL_FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS",
+        _ => "unknown framebuffer status",
+    }
+}
+
+/// Where a debug message comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugSource {
+    Api = gl::DEBUG_SOURCE_API as isize,
+    WindowSystem = gl::DEBUG_SOURCE_WINDOW_SYSTEM as isize,
+    ShaderCompiler = gl::DEBUG_SOURCE_SHADER_COMPILER as isize,
+    ThirdParty = gl::DEBUG_SOURCE_THIRD_PARTY as isize,
+    /// Inserted by the application, including debug groups
+    Application = gl::DEBUG_SOURCE_APPLICATION as isize,
+    Other = gl::DEBUG_SOURCE_OTHER as isize,
+}
+
+/// What a debug message is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugType {
+    Error = gl::DEBUG_TYPE_ERROR as isize,
+    DeprecatedBehavior = gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR as isize,
+    UndefinedBehavior = gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR as isize,
+    Portability = gl::DEBUG_TYPE_PORTABILITY as isize,
+    Performance = gl::DEBUG_TYPE_PERFORMANCE as isize,
+    Marker = gl::DEBUG_TYPE_MARKER as isize,
+    PushGroup = gl::DEBUG_TYPE_PUSH_GROUP as isize,
+    PopGroup = gl::DEBUG_TYPE_POP_GROUP as isize,
+    Other = gl::DEBUG_TYPE_OTHER as isize,
+}
+
+/// How serious a debug message is, from least to most
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DebugSeverity {
+    Notification,
+    Low,
+    Medium,
+    High,
+}
+
+impl DebugSource {
+    fn from_gl(source: GLenum) -> Self {
+        match source {
+            gl::DEBUG_SOURCE_API => Self::Api,
+            gl::DEBUG_SOURCE_WINDOW_SYSTEM => Self::WindowSystem,
+            gl::DEBUG_SOURCE_SHADER_COMPILER => Self::ShaderCompiler,
+            gl::DEBUG_SOURCE_THIRD_PARTY => Self::ThirdParty,
+            gl::DEBUG_SOURCE_APPLICATION => Self::Application,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl DebugType {
+    fn from_gl(ty: GLenum) -> Self {
+        match ty {
+            gl::DEBUG_TYPE_ERROR => Self::Error,
+            gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => Self::DeprecatedBehavior,
+            gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR => Self::UndefinedBehavior,
+            gl::DEBUG_TYPE_PORTABILITY => Self::Portability,
+            gl::DEBUG_TYPE_PERFORMANCE => Self::Performance,
+            gl::DEBUG_TYPE_MARKER => Self::Marker,
+            gl::DEBUG_TYPE_PUSH_GROUP => Self::PushGroup,
+            gl::DEBUG_TYPE_POP_GROUP => Self::PopGroup,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl DebugSeverity {
+    fn from_gl(severity: GLenum) -> Self {
+        match severity {
+            gl::DEBUG_SEVERITY_HIGH => Self::High,
+            gl::DEBUG_SEVERITY_MEDIUM => Self::Medium,
+            gl::DEBUG_SEVERITY_LOW => Self::Low,
+            _ => Self::Notification,
+        }
+    }
+
+    fn gl_enum(self) -> GLenum {
+        match self {
+            Self::Notification => gl::DEBUG_SEVERITY_NOTIFICATION,
+            Self::Low => gl::DEBUG_SEVERITY_LOW,
+            Self::Medium => gl::DEBUG_SEVERITY_MEDIUM,
+            Self::High => gl::DEBUG_SEVERITY_HIGH,
+        }
+    }
+}
+
+/// A message from the GL implementation
+#[derive(Debug, Clone)]
+pub struct DebugMessage<'a> {
+    pub source: DebugSource,
+    pub ty: DebugType,
+    /// Implementation-specific message id
+    pub id: u32,
+    pub severity: DebugSeverity,
+    pub text: &'a str,
+}
+
+/// Receives debug messages. Implemented for closures taking a `&DebugMessage`
+pub trait DebugLogger: Send {
+    fn log(&mut self, message: &DebugMessage);
+}
+
+impl<F: FnMut(&DebugMessage) + Send> DebugLogger for F {
+    fn log(&mut self, message: &DebugMessage) {
+        self(message)
+    }
+}
+
+/// Prints messages at or above a severity to stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrintLogger {
+    pub min_severity: DebugSeverity,
+}
+
+impl Default for PrintLogger {
+    /// Skips notifications, which some drivers send for every buffer upload
+    fn default() -> Self {
+        Self {
+            min_severity: DebugSeverity::Low,
+        }
+    }
+}
+
+impl DebugLogger for PrintLogger {
+    fn log(&mut self, message: &DebugMessage) {
+        if message.severity >= self.min_severity {
+            eprintln!(
+                "GL {:?} {:?} ({:?}, {}): {}",
+                message.severity, message.ty, message.source, message.id, message.text
+            );
+        }
+    }
+}
+
+static LOGGER: Mutex<Option<Box<dyn DebugLogger>>> = Mutex::new(None);
+
+extern "system" fn debug_callback(
+    source: GLenum,
+    ty: GLenum,
+    id: GLuint,
+    severity: GLenum,
+    length: GLsizei,
+    message: *const GLchar,
+    _user_param: *mut c_void,
+) {
+    let text = if length >= 0 {
+        let bytes = unsafe { std::slice::from_raw_parts(message.cast::<u8>(), length as usize) };
+        String::from_utf8_lossy(bytes)
+    } else {
+        unsafe { CStr::from_ptr(message) }.to_string_lossy()
+    };
+    let message = DebugMessage {
+        source: DebugSource::from_gl(source),
+        ty: DebugType::from_gl(ty),
+        id,
+        severity: DebugSeverity::from_gl(severity),
+        text: text.trim_end(),
+    };
+    let mut logger = match LOGGER.try_lock() {
+        Ok(logger) => logger,
+        Err(TryLockError::Poisoned(e)) => e.into_inner(),
+        // the logger itself made a GL call that raised this message
+        Err(TryLockError::WouldBlock) => return,
+    };
+    if let Some(logger) = logger.as_mut() {
+        logger.log(&message);
+    }
+}
+
+/// Whether the current context was created as a debug context
+pub fn is_debug_context() -> bool {
+    let mut flags = 0;
+    unsafe { gl::GetIntegerv(gl::CONTEXT_FLAGS, &mut flags) };
+    flags as GLenum & gl::CONTEXT_FLAG_DEBUG_BIT != 0
+}
+
+/// Routes the current context's debug messages to `logger`, replacing any previous logger.
+/// Messages are delivered synchronously, so a breakpoint in the logger stops on the offending call.
+/// Fails if the context doesn't support KHR_debug. Non-debug contexts may send few or no messages
+pub fn set_logger(logger: impl DebugLogger + 'static) -> Result<()> {
+    if !gl::DebugMessageCallback::is_loaded() {
+        return Err(Error::InvalidParameter(
+            "this context doesn't support KHR_debug, so it has no debug messages to log".to_owned(),
+        ));
+    }
+    *LOGGER.lock().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(logger));
+    unsafe {
+        gl::Enable(gl::DEBUG_OUTPUT);
+        gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+        gl::DebugMessageCallback(Some(debug_callback), std::ptr::null());
+    }
+    Ok(())
+}
+
+/// Stops routing debug messages
+pub fn clear_logger() {
+    if gl::DebugMessageCallback::is_loaded() {
+        unsafe {
+            gl::DebugMessageCallback(None, std::ptr::null());
+            gl::Disable(gl::DEBUG_OUTPUT);
+        }
+    }
+    *LOGGER.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Turns messages of a source, type and severity on or off at the implementation, before they reach the logger.
+/// `None` matches all of that kind
+pub fn filter_messages(
+    source: Option<DebugSource>,
+    ty: Option<DebugType>,
+    severity: Option<DebugSeverity>,
+    enabled: bool,
+) {
+    if gl::DebugMessageControl::is_loaded() {
+        unsafe {
+            gl::DebugMessageControl(
+                source.map_or(gl::DONT_CARE, |s| s as GLenum),
+                ty.map_or(gl::DONT_CARE, |t| t as GLenum),
+                severity.map_or(gl::DONT_CARE, DebugSeverity::gl_enum),
+                0,
+                std::ptr::null(),
+                enabled.into(),
+            )
+        }
+    }
+}
+
+/// Names an object in debug messages and debugging tools, and in resource dumps.
+/// Buffers, VAOs, textures, framebuffers and renderbuffers only exist once bound for the first time, labelling them before fails.
+/// Without KHR_debug the label only shows up in resource dumps
+pub fn label_object(kind: ResourceKind, id: u32, label: &str) -> Result<()> {
+    if !gl::ObjectLabel::is_loaded() {
+        resources::set_label(kind, id, label);
+        return Ok(());
+    }
+    let (identifier, exists) = unsafe {
+        match kind {
+            ResourceKind::Buffer => (gl::BUFFER, gl::IsBuffer(id)),
+            ResourceKind::VertexArray => (gl::VERTEX_ARRAY, gl::IsVertexArray(id)),
+            ResourceKind::Texture => (gl::TEXTURE, gl::IsTexture(id)),
+            ResourceKind::Shader => (gl::SHADER, gl::IsShader(id)),
+            ResourceKind::Program => (gl::PROGRAM, gl::IsProgram(id)),
+            ResourceKind::Framebuffer => (gl::FRAMEBUFFER, gl::IsFramebuffer(id)),
+            ResourceKind::Renderbuffer => (gl::RENDERBUFFER, gl::IsRenderbuffer(id)),
+        }
+    };
+    if exists == gl::FALSE {
+        return Err(Error::InvalidParameter(format!(
+            "can't label {:?} {} \"{}\" before it is first bound",
+            kind, id, label
+        )));
+    }
+    resources::set_label(kind, id, label);
+    unsafe {
+        gl::ObjectLabel(
+            identifier,
+            id,
+            label.len() as GLsizei,
+            label.as_ptr().cast(),
+        )
+    }
+    Ok(())
+}
+
+/// A named scope of GL calls, shown as a group in debugging tools. Popped when dropped
+#[must_use = "the group is popped as soon as it is dropped"]
+pub struct DebugGroup(bool);
+
+/// Opens a debug group named `name` that lasts until the returned guard is dropped
+pub fn debug_group(name: &str) -> DebugGroup {
+    let supported = gl::PushDebugGroup::is_loaded();
+    if supported {
+        unsafe {
+            gl::PushDebugGroup(
+                gl::DEBUG_SOURCE_APPLICATION,
+                0,
+                name.len() as GLsizei,
+                name.as_ptr().cast(),
+            )
+        }
+    }
+    DebugGroup(supported)
+}
+
+impl Drop for DebugGroup {
+    fn drop(&mut self) {
+        if self.0 {
+            unsafe { gl::PopDebugGroup() }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_errors_and_framebuffer_statuses() {
+        assert_eq!(error_name(gl::NO_ERROR), "GL_NO_ERROR");
+        assert_eq!(error_name(gl::INVALID_OPERATION), "GL_INVALID_OPERATION");
+        assert_eq!(error_name(gl::OUT_OF_MEMORY), "GL_OUT_OF_MEMORY");
+        assert_eq!(error_name(0xFFFF), "unknown GL error");
+
+        assert_eq!(
+            framebuffer_status_name(gl::FRAMEBUFFER_COMPLETE),
+            "GL_FRAMEBUFFER_COMPLETE"
+        );
+        assert_eq!(
+            framebuffer_status_name(gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT),
+            "GL_FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT"
+        );
+        assert_eq!(framebuffer_status_name(0), "unknown framebuffer status");
+    }
+
+    #[test]
+    fn sources_and_types_map_from_their_enums() {
+        for source in [
+            DebugSource::Api,
+            DebugSource::WindowSystem,
+            DebugSource::ShaderCompiler,
+            DebugSource::ThirdParty,
+            DebugSource::Application,
+            DebugSource::Other,
+        ] {
+            assert_eq!(DebugSource::from_gl(source as GLenum), source);
+        }
+        assert_eq!(DebugSource::from_gl(0), DebugSource::Other);
+
+        for ty in [
+            DebugType::Error,
+            DebugType::DeprecatedBehavior,
+            DebugType::UndefinedBehavior,
+            DebugType::Portability,
+            DebugType::Performance,
+            DebugType::Marker,
+            DebugType::PushGroup,
+            DebugType::PopGroup,
+            DebugType::Other,
+        ] {
+            assert_eq!(DebugType::from_gl(ty as GLenum), ty);
+        }
+        assert_eq!(DebugType::from_gl(0), DebugType::Other);
+    }
+
+    #[test]
+    fn severities_round_trip_and_order() {
+        let severities = [
+            DebugSeverity::Notification,
+            DebugSeverity::Low,
+            DebugSeverity::Medium,
+            DebugSeverity::High,
+        ];
+        for severity in severities {
+            assert_eq!(DebugSeverity::from_gl(severity.gl_enum()), severity);
+        }
+        assert!(severities.is_sorted());
+        assert_eq!(
+            DebugSeverity::from_gl(gl::DEBUG_SEVERITY_NOTIFICATION),
+            DebugSeverity::Notification
+        );
+        assert_eq!(DebugSeverity::from_gl(0), DebugSeverity::Notification);
+    }
+}
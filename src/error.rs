@@ -0,0 +1,127 @@
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use gl::types::GLenum;
+
+use crate::{
+    debug,
+    shaders::{ShaderLogLine, ShaderType},
+    wavefront_parser::WavefrontError,
+};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors from any fallible call in the crate
+#[derive(Debug)]
+pub enum Error {
+    /// A GL call failed. `code` is what `glGetError` reported, which may be `GL_NO_ERROR` when an object simply couldn't be created
+    Gl { call: &'static str, code: GLenum },
+    /// GLFW couldn't start, or couldn't create the window and its GL context, e.g. when a headless backend is missing
+    Window(String),
+    /// A framebuffer's attachments don't make a framebuffer that can be drawn into
+    FramebufferIncomplete { status: GLenum },
+    /// A shader stage failed to compile
+    ShaderCompile {
+        stage: ShaderType,
+        log: Vec<ShaderLogLine>,
+    },
+    /// A shader's `#include`s couldn't be resolved. `line` is the line of `path` with the bad include
+    Preprocess {
+        path: PathBuf,
+        line: u32,
+        message: String,
+    },
+    /// A shader program failed to link
+    Link { log: String },
+    /// A program's shaders declare inputs or uniforms that don't match what the caller provides
+    ShaderInterface(Vec<String>),
+    /// Reading or writing a file failed
+    Io { path: PathBuf, source: io::Error },
+    /// An OBJ or MTL file is malformed
+    Wavefront(WavefrontError),
+    /// A glTF file is malformed or its buffers couldn't be read
+    Gltf(gltf::Error),
+    /// An argument, or the state of the object it was called on, doesn't make sense for the call
+    InvalidParameter(String),
+}
+
+impl Error {
+    /// Makes an error for a failed GL call, taking the code from `glGetError`
+    pub fn gl(call: &'static str) -> Self {
+        Self::Gl {
+            call,
+            code: unsafe { gl::GetError() },
+        }
+    }
+
+    /// Adapter for `map_err` attaching the path an IO error happened on
+    pub fn io(path: impl AsRef<Path>) -> impl FnOnce(io::Error) -> Self {
+        let path = path.as_ref().to_path_buf();
+        move |source| Self::Io { path, source }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Gl { call, code } => {
+                write!(f, "{} failed: {}", call, debug::error_name(*code))
+            }
+            Error::Window(message) => write!(f, "Couldn't create window: {}", message),
+            Error::FramebufferIncomplete { status } => write!(
+                f,
+                "Framebuffer incomplete: {}",
+                debug::framebuffer_status_name(*status)
+            ),
+            Error::ShaderCompile { stage, log } => {
+                write!(f, "{:?} shader compile error:", stage)?;
+                for line in log {
+                    write!(f, "\n{}", line)?;
+                }
+                Ok(())
+            }
+            Error::Preprocess {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            Error::Link { log } => write!(f, "Linking error: {}", log.trim_end()),
+            Error::ShaderInterface(problems) => {
+                write!(f, "Shader interface mismatch:")?;
+                for problem in problems {
+                    write!(f, "\n{}", problem)?;
+                }
+                Ok(())
+            }
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Wavefront(e) => write!(f, "{}", e),
+            Error::Gltf(e) => write!(f, "glTF error: {}", e),
+            Error::InvalidParameter(message) => write!(f, "Invalid parameter: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Wavefront(e) => Some(e),
+            Error::Gltf(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<WavefrontError> for Error {
+    fn from(e: WavefrontError) -> Self {
+        Error::Wavefront(e)
+    }
+}
+
+impl From<gltf::Error> for Error {
+    fn from(e: gltf::Error) -> Self {
+        Error::Gltf(e)
+    }
+}
@@ -0,0 +1,513 @@
+use gl::types::{GLbitfield, GLenum, GLint, GLuint};
+use ultraviolet::IVec2;
+
+use crate::{
+    debug,
+    error::{Error, Result},
+    functions,
+    resources::{self, ResourceKind},
+    textures::{TexScaleOp, Texture, TextureFormat, TextureType},
+};
+
+/// Holds a renderbuffer object: storage that can be drawn into and blitted from, but not sampled
+pub struct Renderbuffer(GLuint);
+impl Renderbuffer {
+    /// Tries to generate a new renderbuffer object
+    pub fn new() -> Result<Self> {
+        let mut rbo = 0;
+        unsafe { gl::GenRenderbuffers(1, &mut rbo) }
+        if rbo != 0 {
+            Ok(Self::from_raw(rbo))
+        } else {
+            Err(Error::gl("glGenRenderbuffers"))
+        }
+    }
+
+    /// Binds this renderbuffer
+    pub fn bind(&self) {
+        unsafe { gl::BindRenderbuffer(gl::RENDERBUFFER, self.0) }
+    }
+
+    /// (Re)allocates the renderbuffer as `size` pixels of `format`, discarding its contents. Leaves it bound
+    pub fn storage(&self, format: TextureFormat, size: IVec2) {
+        self.bind();
+        unsafe {
+            gl::RenderbufferStorage(gl::RENDERBUFFER, format.internal_format(), size.x, size.y)
+        }
+    }
+
+    /// The GL name of the renderbuffer
+    pub fn id(&self) -> GLuint {
+        self.0
+    }
+
+    /// Takes ownership of an existing renderbuffer, which will be deleted when dropped
+    pub fn from_raw(id: GLuint) -> Self {
+        resources::created(ResourceKind::Renderbuffer, id);
+        Self(id)
+    }
+
+    /// Gives up ownership of the renderbuffer without deleting it
+    pub fn into_raw(self) -> GLuint {
+        resources::released(ResourceKind::Renderbuffer, self.0);
+        std::mem::ManuallyDrop::new(self).0
+    }
+
+    /// Names the renderbuffer in debug messages, debugging tools and resource dumps
+    pub fn set_label(&self, label: &str) -> Result<()> {
+        debug::label_object(ResourceKind::Renderbuffer, self.0, label)
+    }
+}
+
+/// Deletes the renderbuffer
+impl Drop for Renderbuffer {
+    fn drop(&mut self) {
+        resources::released(ResourceKind::Renderbuffer, self.0);
+        unsafe { gl::DeleteRenderbuffers(1, &self.0) }
+    }
+}
+
+/// What an attachment's image lives in
+pub enum AttachmentStorage {
+    /// Can be sampled after rendering, e.g. for post-processing or shadow lookups
+    Texture(Texture),
+    /// Can only be drawn into, read back and blitted
+    Renderbuffer(Renderbuffer),
+}
+
+/// An image attached to a framebuffer
+pub struct Attachment {
+    pub format: TextureFormat,
+    pub storage: AttachmentStorage,
+}
+
+impl Attachment {
+    fn new(format: TextureFormat, size: IVec2, texture: bool) -> Result<Self> {
+        let storage = if texture {
+            AttachmentStorage::Texture(Texture::empty(format, size)?)
+        } else {
+            let rbo = Renderbuffer::new()?;
+            rbo.storage(format, size);
+            AttachmentStorage::Renderbuffer(rbo)
+        };
+        Ok(Self { format, storage })
+    }
+
+    /// The texture backing this attachment, if it isn't a renderbuffer
+    pub fn texture(&self) -> Option<&Texture> {
+        match &self.storage {
+            AttachmentStorage::Texture(tex) => Some(tex),
+            AttachmentStorage::Renderbuffer(_) => None,
+        }
+    }
+
+    /// Attaches the image to the bound framebuffer at `point`
+    fn attach(&self, point: GLenum) {
+        unsafe {
+            match &self.storage {
+                AttachmentStorage::Texture(tex) => gl::FramebufferTexture2D(
+                    gl::FRAMEBUFFER,
+                    point,
+                    TextureType::Tex2d as _,
+                    tex.id(),
+                    0,
+                ),
+                AttachmentStorage::Renderbuffer(rbo) => {
+                    gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, point, gl::RENDERBUFFER, rbo.0)
+                }
+            }
+        }
+    }
+
+    /// Reallocates the image at a new size. The attached object stays the same
+    fn resize(&self, size: IVec2) {
+        match &self.storage {
+            AttachmentStorage::Texture(tex) => {
+                tex.bind(TextureType::Tex2d);
+                Texture::allocate(self.format, size);
+            }
+            AttachmentStorage::Renderbuffer(rbo) => rbo.storage(self.format, size),
+        }
+    }
+
+    fn into_raw(self) {
+        match self.storage {
+            AttachmentStorage::Texture(tex) => {
+                tex.into_raw();
+            }
+            AttachmentStorage::Renderbuffer(rbo) => {
+                rbo.into_raw();
+            }
+        }
+    }
+}
+
+/// Which buffers a blit copies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlitMask {
+    pub color: bool,
+    pub depth: bool,
+    pub stencil: bool,
+}
+
+impl BlitMask {
+    pub const COLOR: Self = Self {
+        color: true,
+        depth: false,
+        stencil: false,
+    };
+    pub const DEPTH: Self = Self {
+        color: false,
+        depth: true,
+        stencil: false,
+    };
+    pub const ALL: Self = Self {
+        color: true,
+        depth: true,
+        stencil: true,
+    };
+
+    fn bits(self) -> GLbitfield {
+        let mut bits = 0;
+        if self.color {
+            bits |= gl::COLOR_BUFFER_BIT;
+        }
+        if self.depth {
+            bits |= gl::DEPTH_BUFFER_BIT;
+        }
+        if self.stencil {
+            bits |= gl::STENCIL_BUFFER_BIT;
+        }
+        bits
+    }
+}
+
+/// Holds a framebuffer object and the images attached to it.
+/// Colour attachments are drawn to in order, so fragment output `n` lands in `color[n]`
+pub struct Framebuffer {
+    id: GLuint,
+    /// Size of every attachment, in pixels
+    size: IVec2,
+    color: Vec<Attachment>,
+    /// Depth or depth/stencil attachment
+    depth: Option<Attachment>,
+}
+
+impl Framebuffer {
+    /// Tries to generate a new framebuffer with nothing attached. Attachments will be `size` pixels
+    pub fn new(size: IVec2) -> Result<Self> {
+        check_size(size)?;
+        let mut fbo = 0;
+        unsafe { gl::GenFramebuffers(1, &mut fbo) }
+        if fbo != 0 {
+            Ok(Self::from_raw(fbo, size))
+        } else {
+            Err(Error::gl("glGenFramebuffers"))
+        }
+    }
+
+    /// Adds a colour attachment backed by a texture, which can be sampled once rendered to
+    pub fn attach_color(&mut self, format: TextureFormat) -> Result<()> {
+        self.push_color(format, true)
+    }
+
+    /// Adds a colour attachment backed by a renderbuffer, for images that are only read back or blitted
+    pub fn attach_color_renderbuffer(&mut self, format: TextureFormat) -> Result<()> {
+        self.push_color(format, false)
+    }
+
+    /// Adds the depth attachment, backed by a texture so it can be sampled, e.g. as a shadow map
+    pub fn attach_depth(&mut self, format: TextureFormat) -> Result<()> {
+        self.set_depth(format, true)
+    }
+
+    /// Adds the depth attachment, backed by a renderbuffer, for depth testing only
+    pub fn attach_depth_renderbuffer(&mut self, format: TextureFormat) -> Result<()> {
+        self.set_depth(format, false)
+    }
+
+    fn push_color(&mut self, format: TextureFormat, texture: bool) -> Result<()> {
+        if format.is_depth() {
+            return Err(Error::InvalidParameter(format!(
+                "{:?} can't be used as a colour attachment",
+                format
+            )));
+        }
+        let max = max_color_attachments();
+        if self.color.len() >= max {
+            return Err(Error::InvalidParameter(format!(
+                "this context supports at most {} colour attachments",
+                max
+            )));
+        }
+        let attachment = Attachment::new(format, self.size, texture)?;
+        let _binding = self.bind_scoped();
+        attachment.attach(gl::COLOR_ATTACHMENT0 + self.color.len() as GLenum);
+        self.color.push(attachment);
+        self.set_draw_buffers();
+        Ok(())
+    }
+
+    fn set_depth(&mut self, format: TextureFormat, texture: bool) -> Result<()> {
+        if !format.is_depth() {
+            return Err(Error::InvalidParameter(format!(
+                "{:?} can't be used as a depth attachment",
+                format
+            )));
+        }
+        if self.depth.is_some() {
+            return Err(Error::InvalidParameter(
+                "framebuffer already has a depth attachment".to_owned(),
+            ));
+        }
+        let attachment = Attachment::new(format, self.size, texture)?;
+        let _binding = self.bind_scoped();
+        attachment.attach(if format.has_stencil() {
+            gl::DEPTH_STENCIL_ATTACHMENT
+        } else {
+            gl::DEPTH_ATTACHMENT
+        });
+        self.depth = Some(attachment);
+        if self.color.is_empty() {
+            // depth-only framebuffers must not draw to or read from colour buffers they don't have
+            self.set_draw_buffers();
+        }
+        Ok(())
+    }
+
+    /// Routes fragment outputs to every colour attachment, or nowhere for depth-only framebuffers
+    fn set_draw_buffers(&self) {
+        let buffers: Vec<GLenum> = (0..self.color.len() as GLenum)
+            .map(|i| gl::COLOR_ATTACHMENT0 + i)
+            .collect();
+        unsafe {
+            if buffers.is_empty() {
+                gl::DrawBuffer(gl::NONE);
+                gl::ReadBuffer(gl::NONE);
+            } else {
+                gl::DrawBuffers(buffers.len() as _, buffers.as_ptr());
+                if buffers.len() == 1 {
+                    // a framebuffer that was depth-only read from nothing, so point reads at its first colour buffer
+                    gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+                }
+            }
+        }
+    }
+
+    /// The texture behind colour attachment `index`, if it has one
+    pub fn color_texture(&self, index: usize) -> Option<&Texture> {
+        self.color.get(index).and_then(Attachment::texture)
+    }
+
+    /// The texture behind the depth attachment, if it has one
+    pub fn depth_texture(&self) -> Option<&Texture> {
+        self.depth.as_ref().and_then(Attachment::texture)
+    }
+
+    /// Checks the attachments make a framebuffer that can be drawn into. Leaves the bindings as they were
+    pub fn check(&self) -> Result<()> {
+        let _binding = self.bind_scoped();
+        let status = unsafe { gl::CheckFramebufferStatus(gl::FRAMEBUFFER) };
+        if status == gl::FRAMEBUFFER_COMPLETE {
+            Ok(())
+        } else {
+            Err(Error::FramebufferIncomplete { status })
+        }
+    }
+
+    /// Binds the framebuffer for drawing and reading, and sets the viewport to cover it
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+            gl::Viewport(0, 0, self.size.x, self.size.y);
+        }
+    }
+
+    /// Like `Framebuffer::bind`, but the previous framebuffers and viewport are restored when the guard is dropped
+    pub fn bind_scoped(&self) -> FramebufferBinding {
+        let binding = FramebufferBinding::save();
+        self.bind();
+        binding
+    }
+
+    /// Binds the framebuffer for reading only, e.g. for `screenshot::read_color`
+    pub fn bind_read(&self) {
+        unsafe { gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.id) }
+    }
+
+    /// Picks the colour attachment that reads and blits copy from. Binds the framebuffer for reading
+    pub fn set_read_attachment(&self, index: usize) -> Result<()> {
+        if index >= self.color.len() {
+            return Err(Error::InvalidParameter(format!(
+                "framebuffer has no colour attachment {}",
+                index
+            )));
+        }
+        self.bind_read();
+        unsafe { gl::ReadBuffer(gl::COLOR_ATTACHMENT0 + index as GLenum) }
+        Ok(())
+    }
+
+    /// Binds the default framebuffer, i.e. the window
+    pub fn clear_binding() {
+        unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, 0) }
+    }
+
+    /// Copies this framebuffer's contents into `target`, stretching them to fit.
+    /// Depth and stencil must be blitted with `TexScaleOp::Nearest`, between matching formats
+    pub fn blit_to(&self, target: &Framebuffer, mask: BlitMask, filter: TexScaleOp) -> Result<()> {
+        self.blit(target.id, target.size, mask, filter)
+    }
+
+    /// Copies this framebuffer's contents into the default framebuffer of the given size, e.g. `Application::render_size`
+    pub fn blit_to_default(&self, size: IVec2, mask: BlitMask, filter: TexScaleOp) -> Result<()> {
+        self.blit(0, size, mask, filter)
+    }
+
+    fn blit(&self, target: GLuint, size: IVec2, mask: BlitMask, filter: TexScaleOp) -> Result<()> {
+        match filter {
+            TexScaleOp::Nearest => {}
+            TexScaleOp::Linear if !(mask.depth || mask.stencil) => {}
+            _ => {
+                return Err(Error::InvalidParameter(format!(
+                    "{:?} is not a valid filter for blitting {:?}",
+                    filter, mask
+                )));
+            }
+        }
+        let _binding = FramebufferBinding::save();
+        functions::clear_errors();
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.id);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target);
+            gl::BlitFramebuffer(
+                0,
+                0,
+                self.size.x,
+                self.size.y,
+                0,
+                0,
+                size.x,
+                size.y,
+                mask.bits(),
+                filter as _,
+            );
+        }
+        functions::get_error("glBlitFramebuffer")
+    }
+
+    /// Reallocates every attachment at `size`, discarding their contents. Does nothing if the size hasn't changed,
+    /// so it can be called every frame with `Application::render_size` to follow the window.
+    /// The attached textures keep their names, so existing references to them stay valid
+    pub fn resize(&mut self, size: IVec2) -> Result<()> {
+        check_size(size)?;
+        if size == self.size {
+            return Ok(());
+        }
+        for attachment in self.color.iter().chain(&self.depth) {
+            attachment.resize(size);
+        }
+        self.size = size;
+        self.check()
+    }
+
+    /// The GL name of the framebuffer
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    /// Size of every attachment, in pixels
+    pub fn size(&self) -> IVec2 {
+        self.size
+    }
+
+    /// Takes ownership of an existing framebuffer with nothing attached yet, which will be deleted when dropped
+    pub fn from_raw(id: GLuint, size: IVec2) -> Self {
+        resources::created(ResourceKind::Framebuffer, id);
+        Self {
+            id,
+            size,
+            color: Vec::new(),
+            depth: None,
+        }
+    }
+
+    /// Gives up ownership of the framebuffer and its attachments without deleting them
+    pub fn into_raw(mut self) -> GLuint {
+        for attachment in self.color.drain(..).chain(self.depth.take()) {
+            attachment.into_raw();
+        }
+        resources::released(ResourceKind::Framebuffer, self.id);
+        std::mem::ManuallyDrop::new(self).id
+    }
+
+    /// Names the framebuffer in debug messages, debugging tools and resource dumps
+    pub fn set_label(&self, label: &str) -> Result<()> {
+        debug::label_object(ResourceKind::Framebuffer, self.id, label)
+    }
+}
+
+/// Deletes the framebuffer. Its attachments delete themselves
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        resources::released(ResourceKind::Framebuffer, self.id);
+        unsafe { gl::DeleteFramebuffers(1, &self.id) }
+    }
+}
+
+/// Framebuffer bindings and viewport to go back to. Restored when dropped
+#[must_use = "the previous framebuffer is restored as soon as this is dropped"]
+pub struct FramebufferBinding {
+    draw: GLint,
+    read: GLint,
+    viewport: [GLint; 4],
+}
+
+impl FramebufferBinding {
+    /// Remembers the current framebuffer bindings and viewport
+    pub fn save() -> Self {
+        let mut binding = Self {
+            draw: 0,
+            read: 0,
+            viewport: [0; 4],
+        };
+        unsafe {
+            gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut binding.draw);
+            gl::GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut binding.read);
+            gl::GetIntegerv(gl::VIEWPORT, binding.viewport.as_mut_ptr());
+        }
+        binding
+    }
+}
+
+impl Drop for FramebufferBinding {
+    fn drop(&mut self) {
+        let [x, y, width, height] = self.viewport;
+        unsafe {
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.draw as GLuint);
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.read as GLuint);
+            gl::Viewport(x, y, width, height);
+        }
+    }
+}
+
+/// Most colour attachments a framebuffer can draw into at once
+fn max_color_attachments() -> usize {
+    let mut attachments = 0;
+    let mut draw_buffers = 0;
+    unsafe {
+        gl::GetIntegerv(gl::MAX_COLOR_ATTACHMENTS, &mut attachments);
+        gl::GetIntegerv(gl::MAX_DRAW_BUFFERS, &mut draw_buffers);
+    }
+    attachments.min(draw_buffers).max(0) as usize
+}
+
+fn check_size(size: IVec2) -> Result<()> {
+    if size.x <= 0 || size.y <= 0 {
+        return Err(Error::InvalidParameter(format!(
+            "framebuffer size must be positive, got {}x{}",
+            size.x, size.y
+        )));
+    }
+    Ok(())
+}
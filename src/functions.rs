@@ -1,6 +1,9 @@
 use ultraviolet::{Vec3, Vec4};
 
-use crate::wavefront_parser::Vertex;
+use crate::{
+    error::{Error, Result},
+    wavefront_parser::Vertex,
+};
 
 pub fn set_clear_color(col: Vec4) {
     unsafe {
@@ -26,24 +29,20 @@ pub fn set_polygon_mode(mode: PolygonMode) {
     }
 }
 
-/// Prints errors until there is no more. An optional context can be attached to the call to distinguish multiple calls
-pub fn get_error(context: Option<&'static str>) {
-    let mut error_code;
-    loop {
-        unsafe { error_code = gl::GetError() };
-        if error_code == gl::NO_ERROR {
-            return;
-        }
-        if context.is_some() {
-            println!(
-                "GL error: {}: {}",
-                context.expect("value exists"),
-                error_code
-            )
-        } else {
-            println!("GL error: no context: {}", error_code);
-        }
+/// Checks `glGetError` after `call`, returning the first error reported. Any further queued errors are cleared so
+/// they aren't blamed on a later call
+pub fn get_error(call: &'static str) -> Result<()> {
+    let code = unsafe { gl::GetError() };
+    if code == gl::NO_ERROR {
+        return Ok(());
     }
+    clear_errors();
+    Err(Error::Gl { call, code })
+}
+
+/// Discards every queued GL error, so a following `get_error` only reports errors from the calls after it
+pub fn clear_errors() {
+    while unsafe { gl::GetError() } != gl::NO_ERROR {}
 }
 
 /// Generate xz-plane data for tessellation patches
@@ -1,85 +1,313 @@
 use std::f32;
 
-use crate::wavefront_parser::Vertex;
+use bytemuck::{Pod, Zeroable};
+use gl::types::GLenum;
+pub use magiebleue_derive::VertexLayout;
+use ultraviolet::{IVec2, IVec3, IVec4, Mat2, Mat3, Mat4, UVec2, UVec3, UVec4, Vec2, Vec3, Vec4};
+
+use crate::{
+    debug,
+    error::{Error, Result},
+    resources::{self, ResourceKind},
+    wavefront_parser::{TangentVertex, Vertex},
+};
 
 /// Wrapper for a [VAO](https://www.khronos.org/opengl/wiki/Vertex_Specification#Vertex_Array_Object)
-pub struct VertexArray(pub u32, pub Option<Buffer>, pub Option<Vec<Vertex>>);
+pub struct VertexArray {
+    id: u32,
+    vbo: Option<Buffer>,
+    /// CPU copy of the vertices, kept when they were attached in the standard format
+    vertices: Option<Vec<Vertex>>,
+    ebo: Option<(Buffer, Indices)>,
+    /// Number of vertices in the vertex buffer
+    vertex_count: usize,
+    /// Per-instance attribute buffers with the number of instances each holds and the locations they feed
+    instance_buffers: Vec<(Buffer, usize, Vec<u32>)>,
+    /// Attribute locations enabled on the VAO, for the vertex buffer and the instance buffers
+    vertex_locations: Vec<u32>,
+}
 impl VertexArray {
     /// Creates a new VAO
-    pub fn new() -> Option<Self> {
+    pub fn new() -> Result<Self> {
         let mut vao = 0;
         unsafe {
             gl::GenVertexArrays(1, &mut vao);
         }
         if vao != 0 {
-            Some(Self(vao, None, None))
+            Ok(Self::from_raw(vao))
         } else {
-            None
+            Err(Error::gl("glGenVertexArrays"))
         }
     }
 
     /// Binds this VAO as current VAO
     pub fn bind(&self) {
         unsafe {
-            gl::BindVertexArray(self.0);
+            gl::BindVertexArray(self.id);
         }
     }
 
+    /// The GL name of the VAO
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// The attached vertex buffer
+    pub fn vbo(&self) -> Option<&Buffer> {
+        self.vbo.as_ref()
+    }
+
+    /// The attached vertices, if they were attached in the standard format by `attach_vertex` or `update_vertex`
+    pub fn vertices(&self) -> Option<&[Vertex]> {
+        self.vertices.as_deref()
+    }
+
+    /// The attached element buffer and the indices it holds
+    pub fn ebo(&self) -> Option<(&Buffer, &Indices)> {
+        self.ebo.as_ref().map(|(ebo, indices)| (ebo, indices))
+    }
+
+    /// Number of vertices in the vertex buffer
+    pub fn vertex_count(&self) -> usize {
+        self.vertex_count
+    }
+
     /// Attaches vertex data with a standard format (pos-tex-normal)
-    pub fn attach_vertex(&mut self, vertices: Vec<Vertex>) {
+    pub fn attach_vertex(&mut self, vertices: Vec<Vertex>) -> Result<()> {
+        self.attach(&vertices)?;
+        self.vertices = Some(vertices);
+        Ok(())
+    }
+
+    /// Attaches vertex data of any layout, setting up the attributes `T` describes
+    pub fn attach<T: VertexLayout>(&mut self, vertices: &[T]) -> Result<()> {
+        self.attach_with_layout(vertices, &T::attributes())
+    }
+
+    /// Attaches vertex data described by `layout`. Each attribute reads from `size_of::<T>()` strided elements of `vertices`.
+    /// Fails if a location is already fed by an instance buffer
+    pub fn attach_with_layout<T: Pod>(
+        &mut self,
+        vertices: &[T],
+        layout: &[VertexAttribute],
+    ) -> Result<()> {
+        let instance_locations: Vec<u32> = self
+            .instance_buffers
+            .iter()
+            .flat_map(|(_, _, locations)| locations.iter().copied())
+            .collect();
+        check_layout("VertexArray::attach", layout, &instance_locations)?;
         self.bind();
-        self.1 = Buffer::new();
-        self.1.expect("VBO should create").bind(BufferType::Array);
+        let vbo = Buffer::new()?;
+        vbo.bind(BufferType::Array);
+        self.vbo = Some(vbo);
+        buffer_data(
+            BufferType::Array,
+            bytemuck::cast_slice(vertices),
+            gl::STATIC_DRAW,
+        );
+        self.vertices = None;
+        self.vertex_count = vertices.len();
+
+        for attribute in layout {
+            attribute.enable(size_of::<T>());
+        }
+        // locations only the previous layout used would keep reading from the old buffer
+        let mut locations: Vec<u32> = layout.iter().map(|a| a.location).collect();
+        locations.extend(instance_locations);
+        for location in &self.vertex_locations {
+            if !locations.contains(location) {
+                unsafe { gl::DisableVertexAttribArray(*location) };
+            }
+        }
+        self.vertex_locations = locations;
+        Ok(())
+    }
+
+    /// Attaches an element buffer. Draws will then go through the indices instead of the vertices in order
+    pub fn attach_indices(&mut self, indices: Indices) -> Result<()> {
+        self.bind();
+        let ebo = Buffer::new()?;
+        ebo.bind(BufferType::ElementArray);
+        buffer_data(
+            BufferType::ElementArray,
+            indices.as_bytes(),
+            gl::STATIC_DRAW,
+        );
+        self.ebo = Some((ebo, indices));
+        Ok(())
+    }
 
-        self.2 = Some(vertices);
+    /// Attaches the vertices and indices of a deduplicated mesh
+    pub fn attach_indexed(&mut self, vertices: Vec<Vertex>, indices: Indices) -> Result<()> {
+        self.attach_vertex(vertices)?;
+        self.attach_indices(indices)
+    }
+
+    /// Attaches a buffer of per-instance data, such as model matrices or colours. Attributes `T` describes as per-vertex advance once per instance instead.
+    /// Can be called several times to attach separate buffers at different locations
+    pub fn attach_instances<T: VertexLayout>(&mut self, instances: &[T]) -> Result<()> {
+        let layout: Vec<VertexAttribute> = T::attributes()
+            .into_iter()
+            .map(|a| a.per_instance(a.divisor.max(1)))
+            .collect();
+        self.attach_instances_with_layout(instances, &layout)
+    }
 
+    /// Attaches a buffer of per-instance data described by `layout`, used as given.
+    /// Fails if a location is already fed by the vertex buffer or another instance buffer
+    pub fn attach_instances_with_layout<T: Pod>(
+        &mut self,
+        instances: &[T],
+        layout: &[VertexAttribute],
+    ) -> Result<()> {
+        check_layout(
+            "VertexArray::attach_instances",
+            layout,
+            &self.vertex_locations,
+        )?;
+        self.bind();
+        let buffer = Buffer::new()?;
+        buffer.bind(BufferType::Array);
         buffer_data(
             BufferType::Array,
-            bytemuck::cast_slice(self.2.as_ref().expect("vertex vector should exist")),
+            bytemuck::cast_slice(instances),
             gl::STATIC_DRAW,
         );
+        for attribute in layout {
+            attribute.enable(size_of::<T>());
+        }
+        let locations: Vec<u32> = layout.iter().map(|a| a.location).collect();
+        self.vertex_locations.extend(&locations);
+        self.instance_buffers
+            .push((buffer, instances.len(), locations));
+        Ok(())
+    }
+
+    /// The attached vertex buffer, or an error naming `call` if there is none
+    fn vertex_buffer(&self, call: &str) -> Result<&Buffer> {
+        self.vbo
+            .as_ref()
+            .ok_or_else(|| Error::InvalidParameter(format!("{} called on VAO without VBO", call)))
+    }
+
+    /// Replaces the attached vertices, keeping the attribute layout.
+    /// The old storage is orphaned if the size is unchanged, so that frames still drawing from it don't stall the upload, and
+    /// reallocated for dynamic use otherwise
+    pub fn update_vertices<T: Pod>(&mut self, vertices: &[T]) -> Result<()> {
+        upload(
+            self.vertex_buffer("VertexArray::update_vertices")?,
+            bytemuck::cast_slice(vertices),
+        );
+        self.vertices = None;
+        self.vertex_count = vertices.len();
+        Ok(())
+    }
+
+    /// Replaces the attached standard format vertices, keeping the CPU copy in sync
+    pub fn update_vertex(&mut self, vertices: Vec<Vertex>) -> Result<()> {
+        self.update_vertices(&vertices)?;
+        self.vertices = Some(vertices);
+        Ok(())
+    }
 
+    /// Overwrites the vertices starting at vertex `first` in place, without reallocating.
+    /// `T` must be the type the vertices were attached as. The CPU copy is updated too if there is one
+    pub fn update_vertex_range<T: Pod>(&mut self, first: usize, vertices: &[T]) -> Result<()> {
+        let vbo = self.vertex_buffer("VertexArray::update_vertex_range")?;
+        if first + vertices.len() > self.vertex_count {
+            return Err(Error::InvalidParameter(format!(
+                "VertexArray::update_vertex_range writing vertices {}..{} past the {} attached",
+                first,
+                first + vertices.len(),
+                self.vertex_count
+            )));
+        }
+        let offset = first * size_of::<T>();
+        let data: &[u8] = bytemuck::cast_slice(vertices);
+        vbo.sub_data(BufferType::Array, offset, data);
+        if let Some(copy) = &mut self.vertices {
+            let bytes: &mut [u8] = bytemuck::cast_slice_mut(copy);
+            match bytes.get_mut(offset..offset + data.len()) {
+                Some(range) => range.copy_from_slice(data),
+                None => self.vertices = None,
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the contents of the instance buffer attached `index`th, such as to move particles.
+    /// Storage is orphaned or reallocated like `update_vertices`
+    pub fn update_instances<T: Pod>(&mut self, index: usize, instances: &[T]) -> Result<()> {
+        let attached = self.instance_buffers.len();
+        let (buffer, count, _) = self.instance_buffers.get_mut(index).ok_or_else(|| {
+            Error::InvalidParameter(format!(
+                "VertexArray::update_instances called with instance buffer {} of {}",
+                index, attached
+            ))
+        })?;
+        upload(buffer, bytemuck::cast_slice(instances));
+        *count = instances.len();
+        Ok(())
+    }
+
+    /// Number of instances every attached instance buffer has data for
+    pub fn instance_count(&self) -> usize {
+        self.instance_buffers
+            .iter()
+            .map(|(_, count, _)| *count)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Draws `instances` copies of the attached buffer in one call, through the element buffer if one is attached.
+    /// Shaders tell the copies apart through their per-instance attributes or `gl_InstanceID`. Does not attach a shader.
+    pub fn draw_instanced(&self, prim: Primitive, instances: usize) -> Result<()> {
+        self.vertex_buffer("VertexArray::draw_instanced")?;
+        if instances > self.instance_count() && !self.instance_buffers.is_empty() {
+            return Err(Error::InvalidParameter(format!(
+                "VertexArray::draw_instanced drawing {} instances but the instance buffers only hold {}",
+                instances,
+                self.instance_count()
+            )));
+        }
+        self.bind();
         unsafe {
-            gl::VertexAttribPointer(
-                0,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                size_of::<Vertex>().try_into().unwrap(),
-                std::ptr::null(),
-            );
-            gl::EnableVertexAttribArray(0);
-            gl::VertexAttribPointer(
-                1,
-                2,
-                gl::FLOAT,
-                gl::FALSE,
-                size_of::<Vertex>().try_into().unwrap(),
-                (3 * size_of::<f32>()) as *const _,
-            );
-            gl::EnableVertexAttribArray(1);
-            gl::VertexAttribPointer(
-                2,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                size_of::<Vertex>().try_into().unwrap(),
-                (5 * size_of::<f32>()) as *const _,
-            );
-            gl::EnableVertexAttribArray(2);
+            match &self.ebo {
+                Some((_, indices)) => gl::DrawElementsInstanced(
+                    prim as _,
+                    indices.len() as i32,
+                    indices.gl_type(),
+                    std::ptr::null(),
+                    instances as i32,
+                ),
+                None => gl::DrawArraysInstanced(
+                    prim as _,
+                    0,
+                    self.vertex_count as i32,
+                    instances as i32,
+                ),
+            }
         }
+        Ok(())
     }
 
-    /// Draws from the attached buffer. Binds the VAO and draws the complete buffer once. Does not attach a shader.
-    pub fn draw(&self, prim: Primitive) {
-        if self.1.is_none() {
-            eprintln!("VertexArray::draw called on VAO without VBO")
-        } else {
-            self.bind();
-            unsafe {
-                gl::DrawArrays(prim as _, 0, self.2.as_ref().unwrap().len() as i32);
+    /// Draws from the attached buffer. Binds the VAO and draws the complete buffer once, through the element buffer if one is attached. Does not attach a shader.
+    pub fn draw(&self, prim: Primitive) -> Result<()> {
+        self.vertex_buffer("VertexArray::draw")?;
+        self.bind();
+        unsafe {
+            match &self.ebo {
+                Some((_, indices)) => gl::DrawElements(
+                    prim as _,
+                    indices.len() as i32,
+                    indices.gl_type(),
+                    std::ptr::null(),
+                ),
+                None => gl::DrawArrays(prim as _, 0, self.vertex_count as i32),
             }
         }
+        Ok(())
     }
 
     /// Clear current VAO binding
@@ -88,6 +316,342 @@ impl VertexArray {
             gl::BindVertexArray(0);
         }
     }
+
+    /// Takes ownership of an existing VAO with no buffers attached yet, which will be deleted when dropped
+    pub fn from_raw(id: u32) -> Self {
+        resources::created(ResourceKind::VertexArray, id);
+        Self {
+            id,
+            vbo: None,
+            vertices: None,
+            ebo: None,
+            vertex_count: 0,
+            instance_buffers: Vec::new(),
+            vertex_locations: Vec::new(),
+        }
+    }
+
+    /// Gives up ownership of the VAO and the buffers attached to it without deleting them
+    pub fn into_raw(mut self) -> u32 {
+        self.vertices = None;
+        self.vertex_locations = Vec::new();
+        for buffer in self
+            .vbo
+            .take()
+            .into_iter()
+            .chain(self.ebo.take().map(|(ebo, _)| ebo))
+        {
+            buffer.into_raw();
+        }
+        for (buffer, _, _) in self.instance_buffers.drain(..) {
+            buffer.into_raw();
+        }
+        resources::released(ResourceKind::VertexArray, self.id);
+        std::mem::ManuallyDrop::new(self).id
+    }
+
+    /// Names the VAO in debug messages, debugging tools and resource dumps
+    pub fn set_label(&self, label: &str) -> Result<()> {
+        debug::label_object(ResourceKind::VertexArray, self.id, label)
+    }
+}
+
+/// Deletes the VAO, and the buffers attached to it with it
+impl Drop for VertexArray {
+    fn drop(&mut self) {
+        resources::released(ResourceKind::VertexArray, self.id);
+        unsafe { gl::DeleteVertexArrays(1, &self.id) }
+    }
+}
+
+/// Describes the vertex attributes of a vertex type, so `VertexArray::attach` can upload it.
+/// Usually derived, see `magiebleue_derive::VertexLayout`. The derive rejects structs without `#[repr(C)]`:
+/// ```compile_fail
+/// # use magiebleue::gl_objects::VertexLayout;
+/// #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+/// struct Point {
+///     #[location(0)]
+///     position: [f32; 3],
+/// }
+/// ```
+/// and attribute options on a field without a `#[location(n)]`:
+/// ```compile_fail
+/// # use magiebleue::gl_objects::VertexLayout;
+/// #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+/// #[repr(C)]
+/// struct Point {
+///     #[normalized]
+///     color: [u8; 4],
+/// }
+/// ```
+pub trait VertexLayout: Pod {
+    /// One entry per attribute, with offsets into the vertex type
+    fn attributes() -> Vec<VertexAttribute>;
+}
+
+impl VertexLayout for Vertex {
+    /// `aPos` at 0, `aTexCoords` at 1 and `aNormal` at 2
+    fn attributes() -> Vec<VertexAttribute> {
+        vec![
+            VertexAttribute::new("aPos", 0, 3, ComponentType::F32, 0),
+            VertexAttribute::new("aTexCoords", 1, 2, ComponentType::F32, 3 * size_of::<f32>()),
+            VertexAttribute::new("aNormal", 2, 3, ComponentType::F32, 5 * size_of::<f32>()),
+        ]
+    }
+}
+
+impl VertexLayout for TangentVertex {
+    /// The `Vertex` attributes, and `aTangent` at 3
+    fn attributes() -> Vec<VertexAttribute> {
+        let mut attributes = Vertex::attributes();
+        attributes.push(VertexAttribute::new(
+            "aTangent",
+            3,
+            4,
+            ComponentType::F32,
+            8 * size_of::<f32>(),
+        ));
+        attributes
+    }
+}
+
+/// One input of a vertex shader and where it is found within a vertex
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VertexAttribute {
+    /// Name of the input in the shader. Only used for diagnostics, the location is what binds it
+    pub name: &'static str,
+    /// `layout(location = n)` of the input
+    pub location: u32,
+    /// Number of components, 1-4
+    pub components: i32,
+    /// Type of each component in the buffer
+    pub ty: ComponentType,
+    /// How the shader sees the components
+    pub format: AttributeFormat,
+    /// Byte offset of the attribute within a vertex
+    pub offset: usize,
+    /// 0 to advance every vertex, n to advance every n instances
+    pub divisor: u32,
+}
+impl VertexAttribute {
+    /// A per-vertex attribute read as floats
+    pub const fn new(
+        name: &'static str,
+        location: u32,
+        components: i32,
+        ty: ComponentType,
+        offset: usize,
+    ) -> Self {
+        Self {
+            name,
+            location,
+            components,
+            ty,
+            format: AttributeFormat::Float,
+            offset,
+            divisor: 0,
+        }
+    }
+
+    /// Maps integer components to 0-1 (unsigned) or -1-1 (signed)
+    pub const fn normalized(mut self) -> Self {
+        self.format = AttributeFormat::Normalized;
+        self
+    }
+
+    /// Passes integer components to `int`/`uint` shader inputs unconverted
+    pub const fn integer(mut self) -> Self {
+        self.format = AttributeFormat::Integer;
+        self
+    }
+
+    /// Advances the attribute once every `divisor` instances instead of every vertex
+    pub const fn per_instance(mut self, divisor: u32) -> Self {
+        self.divisor = divisor;
+        self
+    }
+
+    /// Splits an attribute taking several locations, such as a matrix, into one attribute per location
+    pub fn columns(self, locations: u32, column_size: usize) -> impl Iterator<Item = Self> {
+        (0..locations).map(move |c| Self {
+            location: self.location + c,
+            offset: self.offset + c as usize * column_size,
+            ..self
+        })
+    }
+
+    /// Points the attribute at the bound array buffer and enables it on the bound VAO.
+    /// `VertexArray::attach` checks the attribute first, an integer format on a float type makes GL report `GL_INVALID_ENUM`
+    pub fn enable(&self, stride: usize) {
+        let stride = stride.try_into().unwrap();
+        let offset = self.offset as *const _;
+        unsafe {
+            match self.format {
+                AttributeFormat::Integer => gl::VertexAttribIPointer(
+                    self.location,
+                    self.components,
+                    self.ty as _,
+                    stride,
+                    offset,
+                ),
+                AttributeFormat::Float | AttributeFormat::Normalized => gl::VertexAttribPointer(
+                    self.location,
+                    self.components,
+                    self.ty as _,
+                    (self.format == AttributeFormat::Normalized) as _,
+                    stride,
+                    offset,
+                ),
+            }
+            gl::EnableVertexAttribArray(self.location);
+            gl::VertexAttribDivisor(self.location, self.divisor);
+        }
+    }
+}
+
+/// Fails if an attribute of `layout` is at one of the `taken` locations, shares its location with another of `layout`,
+/// or asks for float components to be read as integers
+fn check_layout(call: &str, layout: &[VertexAttribute], taken: &[u32]) -> Result<()> {
+    for (i, attribute) in layout.iter().enumerate() {
+        if attribute.format == AttributeFormat::Integer && attribute.ty.is_float() {
+            return Err(Error::InvalidParameter(format!(
+                "{} attribute {} is a float type and can't be read as integers",
+                call, attribute.name
+            )));
+        }
+        let in_layout = layout[..i].iter().any(|a| a.location == attribute.location);
+        if in_layout || taken.contains(&attribute.location) {
+            return Err(Error::InvalidParameter(format!(
+                "{} attribute {} at location {} overlaps another attribute at that location",
+                call, attribute.name, attribute.location
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Types of vertex attribute components
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentType {
+    /// Half float, stored as the bits in a `u16`
+    F16 = gl::HALF_FLOAT as isize,
+    F32 = gl::FLOAT as isize,
+    F64 = gl::DOUBLE as isize,
+    I8 = gl::BYTE as isize,
+    U8 = gl::UNSIGNED_BYTE as isize,
+    I16 = gl::SHORT as isize,
+    U16 = gl::UNSIGNED_SHORT as isize,
+    I32 = gl::INT as isize,
+    U32 = gl::UNSIGNED_INT as isize,
+    /// Four signed components packed in a `u32` as 10-10-10-2 bits, w in the top bits. Needs 4 components
+    I2_10_10_10Rev = gl::INT_2_10_10_10_REV as isize,
+    /// Four unsigned components packed in a `u32` as 10-10-10-2 bits, w in the top bits. Needs 4 components
+    U2_10_10_10Rev = gl::UNSIGNED_INT_2_10_10_10_REV as isize,
+}
+impl ComponentType {
+    /// The GL type enum
+    pub fn gl_type(self) -> GLenum {
+        self as GLenum
+    }
+
+    /// Whether components are floating point, which shaders can't read as integers
+    pub fn is_float(self) -> bool {
+        matches!(self, Self::F16 | Self::F32 | Self::F64)
+    }
+}
+
+/// A type that can be a vertex attribute. Used by `#[derive(VertexLayout)]` to find each field's component count and type
+pub trait VertexComponent {
+    /// Components per location
+    const COMPONENTS: i32;
+    const TYPE: ComponentType;
+    /// Consecutive locations taken, one per column for matrices
+    const LOCATIONS: u32 = 1;
+}
+
+macro_rules! matrix_component {
+    ($($ty:ty => $size:expr;)*) => {
+        $(impl VertexComponent for $ty {
+            const COMPONENTS: i32 = $size;
+            const TYPE: ComponentType = ComponentType::F32;
+            const LOCATIONS: u32 = $size;
+        })*
+    };
+}
+matrix_component! {
+    Mat2 => 2;
+    Mat3 => 3;
+    Mat4 => 4;
+}
+
+macro_rules! vertex_component {
+    ($($ty:ty => $components:expr, $component:ident;)*) => {
+        $(impl VertexComponent for $ty {
+            const COMPONENTS: i32 = $components;
+            const TYPE: ComponentType = ComponentType::$component;
+        })*
+    };
+}
+vertex_component! {
+    Half => 1, F16;
+    f32 => 1, F32;
+    f64 => 1, F64;
+    i8 => 1, I8;
+    u8 => 1, U8;
+    i16 => 1, I16;
+    u16 => 1, U16;
+    i32 => 1, I32;
+    u32 => 1, U32;
+    PackedI10x3 => 4, I2_10_10_10Rev;
+    PackedU10x3 => 4, U2_10_10_10Rev;
+    Vec2 => 2, F32;
+    Vec3 => 3, F32;
+    Vec4 => 4, F32;
+    IVec2 => 2, I32;
+    IVec3 => 3, I32;
+    IVec4 => 4, I32;
+    UVec2 => 2, U32;
+    UVec3 => 3, U32;
+    UVec4 => 4, U32;
+}
+
+/// Arrays of up to 4 scalars
+impl<T: VertexComponent, const N: usize> VertexComponent for [T; N] {
+    const COMPONENTS: i32 = {
+        assert!(
+            T::COMPONENTS == 1 && N >= 1 && N <= 4,
+            "vertex attributes are arrays of 1-4 scalars"
+        );
+        N as i32
+    };
+    const TYPE: ComponentType = T::TYPE;
+}
+
+/// The bits of a half float, for `ComponentType::F16` attributes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Pod, Zeroable)]
+#[repr(transparent)]
+pub struct Half(pub u16);
+
+/// Four signed components packed as 10-10-10-2 bits with w in the top bits, for `ComponentType::I2_10_10_10Rev` attributes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Pod, Zeroable)]
+#[repr(transparent)]
+pub struct PackedI10x3(pub u32);
+
+/// Four unsigned components packed as 10-10-10-2 bits with w in the top bits, for `ComponentType::U2_10_10_10Rev` attributes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Pod, Zeroable)]
+#[repr(transparent)]
+pub struct PackedU10x3(pub u32);
+
+/// How a vertex shader sees an attribute's components
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AttributeFormat {
+    /// Converted to float as they are, so the integer 3 becomes 3.0
+    #[default]
+    Float,
+    /// Integers scaled to 0-1 or -1-1
+    Normalized,
+    /// Integers kept as integers
+    Integer,
 }
 
 /// Types of vertex primitives
@@ -103,6 +667,68 @@ pub enum Primitive {
     Patches = gl::PATCHES as isize,
 }
 
+/// Contents of an element buffer. `u8` and `u16` shrink the upload for meshes with up to 256 and 65536 vertices
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Indices {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+impl Indices {
+    /// Picks the narrowest index type that can address every index in `indices`
+    pub fn narrowest(indices: Vec<u32>) -> Self {
+        let max = indices.iter().copied().max().unwrap_or_default();
+        if max <= u8::MAX as u32 {
+            Indices::U8(indices.into_iter().map(|i| i as u8).collect())
+        } else if max <= u16::MAX as u32 {
+            Indices::U16(indices.into_iter().map(|i| i as u16).collect())
+        } else {
+            Indices::U32(indices)
+        }
+    }
+
+    /// Number of indices
+    pub fn len(&self) -> usize {
+        match self {
+            Indices::U8(v) => v.len(),
+            Indices::U16(v) => v.len(),
+            Indices::U32(v) => v.len(),
+        }
+    }
+
+    /// Whether there are no indices
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets the index at position `i`, widened to `u32`
+    pub fn get(&self, i: usize) -> Option<u32> {
+        match self {
+            Indices::U8(v) => v.get(i).map(|i| *i as u32),
+            Indices::U16(v) => v.get(i).map(|i| *i as u32),
+            Indices::U32(v) => v.get(i).copied(),
+        }
+    }
+
+    /// The GL type enum matching the index width
+    pub fn gl_type(&self) -> gl::types::GLenum {
+        match self {
+            Indices::U8(_) => gl::UNSIGNED_BYTE,
+            Indices::U16(_) => gl::UNSIGNED_SHORT,
+            Indices::U32(_) => gl::UNSIGNED_INT,
+        }
+    }
+
+    /// Raw bytes for uploading
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Indices::U8(v) => v,
+            Indices::U16(v) => bytemuck::cast_slice(v),
+            Indices::U32(v) => bytemuck::cast_slice(v),
+        }
+    }
+}
+
 /// Possible types of buffer
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BufferType {
@@ -110,19 +736,27 @@ pub enum BufferType {
     Array = gl::ARRAY_BUFFER as isize,
     /// Array for pointers to vertices to form shapes
     ElementArray = gl::ELEMENT_ARRAY_BUFFER as isize,
+    /// Backing storage for uniform blocks, laid out with std140
+    Uniform = gl::UNIFORM_BUFFER as isize,
+    /// Backing storage for shader storage blocks, laid out with std430. Needs OpenGL 4.3
+    ShaderStorage = gl::SHADER_STORAGE_BUFFER as isize,
 }
 
 /// Wrapper for a (generic buffer)[https://www.khronos.org/opengl/wiki/Buffer_Object]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct Buffer(pub u32);
+#[derive(Debug, PartialEq, Eq)]
+pub struct Buffer(u32);
 impl Buffer {
     /// Makes a new buffer
-    pub fn new() -> Option<Self> {
+    pub fn new() -> Result<Self> {
         let mut vbo = 0;
         unsafe {
             gl::GenBuffers(1, &mut vbo);
         }
-        if vbo != 0 { Some(Self(vbo)) } else { None }
+        if vbo != 0 {
+            Ok(Self::from_raw(vbo))
+        } else {
+            Err(Error::gl("glGenBuffers"))
+        }
     }
 
     /// Bind this buffer to given type
@@ -134,6 +768,94 @@ impl Buffer {
     pub fn clear_binding(ty: BufferType) {
         unsafe { gl::BindBuffer(ty as _, 0) }
     }
+
+    /// Binds the whole buffer to binding point `index` of an indexed type, `Uniform` or `ShaderStorage`.
+    /// Blocks bound to that point read from it. Also binds it to the type's generic binding
+    pub fn bind_base(&self, ty: BufferType, index: u32) {
+        unsafe { gl::BindBufferBase(ty as _, index, self.0) }
+    }
+
+    /// Binds `size` bytes from `offset` to binding point `index` of an indexed type.
+    /// `offset` must be a multiple of `GL_UNIFORM_BUFFER_OFFSET_ALIGNMENT` for uniform buffers
+    pub fn bind_range(&self, ty: BufferType, index: u32, offset: usize, size: usize) {
+        unsafe { gl::BindBufferRange(ty as _, index, self.0, offset as _, size as _) }
+    }
+
+    /// Size of the buffer's storage in bytes. Binds the buffer to given type
+    pub fn size(&self, ty: BufferType) -> usize {
+        let mut size = 0;
+        self.bind(ty);
+        unsafe { gl::GetBufferParameteri64v(ty as _, gl::BUFFER_SIZE, &mut size) };
+        size as usize
+    }
+
+    /// Overwrites part of the buffer starting `offset` bytes in, without reallocating. Binds the buffer to given type
+    pub fn sub_data(&self, ty: BufferType, offset: usize, data: &[u8]) {
+        self.bind(ty);
+        buffer_sub_data(ty, offset, data);
+    }
+
+    /// Gives the buffer fresh storage of the same size, with undefined contents.
+    /// Draws still reading the old storage keep it until they finish, so rewriting the whole buffer every frame doesn't wait on them
+    pub fn orphan(&self, ty: BufferType, usage: GLenum) {
+        let size = self.size(ty);
+        unsafe { gl::BufferData(ty as _, size as _, std::ptr::null(), usage) };
+    }
+
+    /// Reallocates the buffer with room for `size` bytes, keeping as much of the old contents as fits
+    pub fn resize(&self, ty: BufferType, size: usize, usage: GLenum) -> Result<()> {
+        let kept = self.size(ty).min(size);
+        if kept == 0 {
+            unsafe { gl::BufferData(ty as _, size as _, std::ptr::null(), usage) };
+            return Ok(());
+        }
+        let scratch = Buffer::new()?;
+        unsafe {
+            gl::BindBuffer(gl::COPY_WRITE_BUFFER, scratch.0);
+            gl::BufferData(
+                gl::COPY_WRITE_BUFFER,
+                kept as _,
+                std::ptr::null(),
+                gl::STREAM_COPY,
+            );
+            gl::BindBuffer(gl::COPY_READ_BUFFER, self.0);
+            gl::CopyBufferSubData(gl::COPY_READ_BUFFER, gl::COPY_WRITE_BUFFER, 0, 0, kept as _);
+            gl::BufferData(gl::COPY_READ_BUFFER, size as _, std::ptr::null(), usage);
+            gl::CopyBufferSubData(gl::COPY_WRITE_BUFFER, gl::COPY_READ_BUFFER, 0, 0, kept as _);
+        }
+        self.bind(ty);
+        Ok(())
+    }
+
+    /// The GL name of the buffer
+    pub fn id(&self) -> u32 {
+        self.0
+    }
+
+    /// Takes ownership of an existing buffer, which will be deleted when dropped
+    pub fn from_raw(id: u32) -> Self {
+        resources::created(ResourceKind::Buffer, id);
+        Self(id)
+    }
+
+    /// Gives up ownership of the buffer without deleting it
+    pub fn into_raw(self) -> u32 {
+        resources::released(ResourceKind::Buffer, self.0);
+        std::mem::ManuallyDrop::new(self).0
+    }
+
+    /// Names the buffer in debug messages, debugging tools and resource dumps
+    pub fn set_label(&self, label: &str) -> Result<()> {
+        debug::label_object(ResourceKind::Buffer, self.0, label)
+    }
+}
+
+/// Deletes the buffer
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        resources::released(ResourceKind::Buffer, self.0);
+        unsafe { gl::DeleteBuffers(1, &self.0) }
+    }
 }
 
 /// places data into the bound buffer of given type
@@ -146,4 +868,268 @@ pub fn buffer_data(ty: BufferType, data: &[u8], usage: gl::types::GLenum) {
             usage,
         );
     }
-}
\ No newline at end of file
+}
+
+/// places data into the bound buffer of given type, `offset` bytes in, without reallocating
+pub fn buffer_sub_data(ty: BufferType, offset: usize, data: &[u8]) {
+    unsafe {
+        gl::BufferSubData(
+            ty as _,
+            offset.try_into().unwrap(),
+            data.len().try_into().unwrap(),
+            data.as_ptr().cast(),
+        );
+    }
+}
+
+/// Rewrites a whole array buffer, orphaning the old storage if the size is unchanged and reallocating for dynamic use otherwise
+fn upload(buffer: &Buffer, data: &[u8]) {
+    if buffer.size(BufferType::Array) == data.len() {
+        buffer.orphan(BufferType::Array, gl::DYNAMIC_DRAW);
+        buffer_sub_data(BufferType::Array, 0, data);
+    } else {
+        buffer_data(BufferType::Array, data, gl::DYNAMIC_DRAW);
+    }
+}
+
+/// A buffer persistently mapped into memory and split into regions, for streaming data that changes every frame.
+/// Each frame writes a region and fences it after drawing, the region is only written again once the GPU is done with it.
+/// Needs OpenGL 4.4 or `ARB_buffer_storage`
+/// Fields are private as `region` hands out memory computed from them
+pub struct RingBuffer {
+    buffer: Buffer,
+    ty: BufferType,
+    /// Size of each region in bytes
+    region_size: usize,
+    /// Fences placed after the last draw reading each region
+    fences: Vec<Option<gl::types::GLsync>>,
+    /// Region currently being written
+    current: usize,
+    ptr: *mut u8,
+}
+impl RingBuffer {
+    /// Creates a coherent mapped buffer of `regions` regions of `region_size` bytes. Fails if buffer storage is unsupported
+    pub fn new(ty: BufferType, region_size: usize, regions: usize) -> Result<Self> {
+        if !gl::BufferStorage::is_loaded() {
+            return Err(Error::InvalidParameter(
+                "RingBuffer::new needs glBufferStorage (OpenGL 4.4)".to_owned(),
+            ));
+        }
+        if region_size == 0 || regions == 0 {
+            return Err(Error::InvalidParameter(format!(
+                "RingBuffer::new needs at least one region of at least one byte, got {} of {} bytes",
+                regions, region_size
+            )));
+        }
+        let Some(size) = region_size
+            .checked_mul(regions)
+            .filter(|&size| isize::try_from(size).is_ok())
+        else {
+            return Err(Error::InvalidParameter(format!(
+                "RingBuffer::new can't allocate {} regions of {} bytes",
+                regions, region_size
+            )));
+        };
+        let buffer = Buffer::new()?;
+        let flags = gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+        buffer.bind(ty);
+        let ptr = unsafe {
+            gl::BufferStorage(ty as _, size as _, std::ptr::null(), flags);
+            gl::MapBufferRange(ty as _, 0, size as _, flags)
+        };
+        if ptr.is_null() {
+            return Err(Error::gl("glMapBufferRange"));
+        }
+        Ok(Self {
+            buffer,
+            ty,
+            region_size,
+            fences: vec![None; regions],
+            current: 0,
+            ptr: ptr.cast(),
+        })
+    }
+
+    /// The mapped buffer, to bind for draws. Its storage is immutable, so calls that would reallocate it fail
+    /// with `GL_INVALID_OPERATION` instead of moving it out from under the mapping
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    /// Type the buffer was created for
+    pub fn ty(&self) -> BufferType {
+        self.ty
+    }
+
+    /// Size of each region in bytes
+    pub fn region_size(&self) -> usize {
+        self.region_size
+    }
+
+    /// Number of regions the buffer cycles through
+    pub fn regions(&self) -> usize {
+        self.fences.len()
+    }
+
+    /// Index of the region currently being written
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Byte offset of the current region into the buffer, to point attributes or draws at
+    pub fn offset(&self) -> usize {
+        self.current * self.region_size
+    }
+
+    /// Memory of the current region, waiting first for the GPU to finish the draws that last read it
+    pub fn region(&mut self) -> &mut [u8] {
+        if let Some(fence) = self.fences[self.current].take() {
+            unsafe {
+                while gl::ClientWaitSync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, 1_000_000)
+                    == gl::TIMEOUT_EXPIRED
+                {}
+                gl::DeleteSync(fence);
+            }
+        }
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.add(self.offset()), self.region_size) }
+    }
+
+    /// Copies `data` to the start of the current region
+    pub fn write(&mut self, data: &[u8]) -> Result<()> {
+        if data.len() > self.region_size {
+            return Err(Error::InvalidParameter(format!(
+                "RingBuffer::write given {} bytes for {} byte regions",
+                data.len(),
+                self.region_size
+            )));
+        }
+        self.region()[..data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Fences the current region once the draws reading it are issued and moves on to the next one
+    pub fn advance(&mut self) {
+        let fence = unsafe { gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0) };
+        self.fences[self.current] = Some(fence);
+        self.current = (self.current + 1) % self.fences.len();
+    }
+}
+
+/// Unmaps the buffer, which is then deleted along with the fences
+impl Drop for RingBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            for fence in self.fences.drain(..).flatten() {
+                gl::DeleteSync(fence);
+            }
+            self.buffer.bind(self.ty);
+            gl::UnmapBuffer(self.ty as _);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn narrowest_indices_fit_the_largest_index() {
+        assert_eq!(Indices::narrowest(vec![]), Indices::U8(vec![]));
+        assert_eq!(Indices::narrowest(vec![0, 255]), Indices::U8(vec![0, 255]));
+        assert_eq!(Indices::narrowest(vec![0, 256]), Indices::U16(vec![0, 256]));
+        assert_eq!(
+            Indices::narrowest(vec![1, 65535]),
+            Indices::U16(vec![1, 65535])
+        );
+        assert_eq!(
+            Indices::narrowest(vec![1, 65536]),
+            Indices::U32(vec![1, 65536])
+        );
+    }
+
+    #[test]
+    fn indices_widen_and_encode() {
+        let indices = Indices::narrowest(vec![2, 300, 1]);
+        assert_eq!(indices.len(), 3);
+        assert_eq!(indices.get(1), Some(300));
+        assert_eq!(indices.get(3), None);
+        assert_eq!(indices.gl_type(), gl::UNSIGNED_SHORT);
+        assert_eq!(
+            indices.as_bytes(),
+            bytemuck::cast_slice::<u16, u8>(&[2, 300, 1])
+        );
+
+        let indices = Indices::narrowest(vec![7, 3]);
+        assert_eq!(indices.gl_type(), gl::UNSIGNED_BYTE);
+        assert_eq!(indices.as_bytes(), &[7, 3]);
+    }
+
+    #[derive(Clone, Copy, Pod, Zeroable, VertexLayout)]
+    #[repr(C)]
+    struct Instance {
+        #[location(4)]
+        #[divisor(1)]
+        model: Mat4,
+        #[location(2)]
+        #[normalized]
+        color: [u8; 4],
+        /// Left out of the layout
+        padding: u32,
+        #[location(3)]
+        #[integer]
+        id: u32,
+        #[location(8)]
+        offset: Vec3,
+    }
+
+    #[test]
+    fn derives_vertex_layout() {
+        let column = |c: u32| VertexAttribute {
+            location: 4 + c,
+            offset: c as usize * 16,
+            ..VertexAttribute::new("model", 4, 4, ComponentType::F32, 0).per_instance(1)
+        };
+        assert_eq!(
+            Instance::attributes(),
+            vec![
+                column(0),
+                column(1),
+                column(2),
+                column(3),
+                VertexAttribute::new("color", 2, 4, ComponentType::U8, 64).normalized(),
+                VertexAttribute::new("id", 3, 1, ComponentType::U32, 72).integer(),
+                VertexAttribute::new("offset", 8, 3, ComponentType::F32, 76),
+            ]
+        );
+        let color = Instance::attributes()[4];
+        assert_eq!(color.format, AttributeFormat::Normalized);
+        assert_eq!(color.divisor, 0);
+        assert_eq!(size_of::<Instance>(), 88);
+    }
+
+    #[test]
+    fn overlapping_locations_are_rejected() {
+        let layout = Instance::attributes();
+        assert!(check_layout("attach", &layout, &[0, 1]).is_ok());
+        // the matrix's third column
+        assert!(matches!(
+            check_layout("attach", &layout, &[6]),
+            Err(Error::InvalidParameter(message)) if message.contains("model at location 6")
+        ));
+        let twice = [layout[4], layout[4]];
+        assert!(check_layout("attach", &twice, &[]).is_err());
+    }
+
+    #[test]
+    fn float_components_cant_be_read_as_integers() {
+        let position = VertexAttribute::new("aPos", 0, 3, ComponentType::F32, 0);
+        assert!(check_layout("attach", &[position], &[]).is_ok());
+        assert!(check_layout("attach", &[position.normalized()], &[]).is_ok());
+        assert!(matches!(
+            check_layout("attach", &[position.integer()], &[]),
+            Err(Error::InvalidParameter(message)) if message.contains("aPos")
+        ));
+        let id = VertexAttribute::new("aId", 1, 1, ComponentType::U32, 0).integer();
+        assert!(check_layout("attach", &[id], &[]).is_ok());
+    }
+}
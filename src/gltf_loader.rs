@@ -0,0 +1,713 @@
+use std::path::Path;
+
+use gl::types::GLint;
+use gltf::{camera::Projection, image::Format, mesh::Mode, texture};
+use ultraviolet::{Mat4, Vec3, Vec4};
+
+use crate::{
+    error::Result,
+    gl_objects::{Indices, Primitive, VertexArray},
+    mesh::{self, NormalMode},
+    reflection,
+    screenshot::RgbaImage,
+    shaders::ShaderProgram,
+    textures::{
+        self, TexDirectionWrap, TexScaleOp, TexScaleType, TexWrapBehaviour, Texture, TextureType,
+    },
+    wavefront_parser::{IndexedMesh, TriIndex, Vertex},
+};
+
+/// Texture unit the base colour map is bound to by `PbrMaterial::apply`
+pub const BASE_COLOR_MAP_SLOT: u32 = 0;
+/// Texture unit the metallic-roughness map is bound to by `PbrMaterial::apply`
+pub const METALLIC_ROUGHNESS_MAP_SLOT: u32 = 1;
+/// Texture unit the normal map is bound to by `PbrMaterial::apply`
+pub const NORMAL_MAP_SLOT: u32 = 2;
+/// Texture unit the occlusion map is bound to by `PbrMaterial::apply`
+pub const OCCLUSION_MAP_SLOT: u32 = 3;
+/// Texture unit the emissive map is bound to by `PbrMaterial::apply`
+pub const EMISSIVE_MAP_SLOT: u32 = 4;
+
+/// Reads a `.gltf` (with external or embedded buffers) or `.glb` file with everything it references.
+/// Texture coordinates are flipped to put t = 0 at the bottom of images, matching `Texture::fill_image`.
+/// Missing normals are generated flat and missing tangents are generated for materials with a normal map, as the spec asks
+pub fn load_gltf(path: impl AsRef<Path>) -> Result<GltfModel> {
+    let (document, buffers, images) = gltf::import(path)?;
+
+    let materials: Vec<PbrMaterial> = document.materials().map(PbrMaterial::from_gltf).collect();
+
+    let mut srgb = vec![false; document.textures().len()];
+    for material in &materials {
+        for map in [&material.base_color_map, &material.emissive_map]
+            .into_iter()
+            .flatten()
+        {
+            srgb[map.texture] = true;
+        }
+    }
+    let textures = document
+        .textures()
+        .map(|t| GltfTexture::from_gltf(&t, srgb[t.index()]))
+        .collect();
+
+    let meshes = document
+        .meshes()
+        .map(|m| {
+            let mut primitives = Vec::new();
+            let mut skipped_primitives = Vec::new();
+            for p in m.primitives() {
+                match GltfPrimitive::from_gltf(&p, &buffers, &materials) {
+                    Some(primitive) => primitives.push(primitive),
+                    None => skipped_primitives.push(p.index()),
+                }
+            }
+            GltfMesh {
+                name: m.name().map(str::to_string),
+                primitives,
+                skipped_primitives,
+            }
+        })
+        .collect();
+
+    let nodes = document
+        .nodes()
+        .map(|n| GltfNode {
+            name: n.name().map(str::to_string),
+            transform: Mat4::from(n.transform().matrix()),
+            children: n.children().map(|c| c.index()).collect(),
+            mesh: n.mesh().map(|m| m.index()),
+            camera: n.camera().map(|c| c.index()),
+        })
+        .collect();
+
+    let roots = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .map(|s| s.nodes().map(|n| n.index()).collect())
+        .unwrap_or_default();
+
+    let cameras = document
+        .cameras()
+        .map(|c| GltfCamera {
+            name: c.name().map(str::to_string),
+            projection: match c.projection() {
+                Projection::Perspective(p) => CameraProjection::Perspective {
+                    yfov: p.yfov(),
+                    aspect_ratio: p.aspect_ratio(),
+                    znear: p.znear(),
+                    zfar: p.zfar(),
+                },
+                Projection::Orthographic(o) => CameraProjection::Orthographic {
+                    xmag: o.xmag(),
+                    ymag: o.ymag(),
+                    znear: o.znear(),
+                    zfar: o.zfar(),
+                },
+            },
+        })
+        .collect();
+
+    Ok(GltfModel {
+        meshes,
+        materials,
+        textures,
+        images: images.iter().map(to_rgba).collect(),
+        nodes,
+        roots,
+        cameras,
+    })
+}
+
+/// Everything imported from a glTF file, with cross references kept as indices like in the file
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfModel {
+    pub meshes: Vec<GltfMesh>,
+    pub materials: Vec<PbrMaterial>,
+    pub textures: Vec<GltfTexture>,
+    /// Decoded images, converted to 8-bit RGBA
+    pub images: Vec<RgbaImage>,
+    pub nodes: Vec<GltfNode>,
+    /// Root nodes of the default scene, or of the first scene if none is marked default
+    pub roots: Vec<usize>,
+    pub cameras: Vec<GltfCamera>,
+}
+impl GltfModel {
+    /// Computes the world transform of every node, or `None` for nodes outside the scene
+    pub fn world_transforms(&self) -> Vec<Option<Mat4>> {
+        let mut world = vec![None; self.nodes.len()];
+        let mut stack: Vec<(usize, Mat4)> =
+            self.roots.iter().map(|r| (*r, Mat4::identity())).collect();
+        while let Some((node, parent)) = stack.pop() {
+            let transform = parent * self.nodes[node].transform;
+            world[node] = Some(transform);
+            stack.extend(self.nodes[node].children.iter().map(|c| (*c, transform)));
+        }
+        world
+    }
+
+    /// Lists the cameras placed in the scene with their camera-to-world transforms
+    pub fn scene_cameras(&self) -> Vec<(&GltfCamera, Mat4)> {
+        self.world_transforms()
+            .into_iter()
+            .zip(&self.nodes)
+            .filter_map(|(transform, node)| Some((self.cameras.get(node.camera?)?, transform?)))
+            .collect()
+    }
+
+    /// Uploads every mesh primitive and texture. Textures whose image is missing are left out as `None`
+    pub fn upload(&self) -> Result<GltfResources> {
+        let vertex_arrays = self
+            .meshes
+            .iter()
+            .map(|mesh| {
+                mesh.primitives
+                    .iter()
+                    .map(GltfPrimitive::vertex_array)
+                    .collect()
+            })
+            .collect::<Result<_>>()?;
+        let textures = self
+            .textures
+            .iter()
+            .map(|texture| match self.images.get(texture.image) {
+                Some(image) => texture.upload(image).map(Some),
+                None => Ok(None),
+            })
+            .collect::<Result<_>>()?;
+        Ok(GltfResources {
+            vertex_arrays,
+            textures,
+        })
+    }
+
+    /// Draws the scene with `program`, setting the matrix uniforms per node and the material uniforms per primitive
+    pub fn draw(
+        &self,
+        program: &ShaderProgram,
+        resources: &GltfResources,
+        view: &Mat4,
+        proj: &Mat4,
+    ) -> Result<()> {
+        program.use_program();
+        let default_material = PbrMaterial::default();
+        for (node, world) in self.nodes.iter().zip(self.world_transforms()) {
+            let (Some(mesh), Some(world)) = (node.mesh, world) else {
+                continue;
+            };
+            program.set_matrix_uniforms(&world, view, proj);
+            for (primitive, vao) in self.meshes[mesh]
+                .primitives
+                .iter()
+                .zip(&resources.vertex_arrays[mesh])
+            {
+                let material = primitive
+                    .material
+                    .and_then(|m| self.materials.get(m))
+                    .unwrap_or(&default_material);
+                material.apply(program, &resources.textures);
+                vao.draw(primitive.mode)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// GL objects for a `GltfModel`, indexed the same way as its meshes and textures
+pub struct GltfResources {
+    /// One VAO per primitive of each mesh
+    pub vertex_arrays: Vec<Vec<VertexArray>>,
+    /// `None` for textures whose image is missing from `GltfModel::images`
+    pub textures: Vec<Option<Texture>>,
+}
+
+/// A named list of primitives drawn together
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfMesh {
+    pub name: Option<String>,
+    pub primitives: Vec<GltfPrimitive>,
+    /// Indices in the file of the primitives left out of `primitives` because they have no positions
+    pub skipped_primitives: Vec<usize>,
+}
+
+/// Geometry drawn with one material
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfPrimitive {
+    /// Vertices (pos-tex-normal) and indices. Primitives without indices are given sequential ones
+    pub mesh: IndexedMesh,
+    /// One tangent per vertex, from the file or generated for normal mapping. See `mesh::corner_tangents`
+    pub tangents: Option<Vec<Vec4>>,
+    pub mode: Primitive,
+    /// Index into `GltfModel::materials`
+    pub material: Option<usize>,
+}
+impl GltfPrimitive {
+    fn from_gltf(
+        primitive: &gltf::Primitive<'_>,
+        buffers: &[gltf::buffer::Data],
+        materials: &[PbrMaterial],
+    ) -> Option<Self> {
+        let reader = primitive.reader(|b| buffers.get(b.index()).map(|d| &d.0[..]));
+        let positions: Vec<[f32; 3]> = reader.read_positions()?.collect();
+        let mut tex_coords = reader.read_tex_coords(0).map(|t| t.into_f32());
+        let mut normals = reader.read_normals();
+        let vertices: Vec<Vertex> = positions
+            .iter()
+            .map(|p| {
+                let t = tex_coords
+                    .as_mut()
+                    .and_then(|t| t.next())
+                    .unwrap_or_default();
+                let n = normals.as_mut().and_then(|n| n.next()).unwrap_or_default();
+                [p[0], p[1], p[2], t[0], 1.0 - t[1], n[0], n[1], n[2]]
+            })
+            .collect();
+
+        let mut indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..vertices.len() as u32).collect(),
+        };
+        let mode = match primitive.mode() {
+            Mode::Points => Primitive::Points,
+            Mode::Lines => Primitive::Lines,
+            Mode::LineStrip => Primitive::LineStrip,
+            Mode::LineLoop => {
+                // closed by repeating the first index
+                if let Some(first) = indices.first() {
+                    indices.push(*first);
+                }
+                Primitive::LineStrip
+            }
+            Mode::Triangles => Primitive::Triangles,
+            Mode::TriangleStrip => Primitive::TriangleStrip,
+            Mode::TriangleFan => Primitive::TriangleFan,
+        };
+
+        let mut indexed = IndexedMesh {
+            vertices,
+            indices: Indices::narrowest(indices),
+            generated_triangles: 0,
+        };
+        let triangles: Vec<TriIndex> = indexed.triangles().collect();
+        // glTF bitangents point up the image, towards increasing t once it's flipped, so the handedness carries over
+        let mut tangents = reader
+            .read_tangents()
+            .map(|t| t.map(Vec4::from).collect::<Vec<_>>())
+            .filter(|t| t.len() == indexed.vertices.len());
+
+        if mode == Primitive::Triangles {
+            if normals.is_none() {
+                indexed = mesh::split_normals(&indexed.vertices, &triangles, NormalMode::Flat);
+                tangents = None;
+            }
+            let normal_mapped = primitive
+                .material()
+                .index()
+                .and_then(|m| materials.get(m))
+                .is_some_and(|m| m.normal_map.is_some());
+            if tangents.is_none() && normal_mapped {
+                tangents = Some(indexed.generate_tangents());
+            }
+        }
+
+        Some(Self {
+            mesh: indexed,
+            tangents,
+            mode,
+            material: primitive.material().index(),
+        })
+    }
+
+    /// Creates a VAO holding this primitive's vertices and indices, with the tangents at location 3 if there are any
+    pub fn vertex_array(&self) -> Result<VertexArray> {
+        let mut vao = VertexArray::new()?;
+        match &self.tangents {
+            Some(tangents) => {
+                vao.attach(&mesh::interleave_tangents(&self.mesh.vertices, tangents))?;
+                vao.attach_indices(self.mesh.indices.clone())?;
+            }
+            None => vao.attach_indexed(self.mesh.vertices.clone(), self.mesh.indices.clone())?,
+        }
+        Ok(vao)
+    }
+}
+
+/// How a material's alpha is interpreted
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AlphaMode {
+    /// Alpha is ignored
+    #[default]
+    Opaque,
+    /// Fragments with alpha below the cutoff are discarded, the rest are opaque
+    Mask(f32),
+    /// Alpha blends with what is behind
+    Blend,
+}
+
+/// A reference to a texture from a material
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureRef {
+    /// Index into `GltfModel::textures`
+    pub texture: usize,
+    /// Texture coordinate set. Only set 0 is imported
+    pub tex_coord: u32,
+}
+impl TextureRef {
+    fn from_info(info: &texture::Info<'_>) -> Self {
+        Self {
+            texture: info.texture().index(),
+            tex_coord: info.tex_coord(),
+        }
+    }
+}
+
+/// A metallic-roughness material
+#[derive(Debug, Clone, PartialEq)]
+pub struct PbrMaterial {
+    pub name: Option<String>,
+    /// Linear RGBA, multiplied with the base colour map
+    pub base_color: Vec4,
+    /// sRGB colour in RGB, alpha in A
+    pub base_color_map: Option<TextureRef>,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Roughness in G, metalness in B
+    pub metallic_roughness_map: Option<TextureRef>,
+    /// Tangent space normal map
+    pub normal_map: Option<TextureRef>,
+    /// Multiplier for the normal map's X and Y
+    pub normal_scale: f32,
+    /// Ambient occlusion in R
+    pub occlusion_map: Option<TextureRef>,
+    /// How much of the occlusion map is applied, 0-1
+    pub occlusion_strength: f32,
+    /// Linear RGB, multiplied with the emissive map
+    pub emissive: Vec3,
+    /// sRGB colour
+    pub emissive_map: Option<TextureRef>,
+    pub alpha_mode: AlphaMode,
+    /// Whether back faces are shown
+    pub double_sided: bool,
+}
+impl Default for PbrMaterial {
+    /// The material the spec uses for primitives without one
+    fn default() -> Self {
+        Self {
+            name: None,
+            base_color: Vec4::one(),
+            base_color_map: None,
+            metallic: 1.0,
+            roughness: 1.0,
+            metallic_roughness_map: None,
+            normal_map: None,
+            normal_scale: 1.0,
+            occlusion_map: None,
+            occlusion_strength: 1.0,
+            emissive: Vec3::zero(),
+            emissive_map: None,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+        }
+    }
+}
+impl PbrMaterial {
+    fn from_gltf(material: gltf::Material<'_>) -> Self {
+        let pbr = material.pbr_metallic_roughness();
+        let normal = material.normal_texture();
+        let occlusion = material.occlusion_texture();
+        Self {
+            name: material.name().map(str::to_string),
+            base_color: Vec4::from(pbr.base_color_factor()),
+            base_color_map: pbr.base_color_texture().as_ref().map(TextureRef::from_info),
+            metallic: pbr.metallic_factor(),
+            roughness: pbr.roughness_factor(),
+            metallic_roughness_map: pbr
+                .metallic_roughness_texture()
+                .as_ref()
+                .map(TextureRef::from_info),
+            normal_map: normal.as_ref().map(|n| TextureRef {
+                texture: n.texture().index(),
+                tex_coord: n.tex_coord(),
+            }),
+            normal_scale: normal.as_ref().map_or(1.0, |n| n.scale()),
+            occlusion_map: occlusion.as_ref().map(|o| TextureRef {
+                texture: o.texture().index(),
+                tex_coord: o.tex_coord(),
+            }),
+            occlusion_strength: occlusion.as_ref().map_or(1.0, |o| o.strength()),
+            emissive: Vec3::from(material.emissive_factor()),
+            emissive_map: material
+                .emissive_texture()
+                .as_ref()
+                .map(TextureRef::from_info),
+            alpha_mode: match material.alpha_mode() {
+                gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+                gltf::material::AlphaMode::Mask => {
+                    AlphaMode::Mask(material.alpha_cutoff().unwrap_or(0.5))
+                }
+                gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+            },
+            double_sided: material.double_sided(),
+        }
+    }
+
+    /// Checks that `program` declares every uniform `PbrMaterial::apply` sets, with types it can set them from
+    pub fn check_program(program: &ShaderProgram) -> Result<()> {
+        let info = program.reflect();
+        let mut problems: Vec<String> = [
+            info.uniform_mismatch::<Vec4>("material.base_color"),
+            info.uniform_mismatch::<f32>("material.metallic"),
+            info.uniform_mismatch::<f32>("material.roughness"),
+            info.uniform_mismatch::<f32>("material.normal_scale"),
+            info.uniform_mismatch::<f32>("material.occlusion_strength"),
+            info.uniform_mismatch::<Vec3>("material.emissive"),
+            info.uniform_mismatch::<GLint>("material.alpha_mode"),
+            info.uniform_mismatch::<f32>("material.alpha_cutoff"),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        for name in [
+            "base_color",
+            "metallic_roughness",
+            "normal",
+            "occlusion",
+            "emissive",
+        ] {
+            problems.extend(info.uniform_mismatch::<GLint>(&format!("material.{}_map", name)));
+            problems.extend(info.uniform_mismatch::<bool>(&format!("material.has_{}_map", name)));
+        }
+        reflection::problems_to_result(problems)
+    }
+
+    /// Sets the `material.*` uniforms read by `shaders/pbr.frag`, binds the maps to their slots and sets culling and blending.
+    /// `textures` is indexed like `GltfModel::textures`
+    pub fn apply(&self, program: &ShaderProgram, textures: &[Option<Texture>]) {
+        program.set_uniform("material.base_color", self.base_color);
+        program.set_uniform("material.metallic", self.metallic);
+        program.set_uniform("material.roughness", self.roughness);
+        program.set_uniform("material.normal_scale", self.normal_scale);
+        program.set_uniform("material.occlusion_strength", self.occlusion_strength);
+        program.set_uniform("material.emissive", self.emissive);
+        let (alpha_mode, alpha_cutoff) = match self.alpha_mode {
+            AlphaMode::Opaque => (0, 0.0),
+            AlphaMode::Mask(cutoff) => (1, cutoff),
+            AlphaMode::Blend => (2, 0.0),
+        };
+        program.set_uniform("material.alpha_mode", alpha_mode as GLint);
+        program.set_uniform("material.alpha_cutoff", alpha_cutoff);
+
+        let maps = [
+            ("base_color", BASE_COLOR_MAP_SLOT, &self.base_color_map),
+            (
+                "metallic_roughness",
+                METALLIC_ROUGHNESS_MAP_SLOT,
+                &self.metallic_roughness_map,
+            ),
+            ("normal", NORMAL_MAP_SLOT, &self.normal_map),
+            ("occlusion", OCCLUSION_MAP_SLOT, &self.occlusion_map),
+            ("emissive", EMISSIVE_MAP_SLOT, &self.emissive_map),
+        ];
+        for (name, slot, map) in maps {
+            let texture = map.and_then(|m| textures.get(m.texture)?.as_ref());
+            program.set_uniform(&format!("material.{}_map", name), slot as GLint);
+            program.set_uniform(&format!("material.has_{}_map", name), texture.is_some());
+            if let Some(texture) = texture {
+                textures::set_texture_slot(slot);
+                texture.bind(TextureType::Tex2d);
+            }
+        }
+        textures::set_texture_slot(0);
+
+        unsafe {
+            if self.double_sided {
+                gl::Disable(gl::CULL_FACE);
+            } else {
+                gl::Enable(gl::CULL_FACE);
+                gl::CullFace(gl::BACK);
+            }
+            if self.alpha_mode == AlphaMode::Blend {
+                gl::Enable(gl::BLEND);
+                gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            } else {
+                gl::Disable(gl::BLEND);
+            }
+        }
+    }
+}
+
+/// An image with the sampler settings it is read with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GltfTexture {
+    /// Index into `GltfModel::images`
+    pub image: usize,
+    pub wrap_s: TexWrapBehaviour,
+    pub wrap_t: TexWrapBehaviour,
+    pub minify: TexScaleOp,
+    pub magnify: TexScaleOp,
+    /// Whether the image holds sRGB colours (base colour and emissive maps) rather than linear data
+    pub srgb: bool,
+}
+impl GltfTexture {
+    fn from_gltf(texture: &gltf::Texture<'_>, srgb: bool) -> Self {
+        let sampler = texture.sampler();
+        let wrap = |mode| match mode {
+            texture::WrappingMode::ClampToEdge => TexWrapBehaviour::ClampToEdge,
+            texture::WrappingMode::MirroredRepeat => TexWrapBehaviour::MirroredRepeat,
+            texture::WrappingMode::Repeat => TexWrapBehaviour::Repeat,
+        };
+        Self {
+            image: texture.source().index(),
+            wrap_s: wrap(sampler.wrap_s()),
+            wrap_t: wrap(sampler.wrap_t()),
+            minify: match sampler.min_filter() {
+                Some(texture::MinFilter::Nearest) => TexScaleOp::Nearest,
+                Some(texture::MinFilter::Linear) => TexScaleOp::Linear,
+                Some(texture::MinFilter::NearestMipmapNearest) => TexScaleOp::NearestMipmapNearest,
+                Some(texture::MinFilter::LinearMipmapNearest) => TexScaleOp::LinearMipmapNearest,
+                Some(texture::MinFilter::NearestMipmapLinear) => TexScaleOp::NearestMipmapLinear,
+                Some(texture::MinFilter::LinearMipmapLinear) | None => {
+                    TexScaleOp::LinearMipmapLinear
+                }
+            },
+            magnify: match sampler.mag_filter() {
+                Some(texture::MagFilter::Nearest) => TexScaleOp::Nearest,
+                Some(texture::MagFilter::Linear) | None => TexScaleOp::Linear,
+            },
+            srgb,
+        }
+    }
+
+    /// Creates a texture from `image` with mipmaps and this texture's sampler settings. Leaves the texture bound
+    pub fn upload(&self, image: &RgbaImage) -> Result<Texture> {
+        let tex = Texture::new()?;
+        tex.bind(TextureType::Tex2d);
+        if self.srgb {
+            Texture::fill_image_srgb(image);
+        } else {
+            Texture::fill_image(image);
+        }
+        Texture::gen_mipmap(TextureType::Tex2d);
+        Texture::set_wrap_behaviour(TextureType::Tex2d, TexDirectionWrap::X, self.wrap_s);
+        Texture::set_wrap_behaviour(TextureType::Tex2d, TexDirectionWrap::Y, self.wrap_t);
+        Texture::set_scale_behaviour(TextureType::Tex2d, TexScaleType::Minify, self.minify)?;
+        Texture::set_scale_behaviour(TextureType::Tex2d, TexScaleType::Magnify, self.magnify)?;
+        Ok(tex)
+    }
+}
+
+/// An element of the scene hierarchy
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfNode {
+    pub name: Option<String>,
+    /// Transform relative to the parent node
+    pub transform: Mat4,
+    /// Indices into `GltfModel::nodes`
+    pub children: Vec<usize>,
+    /// Index into `GltfModel::meshes`
+    pub mesh: Option<usize>,
+    /// Index into `GltfModel::cameras`
+    pub camera: Option<usize>,
+}
+
+/// A camera, looking down its node's -Z axis with +Y up
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfCamera {
+    pub name: Option<String>,
+    pub projection: CameraProjection,
+}
+
+/// Projection parameters of a camera
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CameraProjection {
+    Perspective {
+        /// Vertical field of view in radians
+        yfov: f32,
+        /// Width over height. When missing the viewport's aspect ratio is used
+        aspect_ratio: Option<f32>,
+        znear: f32,
+        /// When missing the projection is infinite
+        zfar: Option<f32>,
+    },
+    Orthographic {
+        /// Half the width of the view
+        xmag: f32,
+        /// Half the height of the view
+        ymag: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+impl CameraProjection {
+    /// Builds the GL projection matrix, using `viewport_aspect` when the camera doesn't fix one
+    pub fn matrix(&self, viewport_aspect: f32) -> Mat4 {
+        match *self {
+            CameraProjection::Perspective {
+                yfov,
+                aspect_ratio,
+                znear,
+                zfar: Some(zfar),
+            } => ultraviolet::projection::perspective_gl(
+                yfov,
+                aspect_ratio.unwrap_or(viewport_aspect),
+                znear,
+                zfar,
+            ),
+            CameraProjection::Perspective {
+                yfov,
+                aspect_ratio,
+                znear,
+                zfar: None,
+            } => ultraviolet::projection::perspective_infinite_z_gl(
+                yfov,
+                aspect_ratio.unwrap_or(viewport_aspect),
+                znear,
+            ),
+            CameraProjection::Orthographic {
+                xmag,
+                ymag,
+                znear,
+                zfar,
+            } => ultraviolet::projection::orthographic_gl(-xmag, xmag, -ymag, ymag, znear, zfar),
+        }
+    }
+}
+
+/// Converts a decoded image of any format to 8-bit RGBA, keeping the top 8 bits of wider channels
+fn to_rgba(image: &gltf::image::Data) -> RgbaImage {
+    let channels = match image.format {
+        Format::R8 | Format::R16 => 1,
+        Format::R8G8 | Format::R16G16 => 2,
+        Format::R8G8B8 | Format::R16G16B16 | Format::R32G32B32FLOAT => 3,
+        Format::R8G8B8A8 | Format::R16G16B16A16 | Format::R32G32B32A32FLOAT => 4,
+    };
+    let samples: Vec<u8> = match image.format {
+        Format::R8 | Format::R8G8 | Format::R8G8B8 | Format::R8G8B8A8 => image.pixels.clone(),
+        Format::R16 | Format::R16G16 | Format::R16G16B16 | Format::R16G16B16A16 => image
+            .pixels
+            .chunks_exact(2)
+            .map(|c| (u16::from_ne_bytes([c[0], c[1]]) >> 8) as u8)
+            .collect(),
+        Format::R32G32B32FLOAT | Format::R32G32B32A32FLOAT => image
+            .pixels
+            .chunks_exact(4)
+            .map(|c| {
+                (f32::from_ne_bytes([c[0], c[1], c[2], c[3]]).clamp(0.0, 1.0) * 255.0).round() as u8
+            })
+            .collect(),
+    };
+    RgbaImage {
+        width: image.width,
+        height: image.height,
+        pixels: samples
+            .chunks_exact(channels)
+            .map(|p| match p {
+                // single channel data such as occlusion is read from R
+                [r] => [*r, *r, *r, 255],
+                [r, g] => [*r, *g, 0, 255],
+                [r, g, b] => [*r, *g, *b, 255],
+                [r, g, b, a] => [*r, *g, *b, *a],
+                _ => unreachable!("images have 1-4 channels"),
+            })
+            .collect(),
+    }
+}
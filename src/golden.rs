@@ -0,0 +1,447 @@
+use std::{
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use ultraviolet::IVec2;
+
+use crate::{Application, HeadlessBackend, WindowContext, error::Error, screenshot::RgbaImage};
+
+/// Colour difference (CIE76 ΔE) below which two colours look the same to most people
+pub const JUST_NOTICEABLE_DIFFERENCE: f32 = 2.3;
+
+/// A scene that can be rendered offscreen and compared against a reference image
+pub trait Scene {
+    /// Name of the scene. The reference image is stored as `<name>.png`
+    fn name(&self) -> &str;
+
+    /// Size of the rendered image in pixels
+    fn size(&self) -> IVec2 {
+        IVec2::new(256, 256)
+    }
+
+    /// Number of frames to draw before capturing, for scenes that need to settle
+    fn frames(&self) -> u32 {
+        1
+    }
+
+    /// Creates the scene's GL resources. Called once after the context exists
+    fn setup(&mut self, app: &mut Application);
+
+    /// Draws one frame into the bound render target
+    fn draw(&mut self, app: &mut Application);
+}
+
+/// How far a rendered image may stray from its reference
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerance {
+    /// Largest difference allowed in any one channel before a pixel counts as mismatched
+    pub channel: u8,
+    /// Fraction (0-1) of pixels that may be mismatched before the comparison fails
+    pub mismatched_fraction: f64,
+}
+impl Default for Tolerance {
+    /// Allows for rounding differences between rasterizer versions, but nothing visible
+    fn default() -> Self {
+        Self {
+            channel: 2,
+            mismatched_fraction: 0.0,
+        }
+    }
+}
+
+/// Where reference images live and how strictly they are compared
+#[derive(Debug, Clone, PartialEq)]
+pub struct GoldenConfig {
+    /// Directory holding the `<name>.png` reference images
+    pub reference_dir: PathBuf,
+    /// Directory the actual and diff images are written to on failure
+    pub output_dir: PathBuf,
+    pub tolerance: Tolerance,
+    /// Context backend scenes are rendered with
+    pub backend: HeadlessBackend,
+    /// Overwrite reference images with the rendered output instead of comparing. The only way references get written
+    pub bless: bool,
+}
+impl Default for GoldenConfig {
+    /// References in `tests/golden`, output in `target/golden`, rendering through EGL.
+    /// Run with `LIBGL_ALWAYS_SOFTWARE=1` so Mesa picks llvmpipe, and `MAGIEBLEUE_BLESS=1` to re-record all references
+    fn default() -> Self {
+        Self {
+            reference_dir: PathBuf::from("tests/golden"),
+            output_dir: PathBuf::from("target/golden"),
+            tolerance: Tolerance::default(),
+            backend: HeadlessBackend::Egl,
+            bless: env::var("MAGIEBLEUE_BLESS").is_ok_and(|v| v == "1"),
+        }
+    }
+}
+
+/// Per-pixel comparison of a rendered image against its reference
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffReport {
+    pub width: u32,
+    pub height: u32,
+    /// Pixels with any channel outside the tolerance
+    pub mismatched_pixels: usize,
+    /// Largest single channel difference found
+    pub max_channel_delta: u8,
+    /// Mean CIE76 ΔE over all pixels
+    pub mean_delta_e: f32,
+    /// Largest CIE76 ΔE of any pixel
+    pub max_delta_e: f32,
+    /// Pixels whose ΔE is at or above `JUST_NOTICEABLE_DIFFERENCE`
+    pub noticeable_pixels: usize,
+    /// Mismatched pixels drawn in red over a faded copy of the reference
+    pub diff: RgbaImage,
+}
+impl DiffReport {
+    /// Fraction (0-1) of pixels that are mismatched
+    pub fn mismatched_fraction(&self) -> f64 {
+        let total = self.width as usize * self.height as usize;
+        if total == 0 {
+            0.0
+        } else {
+            self.mismatched_pixels as f64 / total as f64
+        }
+    }
+
+    /// Whether the comparison falls within `tolerance`
+    pub fn passes(&self, tolerance: &Tolerance) -> bool {
+        self.mismatched_fraction() <= tolerance.mismatched_fraction
+    }
+}
+impl fmt::Display for DiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} pixels mismatched ({:.3}%), max channel delta {}, ΔE mean {:.3} max {:.3}, {} noticeable pixels",
+            self.mismatched_pixels,
+            self.width as usize * self.height as usize,
+            self.mismatched_fraction() * 100.0,
+            self.max_channel_delta,
+            self.mean_delta_e,
+            self.max_delta_e,
+            self.noticeable_pixels,
+        )
+    }
+}
+
+/// Ways a golden-image check can fail
+#[derive(Debug)]
+pub enum GoldenError {
+    /// The headless context couldn't be created, e.g. because the backend isn't installed
+    Context(Error),
+    /// Reading the reference or writing output failed
+    Io(PathBuf, io::Error),
+    /// There is no reference image to compare against. Record one with `MAGIEBLEUE_BLESS=1`
+    MissingReference(PathBuf),
+    /// The rendered image and reference have different dimensions
+    SizeMismatch { actual: IVec2, expected: IVec2 },
+    /// The rendered image differs from the reference by more than the tolerance
+    Mismatch {
+        report: DiffReport,
+        actual_path: PathBuf,
+        diff_path: PathBuf,
+    },
+}
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GoldenError::Context(e) => write!(f, "no headless context: {}", e),
+            GoldenError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            GoldenError::MissingReference(path) => write!(
+                f,
+                "no reference image at {}, run with MAGIEBLEUE_BLESS=1 to record it",
+                path.display()
+            ),
+            GoldenError::SizeMismatch { actual, expected } => write!(
+                f,
+                "rendered {}x{} but reference is {}x{}",
+                actual.x, actual.y, expected.x, expected.y
+            ),
+            GoldenError::Mismatch {
+                report,
+                actual_path,
+                diff_path,
+            } => write!(
+                f,
+                "{}. actual image: {}, diff image: {}",
+                report,
+                actual_path.display(),
+                diff_path.display()
+            ),
+        }
+    }
+}
+impl std::error::Error for GoldenError {}
+
+/// Result of a passing golden-image check
+#[derive(Debug, Clone, PartialEq)]
+pub enum GoldenOutcome {
+    /// The image matched its reference within tolerance
+    Matched(DiffReport),
+    /// Blessing was requested, so the image was recorded as the reference
+    Recorded(PathBuf),
+}
+
+/// Compares two images of the same size pixel by pixel
+pub fn compare(actual: &RgbaImage, expected: &RgbaImage, tolerance: &Tolerance) -> DiffReport {
+    let mut diff = RgbaImage::new(expected.width, expected.height, [0, 0, 0, 255]);
+    let mut mismatched_pixels = 0;
+    let mut max_channel_delta = 0;
+    let mut total_delta_e = 0.0;
+    let mut max_delta_e = 0.0_f32;
+    let mut noticeable_pixels = 0;
+
+    for ((a, e), d) in actual
+        .pixels
+        .iter()
+        .zip(&expected.pixels)
+        .zip(diff.pixels.iter_mut())
+    {
+        let channel_delta = a
+            .iter()
+            .zip(e)
+            .map(|(a, e)| a.abs_diff(*e))
+            .max()
+            .unwrap_or(0);
+        max_channel_delta = max_channel_delta.max(channel_delta);
+
+        let delta_e = delta_e(*a, *e);
+        total_delta_e += delta_e as f64;
+        max_delta_e = max_delta_e.max(delta_e);
+        if delta_e >= JUST_NOTICEABLE_DIFFERENCE {
+            noticeable_pixels += 1;
+        }
+
+        if channel_delta > tolerance.channel {
+            mismatched_pixels += 1;
+            *d = [255, 0, 0, 255];
+        } else {
+            let luma = (0.299 * e[0] as f32 + 0.587 * e[1] as f32 + 0.114 * e[2] as f32) as u8;
+            let faded = 255 - (255 - luma) / 4;
+            *d = [faded, faded, faded, 255];
+        }
+    }
+
+    let total = expected.pixels.len().max(1);
+    DiffReport {
+        width: expected.width,
+        height: expected.height,
+        mismatched_pixels,
+        max_channel_delta,
+        mean_delta_e: (total_delta_e / total as f64) as f32,
+        max_delta_e,
+        noticeable_pixels,
+        diff,
+    }
+}
+
+/// Checks `actual` against the reference image `name`, writing the actual and diff images to the output directory on failure
+pub fn check(
+    name: &str,
+    actual: &RgbaImage,
+    config: &GoldenConfig,
+) -> Result<GoldenOutcome, GoldenError> {
+    let reference_path = config.reference_dir.join(format!("{}.png", name));
+
+    if config.bless {
+        create_parent(&reference_path)?;
+        actual
+            .save_png(&reference_path)
+            .map_err(|e| GoldenError::Io(reference_path.clone(), e))?;
+        return Ok(GoldenOutcome::Recorded(reference_path));
+    }
+    if !reference_path.exists() {
+        return Err(GoldenError::MissingReference(reference_path));
+    }
+
+    let expected =
+        RgbaImage::load(&reference_path).map_err(|e| GoldenError::Io(reference_path, e))?;
+    if actual.width != expected.width || actual.height != expected.height {
+        return Err(GoldenError::SizeMismatch {
+            actual: IVec2::new(actual.width as i32, actual.height as i32),
+            expected: IVec2::new(expected.width as i32, expected.height as i32),
+        });
+    }
+
+    let report = compare(actual, &expected, &config.tolerance);
+    if report.passes(&config.tolerance) {
+        return Ok(GoldenOutcome::Matched(report));
+    }
+
+    let actual_path = config.output_dir.join(format!("{}.actual.png", name));
+    let diff_path = config.output_dir.join(format!("{}.diff.png", name));
+    create_parent(&actual_path)?;
+    actual
+        .save_png(&actual_path)
+        .map_err(|e| GoldenError::Io(actual_path.clone(), e))?;
+    report
+        .diff
+        .save_png(&diff_path)
+        .map_err(|e| GoldenError::Io(diff_path.clone(), e))?;
+    Err(GoldenError::Mismatch {
+        report,
+        actual_path,
+        diff_path,
+    })
+}
+
+/// Renders a scene offscreen and returns the final frame.
+/// Takes the scene by value and drops it before the application, so its GL objects go while their context is current.
+/// Fails with `GoldenError::Context` when `backend` isn't available, which callers may treat as a skip
+pub fn render_scene(
+    mut scene: impl Scene,
+    backend: HeadlessBackend,
+) -> Result<RgbaImage, GoldenError> {
+    let mut app = Application::start_headless(
+        WindowContext {
+            size: scene.size(),
+            window_title: format!("Magiebleue - {}", scene.name()),
+            window_mode: glfw::WindowMode::Windowed,
+        },
+        backend,
+    )
+    .map_err(GoldenError::Context)?;
+    scene.setup(&mut app);
+    for _ in 0..scene.frames() {
+        app.bind_render_target();
+        scene.draw(&mut app);
+    }
+    unsafe { gl::Finish() };
+    let pixels = app.read_pixels();
+    drop(scene);
+    Ok(pixels)
+}
+
+/// Renders a scene and checks it against its reference image, panicking with the diff report on failure.
+/// Meant to be called from tests. Skips with a notice when no headless context can be created, e.g. without EGL
+pub fn assert_scene(scene: impl Scene, config: &GoldenConfig) {
+    let name = scene.name().to_owned();
+    let result =
+        render_scene(scene, config.backend).and_then(|actual| check(&name, &actual, config));
+    match result {
+        Ok(GoldenOutcome::Matched(_)) => {}
+        Ok(GoldenOutcome::Recorded(path)) => {
+            println!("Recorded reference image {}", path.display())
+        }
+        Err(GoldenError::Context(e)) => {
+            println!("skipping golden image `{}`: {}", name, e)
+        }
+        Err(e) => panic!("golden image `{}` failed: {}", name, e),
+    }
+}
+
+fn create_parent(path: &Path) -> Result<(), GoldenError> {
+    match path.parent() {
+        Some(dir) => fs::create_dir_all(dir).map_err(|e| GoldenError::Io(dir.to_path_buf(), e)),
+        None => Ok(()),
+    }
+}
+
+/// CIE76 colour difference between two sRGB colours, ignoring alpha
+fn delta_e(a: [u8; 4], b: [u8; 4]) -> f32 {
+    let a = srgb_to_lab(a);
+    let b = srgb_to_lab(b);
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+/// Converts an sRGB colour to CIELAB under a D65 white point
+fn srgb_to_lab(col: [u8; 4]) -> [f32; 3] {
+    let linear = |c: u8| {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let (r, g, b) = (linear(col[0]), linear(col[1]), linear(col[2]));
+
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let f = |t: f32| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_lab(actual: [f32; 3], expected: [f32; 3]) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 0.05, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    fn image(pixels: Vec<[u8; 4]>) -> RgbaImage {
+        RgbaImage {
+            width: pixels.len() as u32,
+            height: 1,
+            pixels,
+        }
+    }
+
+    #[test]
+    fn lab_matches_reference_values() {
+        assert_lab(srgb_to_lab([0, 0, 0, 255]), [0.0, 0.0, 0.0]);
+        assert_lab(srgb_to_lab([255, 255, 255, 255]), [100.0, 0.0, 0.0]);
+        assert_lab(srgb_to_lab([255, 0, 0, 255]), [53.24, 80.09, 67.20]);
+        assert_lab(srgb_to_lab([0, 255, 0, 255]), [87.73, -86.18, 83.18]);
+        assert_lab(srgb_to_lab([0, 0, 255, 255]), [32.30, 79.19, -107.86]);
+        assert_lab(srgb_to_lab([128, 128, 128, 255]), [53.59, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn delta_e_is_cie76_distance() {
+        assert!((delta_e([0, 0, 0, 255], [255, 255, 255, 255]) - 100.0).abs() < 0.05);
+        assert!((delta_e([255, 0, 0, 255], [0, 255, 0, 255]) - 170.56).abs() < 0.1);
+        assert_eq!(delta_e([10, 20, 30, 255], [10, 20, 30, 0]), 0.0);
+        assert!(delta_e([128, 128, 128, 255], [129, 128, 128, 255]) < JUST_NOTICEABLE_DIFFERENCE);
+    }
+
+    #[test]
+    fn channel_tolerance_is_inclusive() {
+        let tolerance = Tolerance {
+            channel: 2,
+            mismatched_fraction: 0.0,
+        };
+        let expected = image(vec![[100, 100, 100, 255]; 3]);
+        let actual = image(vec![
+            [100, 100, 100, 255],
+            [102, 98, 100, 255],
+            [100, 100, 103, 255],
+        ]);
+        let report = compare(&actual, &expected, &tolerance);
+        assert_eq!(report.mismatched_pixels, 1);
+        assert_eq!(report.max_channel_delta, 3);
+        assert!(!report.passes(&tolerance));
+        assert!(report.passes(&Tolerance {
+            mismatched_fraction: 0.34,
+            ..tolerance
+        }));
+    }
+
+    #[test]
+    fn diff_marks_mismatches_over_faded_reference() {
+        let expected = image(vec![[0, 0, 0, 255], [255, 255, 255, 255], [0, 0, 0, 255]]);
+        let actual = image(vec![[0, 0, 0, 255], [255, 255, 255, 255], [50, 0, 0, 255]]);
+        let report = compare(&actual, &expected, &Tolerance::default());
+        assert_eq!(
+            report.diff.pixels,
+            vec![[192, 192, 192, 255], [255, 255, 255, 255], [255, 0, 0, 255]]
+        );
+        assert_eq!(report.noticeable_pixels, 1);
+        assert_eq!(report.max_delta_e, delta_e([50, 0, 0, 255], [0, 0, 0, 255]));
+    }
+}
@@ -0,0 +1,305 @@
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    error::Result,
+    shaders::{self, PreprocessedSource, Preprocessor, ShaderProgram, ShaderType},
+};
+
+/// Paths of the source files of each stage of a program, and how to preprocess them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderSources {
+    pub vert: PathBuf,
+    pub tesc: Option<PathBuf>,
+    pub tese: Option<PathBuf>,
+    pub geom: Option<PathBuf>,
+    pub frag: PathBuf,
+    pub preprocessor: Preprocessor,
+}
+
+impl ShaderSources {
+    /// The source files, in pipeline order
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.stages().map(|(_, path)| path)
+    }
+
+    /// Reads and preprocesses each stage
+    pub fn preprocess(&self) -> Result<Vec<(ShaderType, PreprocessedSource)>> {
+        let mut sources = Vec::new();
+        for (ty, path) in self.stages() {
+            sources.push((ty, self.preprocessor.process_file(path)?));
+        }
+        Ok(sources)
+    }
+
+    /// Each source file with the stage it's for
+    fn stages(&self) -> impl Iterator<Item = (ShaderType, &Path)> {
+        shaders::stages(
+            self.vert.as_path(),
+            self.tesc.as_deref(),
+            self.tese.as_deref(),
+            self.geom.as_deref(),
+            self.frag.as_path(),
+        )
+    }
+
+    /// Reads and compiles the sources into a new program
+    pub fn compile(&self) -> Result<ShaderProgram> {
+        ShaderProgram::from_preprocessed(&self.preprocess()?)
+    }
+}
+
+/// Last modification time of `path`, or `None` while it can't be read, e.g. mid-save by editors that replace the file
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Whether any watched file's modification time differs from the one recorded
+fn any_changed(watched: &[(PathBuf, Option<SystemTime>)]) -> bool {
+    watched.iter().any(|(path, last)| modified(path) != *last)
+}
+
+/// Every file `stages` read, with its modification time from `before` if it was watched already
+fn watch_list(
+    before: &[(PathBuf, Option<SystemTime>)],
+    stages: &[(ShaderType, PreprocessedSource)],
+) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let mut watched: Vec<(PathBuf, Option<SystemTime>)> = Vec::new();
+    for path in stages.iter().flat_map(|(_, source)| &source.files) {
+        if watched.iter().any(|(watched, _)| watched == path) {
+            continue;
+        }
+        let last = match before.iter().find(|(watched, _)| watched == path) {
+            Some(&(_, last)) => last,
+            None => modified(path),
+        };
+        watched.push((path.clone(), last));
+    }
+    watched
+}
+
+/// A shader program that remembers its source files, including ones it `#include`s, and recompiles when they change.
+/// Derefs to the current program, so it's used like one. Call `reload_if_changed` from the render thread, e.g. once a frame
+pub struct WatchedProgram {
+    pub program: ShaderProgram,
+    pub sources: ShaderSources,
+    /// Every file the sources read, with its modification time when it was last read
+    watched: Vec<(PathBuf, Option<SystemTime>)>,
+    label: Option<String>,
+}
+
+impl WatchedProgram {
+    /// Compiles the program from its sources. Fails like `ShaderProgram::from_filepath` if the first compile does
+    pub fn new(sources: ShaderSources) -> Result<Self> {
+        let before: Vec<_> = sources
+            .paths()
+            .map(|path| (path.to_path_buf(), modified(path)))
+            .collect();
+        let stages = sources.preprocess()?;
+        let program = ShaderProgram::from_preprocessed(&stages)?;
+        Ok(Self {
+            program,
+            sources,
+            watched: watch_list(&before, &stages),
+            label: None,
+        })
+    }
+
+    /// Compiles a watched program from a mandatory vertex & fragment and optional tessellation control/evaluation & geometry shader filepaths.
+    pub fn from_filepath(
+        vert: &str,
+        tesc: Option<&str>,
+        tese: Option<&str>,
+        geom: Option<&str>,
+        frag: &str,
+    ) -> Result<Self> {
+        Self::new(ShaderSources {
+            vert: vert.into(),
+            tesc: tesc.map(PathBuf::from),
+            tese: tese.map(PathBuf::from),
+            geom: geom.map(PathBuf::from),
+            frag: frag.into(),
+            preprocessor: Preprocessor::default(),
+        })
+    }
+
+    /// Whether any source or included file was modified since it was last read
+    pub fn changed(&self) -> bool {
+        any_changed(&self.watched)
+    }
+
+    /// Recompiles the program if any of its sources changed, and swaps it in if it compiles and links.
+    /// Otherwise the old program stays and the compile or link log is printed. Returns whether the program was replaced.
+    ///
+    /// The new program gets the old one's uniform block bindings and label. Other uniforms start from their defaults,
+    /// so ones set once at setup must be set again when this returns `true`
+    pub fn reload_if_changed(&mut self) -> bool {
+        if !self.changed() {
+            return false;
+        }
+        match self.reload() {
+            Ok(()) => {
+                println!("Reloaded shader program from {}", self.describe());
+                true
+            }
+            Err(e) => {
+                eprintln!(
+                    "Keeping the previous shader program for {}: {}",
+                    self.describe(),
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    /// Recompiles the program from its sources whether they changed or not, keeping the old program on failure
+    pub fn reload(&mut self) -> Result<()> {
+        // noted before reading, so a save during the compile triggers another reload
+        let before: Vec<_> = self
+            .watched
+            .iter()
+            .map(|(path, _)| (path.clone(), modified(path)))
+            .collect();
+        let stages = match self.sources.preprocess() {
+            Ok(stages) => stages,
+            Err(e) => {
+                self.watched = before;
+                return Err(e);
+            }
+        };
+        self.watched = watch_list(&before, &stages);
+        let program = ShaderProgram::from_preprocessed(&stages)?;
+
+        for block in self.program.reflect().uniform_blocks {
+            // blocks the new program no longer has are simply dropped
+            let _ = program.bind_uniform_block(&block.name, block.binding);
+        }
+        if let Some(label) = &self.label {
+            program.set_label(label)?;
+        }
+        self.program = program;
+        Ok(())
+    }
+
+    /// Names the program in debug messages, debugging tools and resource dumps, including after reloads
+    pub fn set_label(&mut self, label: &str) -> Result<()> {
+        self.program.set_label(label)?;
+        self.label = Some(label.to_owned());
+        Ok(())
+    }
+
+    /// The source paths, for messages
+    fn describe(&self) -> String {
+        self.sources
+            .paths()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl Deref for WatchedProgram {
+    type Target = ShaderProgram;
+
+    fn deref(&self) -> &ShaderProgram {
+        &self.program
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    /// Writes a vertex & fragment shader sharing an include into a fresh temp directory, all modified at the same time
+    fn write_sources(test: &str) -> ShaderSources {
+        let dir = std::env::temp_dir().join(format!("magiebleue-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in [
+            ("common.glsl", "float scale = 2.0;\n"),
+            (
+                "main.vert",
+                "#version 330 core\n#include \"common.glsl\"\nvoid main() {}\n",
+            ),
+            (
+                "main.frag",
+                "#version 330 core\n#include \"common.glsl\"\nvoid main() {}\n",
+            ),
+        ] {
+            fs::write(dir.join(name), contents).unwrap();
+            touch(&dir.join(name), 1000);
+        }
+        ShaderSources {
+            vert: dir.join("main.vert"),
+            tesc: None,
+            tese: None,
+            geom: None,
+            frag: dir.join("main.frag"),
+            preprocessor: Preprocessor::default(),
+        }
+    }
+
+    /// Sets the modification time of `path` to `seconds` after the epoch
+    fn touch(path: &Path, seconds: u64) {
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    #[test]
+    fn includes_are_watched_once() {
+        let sources = write_sources("watch-list");
+        let watched = watch_list(&[], &sources.preprocess().unwrap());
+        let names: Vec<_> = watched
+            .iter()
+            .map(|(path, _)| path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, ["main.vert", "common.glsl", "main.frag"]);
+        assert!(!any_changed(&watched));
+    }
+
+    #[test]
+    fn touching_an_include_is_a_change() {
+        let sources = write_sources("touched-include");
+        let watched = watch_list(&[], &sources.preprocess().unwrap());
+        let include = sources.vert.with_file_name("common.glsl");
+        touch(&include, 2000);
+        assert!(any_changed(&watched));
+
+        // reading it again settles the change
+        let watched = watch_list(&[], &sources.preprocess().unwrap());
+        assert!(!any_changed(&watched));
+
+        fs::remove_file(&include).unwrap();
+        assert!(any_changed(&watched));
+    }
+
+    #[test]
+    fn saves_during_a_reload_are_still_changes() {
+        let sources = write_sources("save-during-reload");
+        let before: Vec<_> = sources
+            .paths()
+            .map(|path| (path.to_path_buf(), modified(path)))
+            .collect();
+        let stages = sources.preprocess().unwrap();
+        // saved after the times were noted but before the reload finished
+        touch(&sources.frag, 2000);
+        let watched = watch_list(&before, &stages);
+        assert!(any_changed(&watched));
+        let frag = watched.iter().find(|(path, _)| *path == sources.frag);
+        assert_eq!(
+            frag.unwrap().1,
+            Some(UNIX_EPOCH + Duration::from_secs(1000))
+        );
+    }
+}
@@ -1,13 +1,37 @@
 extern crate gl;
+// lets `#[derive(VertexLayout)]` and `#[derive(ShaderBlock)]` name `::magiebleue` from inside this crate too
+extern crate self as magiebleue;
 
+pub mod debug;
+pub mod error;
+pub mod framebuffers;
 pub mod functions;
 pub mod gl_objects;
+pub mod gltf_loader;
+pub mod golden;
+pub mod hot_reload;
+pub mod materials;
+pub mod mesh;
+pub mod postprocess;
+pub mod reflection;
+pub mod resources;
+pub mod screenshot;
 pub mod shaders;
 pub mod textures;
+pub mod uniform_blocks;
+pub mod uniforms;
 pub mod wavefront_parser;
 
+use framebuffers::Framebuffer;
 use glfw::{Action, Context, CursorMode, WindowEvent};
-use std::collections::HashSet;
+use screenshot::{DepthImage, RgbaImage};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use textures::TextureFormat;
+use ultraviolet::IVec2;
 
 pub struct WindowContext {
     pub size: ultraviolet::IVec2,
@@ -15,43 +39,213 @@ pub struct WindowContext {
     pub window_mode: glfw::WindowMode<'static>,
 }
 
+/// Ways to create a GL context without a visible window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeadlessBackend {
+    /// An invisible window on the native platform. Still needs a running display server
+    HiddenWindow,
+    /// A surfaceless EGL context on GLFW's null platform, e.g. Mesa's llvmpipe
+    Egl,
+    /// An OSMesa context on GLFW's null platform. Needs neither a display nor a GPU
+    OsMesa,
+}
+
+/// Colour & depth renderbuffers that headless applications draw into instead of the default framebuffer.
+/// Creates and binds a complete RGBA8 + depth24/stencil8 framebuffer of the given size
+fn offscreen_target(size: IVec2) -> error::Result<Framebuffer> {
+    let mut target = Framebuffer::new(size)?;
+    target.attach_color_renderbuffer(TextureFormat::Rgba8)?;
+    target.attach_depth_renderbuffer(TextureFormat::Depth24Stencil8)?;
+    target.set_label("offscreen target")?;
+    target.check()?;
+    target.bind();
+    Ok(target)
+}
+
+/// Initialises GLFW, printing its errors rather than panicking on them
+fn init_glfw() -> error::Result<glfw::Glfw> {
+    use glfw::log_errors;
+    glfw::init(log_errors!()).map_err(|e| error::Error::Window(e.to_string()))
+}
+
+/// Owns the window and its GL context.
+/// GL objects delete themselves when dropped, which needs the context to still be current, so drop them before the application
 pub struct Application {
+    // dropped first, while the context is still alive
+    offscreen: Option<Framebuffer>,
     pub glfw: glfw::Glfw,
     pub window: glfw::PWindow,
     events: glfw::GlfwReceiver<(f64, WindowEvent)>,
     keys_pressed: HashSet<glfw::Key>,
+    screenshot_hotkey: Option<(glfw::Key, PathBuf)>,
+    screenshot_requested: bool,
 }
 
 impl Application {
     /// Starts the Magiebleue application. Takes context from `context`.
-    pub fn start(ctx: WindowContext) -> Self {
-        use glfw::fail_on_errors;
-        let mut glfw = glfw::init(fail_on_errors!()).unwrap();
+    pub fn start(ctx: WindowContext) -> error::Result<Self> {
+        let glfw = init_glfw()?;
+
+        let mut app = Self::create(glfw, &ctx)?;
 
+        app.window.set_cursor_mode(CursorMode::Disabled);
+        app.window
+            .set_cursor_pos(ctx.size.x as f64 / 2.0, ctx.size.y as f64 / 2.0);
+        app.glfw.set_swap_interval(glfw::SwapInterval::Sync(1));
+
+        Ok(app)
+    }
+
+    /// Starts the Magiebleue application without a visible window, for rendering in CI or on render servers.
+    /// Everything is drawn into an offscreen framebuffer of `ctx.size`, which stays bound and can be read with `Application::read_pixels`.
+    /// Fails if the backend isn't available, e.g. without EGL or OSMesa on CI machines, so callers can skip
+    pub fn start_headless(ctx: WindowContext, backend: HeadlessBackend) -> error::Result<Self> {
+        if backend != HeadlessBackend::HiddenWindow {
+            glfw::init_hint(glfw::InitHint::Platform(glfw::Platform::Null));
+        }
+        let mut glfw = init_glfw()?;
+
+        glfw.window_hint(glfw::WindowHint::Visible(false));
+        // software rasterizers hand out legacy contexts unless asked, which lack tessellation
+        glfw.window_hint(glfw::WindowHint::ContextVersion(4, 1));
+        glfw.window_hint(glfw::WindowHint::OpenGlProfile(
+            glfw::OpenGlProfileHint::Core,
+        ));
+        match backend {
+            HeadlessBackend::HiddenWindow => {}
+            HeadlessBackend::Egl => glfw.window_hint(glfw::WindowHint::ContextCreationApi(
+                glfw::ContextCreationApi::Egl,
+            )),
+            HeadlessBackend::OsMesa => glfw.window_hint(glfw::WindowHint::ContextCreationApi(
+                glfw::ContextCreationApi::OsMesa,
+            )),
+        }
+
+        let mut app = Self::create(glfw, &ctx)?;
+        app.offscreen = Some(offscreen_target(ctx.size)?);
+
+        Ok(app)
+    }
+
+    /// Creates the window & context and loads GL functions from it.
+    /// Debug builds ask for a debug context and print its warnings and errors, see `debug::set_logger` to handle them otherwise
+    fn create(mut glfw: glfw::Glfw, ctx: &WindowContext) -> error::Result<Self> {
+        glfw.window_hint(glfw::WindowHint::OpenGlDebugContext(cfg!(debug_assertions)));
+        let (Ok(width), Ok(height)) = (ctx.size.x.try_into(), ctx.size.y.try_into()) else {
+            return Err(error::Error::InvalidParameter(format!(
+                "window size must be positive, got {}x{}",
+                ctx.size.x, ctx.size.y
+            )));
+        };
         let (mut window, events) = glfw
-            .create_window(
-                ctx.size.x.try_into().expect("window size must be positive"),
-                ctx.size.y.try_into().expect("window size must be positive"),
-                &ctx.window_title,
-                ctx.window_mode,
-            )
-            .expect("Failed to create window");
-
-        window.set_cursor_mode(CursorMode::Disabled);
-        window.set_cursor_pos(1920.0 / 2.0, 1080.0 / 2.0);
+            .create_window(width, height, &ctx.window_title, ctx.window_mode)
+            .ok_or_else(|| error::Error::Window(glfw::get_error_string().1))?;
+
         window.make_current();
         window.set_key_polling(true);
-        glfw.set_swap_interval(glfw::SwapInterval::Sync(1));
 
-        gl::load_with(|s| window.get_proc_address(s).unwrap() as *const _);
+        gl::load_with(|s| {
+            window
+                .get_proc_address(s)
+                .map_or(std::ptr::null(), |f| f as *const _)
+        });
+        if cfg!(debug_assertions) && debug::is_debug_context() {
+            // without KHR_debug there are no messages to print
+            let _ = debug::set_logger(debug::PrintLogger::default());
+        }
 
         let keys_pressed = HashSet::<glfw::Key>::new();
 
-        Application {
+        Ok(Application {
             glfw,
             window,
             events,
             keys_pressed,
+            offscreen: None,
+            screenshot_hotkey: None,
+            screenshot_requested: false,
+        })
+    }
+
+    /// Whether this application renders offscreen rather than into a visible window
+    pub fn is_headless(&self) -> bool {
+        self.offscreen.is_some()
+    }
+
+    /// Binds the framebuffer the application presents from: the offscreen target when headless, otherwise the default framebuffer
+    pub fn bind_render_target(&self) {
+        match &self.offscreen {
+            Some(target) => target.bind(),
+            None => Framebuffer::clear_binding(),
+        }
+    }
+
+    /// Size in pixels of the framebuffer the application presents from
+    pub fn render_size(&self) -> IVec2 {
+        match &self.offscreen {
+            Some(target) => target.size(),
+            None => {
+                let (width, height) = self.window.get_framebuffer_size();
+                IVec2::new(width, height)
+            }
+        }
+    }
+
+    /// Reads back the colour of the application's render target
+    pub fn read_pixels(&self) -> RgbaImage {
+        let size = self.render_size();
+        self.bind_read_target();
+        screenshot::read_color(0, 0, size.x as u32, size.y as u32)
+    }
+
+    /// Reads back the depth of the application's render target
+    pub fn read_depth(&self) -> DepthImage {
+        let size = self.render_size();
+        self.bind_read_target();
+        screenshot::read_depth(0, 0, size.x as u32, size.y as u32)
+    }
+
+    /// Saves the colour of the application's render target as a PNG, or a PPM if `path` ends in `.ppm`
+    pub fn screenshot(&self, path: impl AsRef<Path>) -> error::Result<()> {
+        let path = path.as_ref();
+        if path.extension().is_some_and(|ext| ext == "ppm") {
+            self.read_pixels().save_ppm(path)
+        } else {
+            self.read_pixels().save_png(path)
+        }
+        .map_err(error::Error::io(path))
+    }
+
+    /// Saves a timestamped PNG screenshot into `dir` at the end of the frame whenever `key` is pressed
+    pub fn set_screenshot_hotkey(&mut self, key: glfw::Key, dir: impl Into<PathBuf>) {
+        self.screenshot_hotkey = Some((key, dir.into()));
+    }
+
+    /// Removes the screenshot hotkey, if any
+    pub fn clear_screenshot_hotkey(&mut self) {
+        self.screenshot_hotkey = None;
+    }
+
+    fn bind_read_target(&self) {
+        match &self.offscreen {
+            Some(target) => target.bind_read(),
+            None => unsafe { gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0) },
+        }
+    }
+
+    /// Saves the screenshot requested by the hotkey. Must run before the buffers are swapped
+    fn take_hotkey_screenshot(&mut self) {
+        let Some((_, dir)) = &self.screenshot_hotkey else {
+            return;
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("should not be in the future")
+            .as_millis();
+        let path = dir.join(format!("screenshot-{}.png", timestamp));
+        match self.screenshot(&path) {
+            Ok(()) => println!("Saved screenshot to {}", path.display()),
+            Err(e) => eprintln!("Screenshot error: {}", e),
         }
     }
 
@@ -62,8 +256,15 @@ impl Application {
     {
         r#loop(&mut self.window, &self.keys_pressed, self.glfw.get_time());
 
-        // swap buffer
-        self.window.swap_buffers();
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+            self.take_hotkey_screenshot();
+        }
+
+        // swap buffer. headless applications keep their frame in the offscreen target
+        if self.offscreen.is_none() {
+            self.window.swap_buffers();
+        }
 
         // events
         self.glfw.poll_events();
@@ -72,6 +273,13 @@ impl Application {
             if let WindowEvent::Key(key, _, action, _) = event {
                 match action {
                     Action::Press => {
+                        if self
+                            .screenshot_hotkey
+                            .as_ref()
+                            .is_some_and(|(hotkey, _)| *hotkey == key)
+                        {
+                            self.screenshot_requested = true;
+                        }
                         self.keys_pressed.insert(key);
                     }
                     Action::Release => {
@@ -83,3 +291,16 @@ impl Application {
         }
     }
 }
+
+/// Reports the GL objects still alive at shutdown, while the context can still size them.
+/// Only tells leaks apart once every other GL object is dropped, so declare the application before them.
+/// Call `resources::dump` directly to report at another point
+#[cfg(feature = "track-resources")]
+impl Drop for Application {
+    fn drop(&mut self) {
+        // the offscreen target is the application's own, not a leak
+        drop(self.offscreen.take());
+        eprintln!("At shutdown:");
+        resources::dump();
+    }
+}
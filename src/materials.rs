@@ -0,0 +1,552 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use gl::types::GLint;
+use ultraviolet::Vec3;
+
+use crate::{
+    error, reflection,
+    shaders::ShaderProgram,
+    textures::{self, TexWrapBehaviour, Texture, TextureType},
+    wavefront_parser::{ParseErrorKind, Statement, Statements, WavefrontError},
+};
+
+/// Texture unit the diffuse map is bound to by `Material::apply`
+pub const DIFFUSE_MAP_SLOT: u32 = 0;
+/// Texture unit the specular map is bound to by `Material::apply`
+pub const SPECULAR_MAP_SLOT: u32 = 1;
+/// Texture unit the bump map is bound to by `Material::apply`
+pub const BUMP_MAP_SLOT: u32 = 2;
+/// Texture unit the emissive map is bound to by `Material::apply`
+pub const EMISSIVE_MAP_SLOT: u32 = 3;
+
+/// A surface description from an MTL file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub name: String,
+    /// `Ka`
+    pub ambient: Vec3,
+    /// `Kd`
+    pub diffuse: Vec3,
+    /// `Ks`
+    pub specular: Vec3,
+    /// `Ke`
+    pub emissive: Vec3,
+    /// `Ns`, the specular exponent
+    pub shininess: f32,
+    /// `d`, or 1 - `Tr`. 1 is opaque
+    pub dissolve: f32,
+    /// `Ni`, the index of refraction
+    pub optical_density: f32,
+    /// `illum`, the illumination model
+    pub illumination: u32,
+    /// `map_Ka`
+    pub ambient_map: Option<TextureMap>,
+    /// `map_Kd`
+    pub diffuse_map: Option<TextureMap>,
+    /// `map_Ks`
+    pub specular_map: Option<TextureMap>,
+    /// `map_Ke`
+    pub emissive_map: Option<TextureMap>,
+    /// `map_Ns`
+    pub shininess_map: Option<TextureMap>,
+    /// `map_d`
+    pub dissolve_map: Option<TextureMap>,
+    /// `map_Bump` or `bump`
+    pub bump_map: Option<TextureMap>,
+    /// `norm`
+    pub normal_map: Option<TextureMap>,
+    /// `disp`
+    pub displacement_map: Option<TextureMap>,
+}
+impl Default for Material {
+    /// Matte white, the same as a face without `usemtl`
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            ambient: Vec3::zero(),
+            diffuse: Vec3::one(),
+            specular: Vec3::zero(),
+            emissive: Vec3::zero(),
+            shininess: 1.0,
+            dissolve: 1.0,
+            optical_density: 1.0,
+            illumination: 1,
+            ambient_map: None,
+            diffuse_map: None,
+            specular_map: None,
+            emissive_map: None,
+            shininess_map: None,
+            dissolve_map: None,
+            bump_map: None,
+            normal_map: None,
+            displacement_map: None,
+        }
+    }
+}
+
+/// A texture referenced by a material, with the options that affect how it is sampled
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureMap {
+    /// As written in the MTL file, usually relative to it
+    pub path: PathBuf,
+    /// `-o`, added to texture coordinates
+    pub offset: Vec3,
+    /// `-s`, multiplies texture coordinates
+    pub scale: Vec3,
+    /// `-clamp on`
+    pub clamp: bool,
+    /// `-bm`, bump strength
+    pub bump_multiplier: f32,
+}
+
+/// The GPU textures of a material's maps, loaded by `Material::load_textures`
+#[derive(Default)]
+pub struct MaterialTextures {
+    pub diffuse: Option<Texture>,
+    pub specular: Option<Texture>,
+    pub bump: Option<Texture>,
+    pub emissive: Option<Texture>,
+    /// Why the maps that are left out failed to load
+    pub errors: Vec<error::Error>,
+}
+
+impl Material {
+    /// Loads the diffuse, specular, bump and emissive maps relative to `base_dir`. Maps that fail to load are left out, with their errors kept.
+    /// Diffuse and emissive maps hold colours, so they are read as sRGB
+    pub fn load_textures(&self, base_dir: &Path) -> MaterialTextures {
+        let mut errors = Vec::new();
+        let mut load = |map: &Option<TextureMap>, srgb: bool| {
+            let map = map.as_ref()?;
+            let path = base_dir.join(&map.path);
+            let texture = if srgb {
+                Texture::from_file_srgb(&path)
+            } else {
+                Texture::from_file(&path)
+            };
+            match texture {
+                Ok(texture) => {
+                    if map.clamp {
+                        Texture::set_dual_wrap_behaviour(
+                            TextureType::Tex2d,
+                            TexWrapBehaviour::ClampToEdge,
+                        );
+                    }
+                    Some(texture)
+                }
+                Err(e) => {
+                    errors.push(e);
+                    None
+                }
+            }
+        };
+        let textures = MaterialTextures {
+            diffuse: load(&self.diffuse_map, true),
+            specular: load(&self.specular_map, false),
+            bump: load(&self.bump().cloned(), false),
+            emissive: load(&self.emissive_map, true),
+            errors: Vec::new(),
+        };
+        MaterialTextures { errors, ..textures }
+    }
+
+    /// The bump map, falling back to the normal map
+    pub fn bump(&self) -> Option<&TextureMap> {
+        self.bump_map.as_ref().or(self.normal_map.as_ref())
+    }
+
+    /// Checks that `program` declares every uniform `Material::apply` sets, with types it can set them from
+    pub fn check_program(program: &ShaderProgram) -> error::Result<()> {
+        let info = program.reflect();
+        let mut problems: Vec<String> = [
+            info.uniform_mismatch::<Vec3>("material.ambient"),
+            info.uniform_mismatch::<Vec3>("material.diffuse"),
+            info.uniform_mismatch::<Vec3>("material.specular"),
+            info.uniform_mismatch::<Vec3>("material.emissive"),
+            info.uniform_mismatch::<f32>("material.shininess"),
+            info.uniform_mismatch::<f32>("material.dissolve"),
+            info.uniform_mismatch::<GLint>("material.illumination"),
+            info.uniform_mismatch::<f32>("material.bump_multiplier"),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        for name in ["diffuse", "specular", "bump", "emissive"] {
+            problems.extend(info.uniform_mismatch::<GLint>(&format!("material.{}_map", name)));
+            problems.extend(info.uniform_mismatch::<bool>(&format!("material.has_{}_map", name)));
+            problems
+                .extend(info.uniform_mismatch::<Vec3>(&format!("material.{}_map_offset", name)));
+            problems.extend(info.uniform_mismatch::<Vec3>(&format!("material.{}_map_scale", name)));
+        }
+        reflection::problems_to_result(problems)
+    }
+
+    /// Sets this material's uniforms on the active `program` and binds its textures.
+    /// Uses the `material.` struct fields declared in `shaders/material.frag`
+    pub fn apply(&self, program: &ShaderProgram, textures: &MaterialTextures) {
+        program.set_uniform("material.ambient", self.ambient);
+        program.set_uniform("material.diffuse", self.diffuse);
+        program.set_uniform("material.specular", self.specular);
+        program.set_uniform("material.emissive", self.emissive);
+        program.set_uniform("material.shininess", self.shininess);
+        program.set_uniform("material.dissolve", self.dissolve);
+        program.set_uniform("material.illumination", self.illumination as GLint);
+
+        let maps = [
+            (
+                "diffuse",
+                DIFFUSE_MAP_SLOT,
+                &textures.diffuse,
+                &self.diffuse_map,
+            ),
+            (
+                "specular",
+                SPECULAR_MAP_SLOT,
+                &textures.specular,
+                &self.specular_map,
+            ),
+            ("bump", BUMP_MAP_SLOT, &textures.bump, &self.bump().cloned()),
+            (
+                "emissive",
+                EMISSIVE_MAP_SLOT,
+                &textures.emissive,
+                &self.emissive_map,
+            ),
+        ];
+        for (name, slot, texture, map) in maps {
+            program.set_uniform(&format!("material.{}_map", name), slot as GLint);
+            program.set_uniform(&format!("material.has_{}_map", name), texture.is_some());
+            if let Some(texture) = texture {
+                textures::set_texture_slot(slot);
+                texture.bind(TextureType::Tex2d);
+            }
+            if let Some(map) = map {
+                program.set_uniform(&format!("material.{}_map_offset", name), map.offset);
+                program.set_uniform(&format!("material.{}_map_scale", name), map.scale);
+            } else {
+                program.set_uniform(&format!("material.{}_map_offset", name), Vec3::zero());
+                program.set_uniform(&format!("material.{}_map_scale", name), Vec3::one());
+            }
+        }
+        program.set_uniform(
+            "material.bump_multiplier",
+            self.bump().map_or(1.0, |map| map.bump_multiplier),
+        );
+        textures::set_texture_slot(0);
+    }
+}
+
+/// The materials defined in one or more MTL files
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MaterialLibrary {
+    pub materials: Vec<Material>,
+    /// Files named by `mtllib` that don't exist, left out by `Wavefront::load_materials`
+    pub missing: Vec<PathBuf>,
+}
+impl MaterialLibrary {
+    /// Reads and parses an MTL file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, WavefrontError> {
+        Self::parse(&fs::read_to_string(path).map_err(WavefrontError::Io)?)
+    }
+
+    /// Parses MTL source. Statements this parser doesn't know are skipped
+    pub fn parse(src: &str) -> Result<Self, WavefrontError> {
+        let mut library = MaterialLibrary::default();
+        for statement in Statements::new(src) {
+            library.parse_statement(&statement)?;
+        }
+        Ok(library)
+    }
+
+    /// Finds a material by name
+    pub fn get(&self, name: &str) -> Option<&Material> {
+        self.materials.iter().find(|m| m.name == name)
+    }
+
+    /// Adds every material of `other`, replacing materials of the same name
+    pub fn merge(&mut self, other: MaterialLibrary) {
+        self.missing.extend(other.missing);
+        for material in other.materials {
+            match self.materials.iter_mut().find(|m| m.name == material.name) {
+                Some(existing) => *existing = material,
+                None => self.materials.push(material),
+            }
+        }
+    }
+
+    fn parse_statement(&mut self, statement: &Statement<'_>) -> Result<(), WavefrontError> {
+        if statement.keyword.text == "newmtl" {
+            statement.expect_count(1)?;
+            self.materials.push(Material {
+                name: statement.rest(0),
+                ..Default::default()
+            });
+            return Ok(());
+        }
+
+        let Some(material) = self.materials.last_mut() else {
+            return match statement.keyword.text {
+                "Ka" | "Kd" | "Ks" | "Ke" | "Ns" | "Ni" | "d" | "Tr" | "illum" => {
+                    Err(statement.keyword.error(ParseErrorKind::NoMaterial))
+                }
+                _ => Ok(()),
+            };
+        };
+
+        match statement.keyword.text {
+            "Ka" => material.ambient = parse_color(statement)?,
+            "Kd" => material.diffuse = parse_color(statement)?,
+            "Ks" => material.specular = parse_color(statement)?,
+            "Ke" => material.emissive = parse_color(statement)?,
+            "Ns" => material.shininess = statement.floats(1, 1)?[0],
+            "Ni" => material.optical_density = statement.floats(1, 1)?[0],
+            "d" => {
+                // `d -halo factor` only changes how the factor is applied
+                let skip = usize::from(statement.args.first().is_some_and(|a| a.text == "-halo"));
+                material.dissolve = statement.floats_from(skip, 1, 1)?[0];
+            }
+            "Tr" => material.dissolve = 1.0 - statement.floats(1, 1)?[0],
+            "illum" => {
+                statement.expect_count(1)?;
+                let arg = &statement.args[0];
+                material.illumination = arg
+                    .text
+                    .parse()
+                    .map_err(|_| arg.error(ParseErrorKind::InvalidNumber(arg.text.to_owned())))?;
+            }
+            "map_Ka" => material.ambient_map = Some(parse_texture_map(statement)?),
+            "map_Kd" => material.diffuse_map = Some(parse_texture_map(statement)?),
+            "map_Ks" => material.specular_map = Some(parse_texture_map(statement)?),
+            "map_Ke" => material.emissive_map = Some(parse_texture_map(statement)?),
+            "map_Ns" => material.shininess_map = Some(parse_texture_map(statement)?),
+            "map_d" => material.dissolve_map = Some(parse_texture_map(statement)?),
+            "map_Bump" | "map_bump" | "bump" => {
+                material.bump_map = Some(parse_texture_map(statement)?)
+            }
+            "norm" => material.normal_map = Some(parse_texture_map(statement)?),
+            "disp" => material.displacement_map = Some(parse_texture_map(statement)?),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Parses `r [g b]` or `xyz x [y z]`. A single value is used for all channels. Spectral curves are not supported and read as white
+fn parse_color(statement: &Statement<'_>) -> Result<Vec3, WavefrontError> {
+    let skip = match statement.args.first().map(|a| a.text) {
+        Some("spectral") => return Ok(Vec3::one()),
+        Some("xyz") => 1,
+        _ => 0,
+    };
+    let values = statement.floats_from(skip, 1, 3)?;
+    Ok(match values[..] {
+        [v] => Vec3::broadcast(v),
+        [r, g] => Vec3::new(r, g, g),
+        [r, g, b, ..] => Vec3::new(r, g, b),
+        [] => Vec3::one(),
+    })
+}
+
+/// Parses `[options] filename`
+fn parse_texture_map(statement: &Statement<'_>) -> Result<TextureMap, WavefrontError> {
+    let mut map = TextureMap {
+        path: PathBuf::new(),
+        offset: Vec3::zero(),
+        scale: Vec3::one(),
+        clamp: false,
+        bump_multiplier: 1.0,
+    };
+
+    let args = &statement.args;
+    let mut i = 0;
+    while let Some(option) = args.get(i).filter(|a| a.text.starts_with('-')) {
+        i += 1;
+        match option.text {
+            "-o" | "-s" | "-t" => {
+                let values = option_values(statement, i, 1, 3)?;
+                let default = if option.text == "-s" { 1.0 } else { 0.0 };
+                let v = Vec3::new(
+                    values[0],
+                    values.get(1).copied().unwrap_or(default),
+                    values.get(2).copied().unwrap_or(default),
+                );
+                match option.text {
+                    "-o" => map.offset = v,
+                    "-s" => map.scale = v,
+                    _ => {}
+                }
+                i += values.len();
+            }
+            "-bm" => {
+                map.bump_multiplier = option_values(statement, i, 1, 1)?[0];
+                i += 1;
+            }
+            "-mm" => i += option_values(statement, i, 2, 2)?.len(),
+            "-clamp" => {
+                map.clamp = args.get(i).is_some_and(|a| a.text == "on");
+                i += 1;
+            }
+            "-blendu" | "-blendv" | "-cc" | "-imfchan" | "-texres" | "-boost" | "-type" => i += 1,
+            _ => {
+                return Err(option.error(ParseErrorKind::UnknownOption(option.text.to_owned())));
+            }
+        }
+    }
+
+    if i >= args.len() {
+        return Err(statement.end.error(ParseErrorKind::TooFewValues {
+            min: i + 1,
+            found: args.len(),
+        }));
+    }
+    map.path = PathBuf::from(statement.rest(i));
+    Ok(map)
+}
+
+/// Reads the numeric values of a texture map option starting at argument `start`, stopping at the first non-number or after `max`
+fn option_values(
+    statement: &Statement<'_>,
+    start: usize,
+    min: usize,
+    max: usize,
+) -> Result<Vec<f32>, WavefrontError> {
+    let values: Vec<f32> = statement.args[start.min(statement.args.len())..]
+        .iter()
+        .take(max)
+        .map_while(|a| a.text.parse().ok())
+        .collect();
+    if values.len() < min {
+        let at = statement
+            .args
+            .get(start + values.len())
+            .unwrap_or(&statement.end);
+        return Err(match at.text {
+            "" => at.error(ParseErrorKind::TooFewValues {
+                min,
+                found: values.len(),
+            }),
+            text => at.error(ParseErrorKind::InvalidNumber(text.to_owned())),
+        });
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(src: &str) -> (usize, usize, ParseErrorKind) {
+        match MaterialLibrary::parse(src) {
+            Err(WavefrontError::Parse { line, column, kind }) => (line, column, kind),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_colors_and_factors() {
+        let library = MaterialLibrary::parse(
+            "newmtl red\nKd 1 0 0\nKs 0.5\nKe xyz 0.1 0.2 0.3\nNs 96\nd 0.75\n\
+             newmtl glass\nTr 0.25\nillum 4\n",
+        )
+        .unwrap();
+        let red = library.get("red").unwrap();
+        assert_eq!(red.diffuse, Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(red.specular, Vec3::broadcast(0.5));
+        assert_eq!(red.emissive, Vec3::new(0.1, 0.2, 0.3));
+        assert_eq!(red.shininess, 96.0);
+        assert_eq!(red.dissolve, 0.75);
+        assert_eq!(red.ambient, Material::default().ambient);
+
+        let glass = library.get("glass").unwrap();
+        assert_eq!(glass.dissolve, 0.75);
+        assert_eq!(glass.illumination, 4);
+        assert_eq!(glass.diffuse, Vec3::one());
+    }
+
+    #[test]
+    fn halo_dissolve_skips_the_flag() {
+        let library = MaterialLibrary::parse("newmtl m\nd -halo 0.5\n").unwrap();
+        assert_eq!(library.materials[0].dissolve, 0.5);
+    }
+
+    #[test]
+    fn parses_texture_maps_with_options() {
+        let library = MaterialLibrary::parse(
+            "newmtl m\n\
+             map_Kd -o 0.5 0.25 -s 2 -clamp on textures/brick wall.png\n\
+             map_Ke glow.png\n\
+             map_Bump -bm 0.3 -mm 0 1 -imfchan l bump.png\n\
+             norm normal.png\n",
+        )
+        .unwrap();
+        let material = &library.materials[0];
+
+        let diffuse = material.diffuse_map.as_ref().unwrap();
+        assert_eq!(diffuse.path, PathBuf::from("textures/brick wall.png"));
+        assert_eq!(diffuse.offset, Vec3::new(0.5, 0.25, 0.0));
+        assert_eq!(diffuse.scale, Vec3::new(2.0, 1.0, 1.0));
+        assert!(diffuse.clamp);
+        assert_eq!(diffuse.bump_multiplier, 1.0);
+
+        let emissive = material.emissive_map.as_ref().unwrap();
+        assert_eq!(emissive.path, PathBuf::from("glow.png"));
+        assert_eq!(emissive.scale, Vec3::one());
+        assert!(!emissive.clamp);
+
+        let bump = material.bump().unwrap();
+        assert_eq!(bump.path, PathBuf::from("bump.png"));
+        assert_eq!(bump.bump_multiplier, 0.3);
+        assert_eq!(
+            material.normal_map.as_ref().unwrap().path,
+            PathBuf::from("normal.png")
+        );
+    }
+
+    #[test]
+    fn texture_map_errors_point_at_their_token() {
+        assert_eq!(
+            parse_error("newmtl m\nmap_Kd -foo 1 a.png\n"),
+            (2, 8, ParseErrorKind::UnknownOption("-foo".to_owned()))
+        );
+        assert_eq!(
+            parse_error("newmtl m\nmap_Kd -bm x a.png\n"),
+            (2, 12, ParseErrorKind::InvalidNumber("x".to_owned()))
+        );
+        assert_eq!(
+            parse_error("newmtl m\nmap_Kd -s 2\n"),
+            (2, 12, ParseErrorKind::TooFewValues { min: 3, found: 2 })
+        );
+    }
+
+    #[test]
+    fn skips_unknown_statements() {
+        let library = MaterialLibrary::parse(
+            "# exported\nfoo 1 2\nnewmtl m\nPr 0.5\nmap_Pm metal.png\nKd 0.5\n",
+        )
+        .unwrap();
+        assert_eq!(library.materials.len(), 1);
+        assert_eq!(library.materials[0].diffuse, Vec3::broadcast(0.5));
+    }
+
+    #[test]
+    fn material_statements_need_newmtl() {
+        assert_eq!(
+            parse_error("Kd 1 1 1\n"),
+            (1, 1, ParseErrorKind::NoMaterial)
+        );
+        assert_eq!(
+            parse_error("newmtl m\nKd 1 x 1\n"),
+            (2, 6, ParseErrorKind::InvalidNumber("x".to_owned()))
+        );
+    }
+
+    #[test]
+    fn merge_replaces_materials_by_name() {
+        let mut library = MaterialLibrary::parse("newmtl a\nNs 1\nnewmtl b\nNs 2\n").unwrap();
+        library.merge(MaterialLibrary::parse("newmtl b\nNs 3\nnewmtl c\n").unwrap());
+        let shininess: Vec<_> = library.materials.iter().map(|m| m.shininess).collect();
+        assert_eq!(shininess, vec![1.0, 3.0, 1.0]);
+    }
+}
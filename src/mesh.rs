@@ -0,0 +1,479 @@
+use std::collections::HashMap;
+
+use ultraviolet::{Vec2, Vec3, Vec4};
+
+use crate::{
+    gl_objects::Indices,
+    wavefront_parser::{IndexedMesh, TangentVertex, TriIndex, Vertex},
+};
+
+/// How normals are shared between the triangles meeting at a position
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NormalMode {
+    /// Every triangle uses its own face normal, giving a faceted look
+    Flat,
+    /// Corners average the normals of the triangles around their position, weighted by the angle each triangle spans there.
+    /// Triangles whose normals differ by more than `crease_angle` (radians) are left out, keeping hard edges sharp
+    Smooth { crease_angle: f32 },
+}
+
+/// Triangles of a triangle list, three consecutive vertices each
+pub fn list_triangles(vertex_count: usize) -> Vec<TriIndex> {
+    (0..vertex_count / 3)
+        .map(|t| [t * 3, t * 3 + 1, t * 3 + 2])
+        .collect()
+}
+
+/// Triangles covering the quad patches made by `functions::gen_patches`, wound counter-clockwise around the patch normal
+pub fn patch_triangles(vertex_count: usize) -> Vec<TriIndex> {
+    (0..vertex_count / 4)
+        .flat_map(|p| {
+            let i = p * 4;
+            [[i, i + 2, i + 1], [i + 1, i + 2, i + 3]]
+        })
+        .collect()
+}
+
+/// Computes the normal of every corner of every triangle
+pub fn corner_normals(
+    vertices: &[Vertex],
+    triangles: &[TriIndex],
+    mode: NormalMode,
+) -> Vec<[Vec3; 3]> {
+    let positions: Vec<[Vec3; 3]> = triangles
+        .iter()
+        .map(|t| t.map(|i| position(&vertices[i])))
+        .collect();
+    let face_normals: Vec<Vec3> = positions
+        .iter()
+        .map(|p| normalized((p[1] - p[0]).cross(p[2] - p[0])))
+        .collect();
+
+    let crease_angle = match mode {
+        NormalMode::Flat => return face_normals.into_iter().map(|n| [n; 3]).collect(),
+        NormalMode::Smooth { crease_angle } => crease_angle,
+    };
+    // slack so that coplanar triangles still smooth with a crease angle of 0
+    let min_cos = crease_angle.cos() - 1e-5;
+    let angles: Vec<[f32; 3]> = positions.iter().map(|p| corner_angles(*p)).collect();
+
+    // corners meeting at a position, whatever their texture coordinates or old normals
+    let mut shared = HashMap::<[u32; 3], Vec<(usize, usize)>>::new();
+    for (t, p) in positions.iter().enumerate() {
+        for (c, corner) in p.iter().enumerate() {
+            shared
+                .entry(position_key(*corner))
+                .or_default()
+                .push((t, c));
+        }
+    }
+
+    positions
+        .iter()
+        .enumerate()
+        .map(|(t, p)| {
+            [0, 1, 2].map(|c| {
+                let sum = shared[&position_key(p[c])]
+                    .iter()
+                    .filter(|(other, _)| face_normals[t].dot(face_normals[*other]) >= min_cos)
+                    .fold(Vec3::zero(), |sum, (other, oc)| {
+                        sum + face_normals[*other] * angles[*other][*oc]
+                    });
+                if sum.mag_sq() > 0.0 {
+                    sum.normalized()
+                } else {
+                    face_normals[t]
+                }
+            })
+        })
+        .collect()
+}
+
+/// Overwrites the normals of the vertices used by `triangles`.
+/// A vertex shared by corners with different normals (across a crease, or any shared vertex with `NormalMode::Flat`) gets their average,
+/// use `split_normals` to give those corners vertices of their own
+pub fn compute_normals(vertices: &mut [Vertex], triangles: &[TriIndex], mode: NormalMode) {
+    let corners = corner_normals(vertices, triangles, mode);
+    let mut sums = vec![Vec3::zero(); vertices.len()];
+    for (triangle, normals) in triangles.iter().zip(&corners) {
+        for (i, normal) in triangle.iter().zip(normals) {
+            sums[*i] += *normal;
+        }
+    }
+    for (vertex, sum) in vertices.iter_mut().zip(sums) {
+        if sum.mag_sq() > 0.0 {
+            vertex[5..8].copy_from_slice(sum.normalized().as_slice());
+        }
+    }
+}
+
+/// Builds an indexed mesh where every corner has exactly its computed normal.
+/// Vertices are split where their corners disagree and corners that end up identical share a vertex again
+pub fn split_normals(vertices: &[Vertex], triangles: &[TriIndex], mode: NormalMode) -> IndexedMesh {
+    let corners = corner_normals(vertices, triangles, mode);
+    let mut welded = Vec::new();
+    let mut indices = Vec::new();
+    let mut seen = HashMap::<[u32; 8], u32>::new();
+
+    for (triangle, normals) in triangles.iter().zip(&corners) {
+        for (i, normal) in triangle.iter().zip(normals) {
+            let mut vertex = vertices[*i];
+            vertex[5..8].copy_from_slice(normal.as_slice());
+            let index = *seen.entry(vertex.map(f32::to_bits)).or_insert_with(|| {
+                welded.push(vertex);
+                (welded.len() - 1) as u32
+            });
+            indices.push(index);
+        }
+    }
+
+    IndexedMesh {
+        vertices: welded,
+        indices: Indices::narrowest(indices),
+        generated_triangles: 0,
+    }
+}
+
+/// Computes the tangent of every corner of every triangle following the MikkTSpace conventions, for normal mapping.
+/// `xyz` is the unit tangent along increasing U, orthogonal to the vertex normal, and `w` is the handedness (±1), so that
+/// the bitangent is `w * cross(normal, tangent.xyz)` (see `bitangent`). Normals must be set first.
+///
+/// Like MikkTSpace, corners with identical vertex data are smoothed together, weighted by corner angle, but only with corners
+/// of triangles whose texture space has the same orientation. Vertices on a UV mirror seam get one tangent per side,
+/// so normal maps baked against MikkTSpace tangents (Blender, Substance, xNormal) don't seam
+pub fn corner_tangents(vertices: &[Vertex], triangles: &[TriIndex]) -> Vec<[Vec4; 3]> {
+    // per triangle: the direction of increasing U and whether the texture mapping is mirrored
+    let frames: Vec<Option<(Vec3, f32)>> = triangles
+        .iter()
+        .map(|triangle| {
+            let p = triangle.map(|i| position(&vertices[i]));
+            let uv = triangle.map(|i| tex_coord(&vertices[i]));
+            let (e1, e2) = (p[1] - p[0], p[2] - p[0]);
+            let (d1, d2) = (uv[1] - uv[0], uv[2] - uv[0]);
+            let uv_area = d1.x * d2.y - d2.x * d1.y;
+            if uv_area == 0.0 {
+                // no texture mapping to follow
+                return None;
+            }
+            let handedness = uv_area.signum();
+            Some(((e1 * d2.y - e2 * d1.y) * handedness, handedness))
+        })
+        .collect();
+
+    let mut sums = HashMap::<([u32; 8], bool), Vec3>::new();
+    for (triangle, frame) in triangles.iter().zip(&frames) {
+        let Some((tangent, handedness)) = frame else {
+            continue;
+        };
+        let p = triangle.map(|i| position(&vertices[i]));
+        for (i, angle) in triangle.iter().zip(corner_angles(p)) {
+            let n = normal(&vertices[*i]);
+            let projected = *tangent - n * n.dot(*tangent);
+            if projected.mag_sq() > 0.0 {
+                *sums
+                    .entry(vertex_key(&vertices[*i], *handedness))
+                    .or_default() += projected.normalized() * angle;
+            }
+        }
+    }
+
+    triangles
+        .iter()
+        .zip(&frames)
+        .map(|(triangle, frame)| {
+            triangle.map(|i| {
+                let vertex = &vertices[i];
+                let n = normal(vertex);
+                // triangles without a texture mapping join whichever side the vertex has
+                let handedness = match frame {
+                    Some((_, handedness)) => *handedness,
+                    None if sums.contains_key(&vertex_key(vertex, 1.0)) => 1.0,
+                    None => -1.0,
+                };
+                let sum = sums
+                    .get(&vertex_key(vertex, handedness))
+                    .copied()
+                    .unwrap_or_default();
+                let tangent = sum - n * n.dot(sum);
+                let tangent = if tangent.mag_sq() > 1e-12 {
+                    tangent.normalized()
+                } else {
+                    perpendicular(n)
+                };
+                Vec4::new(tangent.x, tangent.y, tangent.z, handedness)
+            })
+        })
+        .collect()
+}
+
+/// Builds an indexed mesh where every corner has exactly its tangent from `corner_tangents`, and the tangent of each of its vertices.
+/// Vertices are split where their corners disagree, e.g. on UV mirror seams, and corners that end up identical share a vertex again
+pub fn split_tangents(vertices: &[Vertex], triangles: &[TriIndex]) -> (IndexedMesh, Vec<Vec4>) {
+    let corners = corner_tangents(vertices, triangles);
+    let mut welded = Vec::new();
+    let mut tangents = Vec::new();
+    let mut indices = Vec::new();
+    let mut seen = HashMap::<([u32; 8], [u32; 4]), u32>::new();
+
+    for (triangle, corner_tangents) in triangles.iter().zip(&corners) {
+        for (i, tangent) in triangle.iter().zip(corner_tangents) {
+            let key = (
+                vertices[*i].map(f32::to_bits),
+                [tangent.x, tangent.y, tangent.z, tangent.w].map(f32::to_bits),
+            );
+            let index = *seen.entry(key).or_insert_with(|| {
+                welded.push(vertices[*i]);
+                tangents.push(*tangent);
+                (welded.len() - 1) as u32
+            });
+            indices.push(index);
+        }
+    }
+
+    let mesh = IndexedMesh {
+        vertices: welded,
+        indices: Indices::narrowest(indices),
+        generated_triangles: 0,
+    };
+    (mesh, tangents)
+}
+
+/// Reconstructs the bitangent from a normal and a tangent made by `corner_tangents`
+pub fn bitangent(normal: Vec3, tangent: Vec4) -> Vec3 {
+    normal.cross(tangent.xyz()) * tangent.w
+}
+
+/// Appends each vertex's tangent to it (pos-tex-normal-tangent)
+pub fn interleave_tangents(vertices: &[Vertex], tangents: &[Vec4]) -> Vec<TangentVertex> {
+    vertices
+        .iter()
+        .zip(tangents)
+        .map(|(v, t)| {
+            [
+                v[0], v[1], v[2], v[3], v[4], v[5], v[6], v[7], t.x, t.y, t.z, t.w,
+            ]
+        })
+        .collect()
+}
+
+fn position(v: &Vertex) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+fn tex_coord(v: &Vertex) -> Vec2 {
+    Vec2::new(v[3], v[4])
+}
+
+fn normal(v: &Vertex) -> Vec3 {
+    Vec3::new(v[5], v[6], v[7])
+}
+
+/// Corners share a tangent when their vertices are identical and their triangles have the same texture space handedness
+fn vertex_key(v: &Vertex, handedness: f32) -> ([u32; 8], bool) {
+    (v.map(f32::to_bits), handedness > 0.0)
+}
+
+/// Positions are matched exactly, with -0 and 0 treated as the same
+fn position_key(p: Vec3) -> [u32; 3] {
+    [p.x, p.y, p.z].map(|c| (c + 0.0).to_bits())
+}
+
+fn normalized(v: Vec3) -> Vec3 {
+    if v.mag_sq() > 0.0 {
+        v.normalized()
+    } else {
+        Vec3::zero()
+    }
+}
+
+/// Interior angle of a triangle at each corner
+fn corner_angles(p: [Vec3; 3]) -> [f32; 3] {
+    [0, 1, 2].map(|i| {
+        let a = p[(i + 1) % 3] - p[i];
+        let b = p[(i + 2) % 3] - p[i];
+        if a.mag_sq() == 0.0 || b.mag_sq() == 0.0 {
+            0.0
+        } else {
+            a.normalized().dot(b.normalized()).clamp(-1.0, 1.0).acos()
+        }
+    })
+}
+
+/// Any unit vector perpendicular to `n`, for vertices without a usable texture mapping
+fn perpendicular(n: Vec3) -> Vec3 {
+    let axis = if n.x.abs() < 0.9 {
+        Vec3::unit_x()
+    } else {
+        Vec3::unit_y()
+    };
+    let t = axis - n * n.dot(axis);
+    if t.mag_sq() > 0.0 {
+        t.normalized()
+    } else {
+        Vec3::unit_x()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(p: [f32; 3], uv: [f32; 2], n: [f32; 3]) -> Vertex {
+        [p[0], p[1], p[2], uv[0], uv[1], n[0], n[1], n[2]]
+    }
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!(
+            (actual - expected).mag() < 1e-5,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    /// A right angle in the xy plane and a 45° angle in the xz plane, meeting at the origin
+    fn fold() -> (Vec<Vertex>, Vec<TriIndex>) {
+        let vertices = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [1.0, 0.0, 1.0],
+        ]
+        .map(|p| vertex(p, [0.0; 2], [0.0; 3]));
+        (vertices.to_vec(), list_triangles(6))
+    }
+
+    #[test]
+    fn flat_normals_are_face_normals() {
+        let (vertices, triangles) = fold();
+        let normals = corner_normals(&vertices, &triangles, NormalMode::Flat);
+        assert_eq!(normals, vec![[Vec3::unit_z(); 3], [Vec3::unit_y(); 3]]);
+    }
+
+    #[test]
+    fn smooth_normals_are_weighted_by_corner_angle() {
+        let (vertices, triangles) = fold();
+        let normals = corner_normals(
+            &vertices,
+            &triangles,
+            NormalMode::Smooth {
+                crease_angle: std::f32::consts::PI,
+            },
+        );
+        // the right angle counts twice as much as the 45° one
+        let origin = Vec3::new(0.0, 1.0, 2.0).normalized();
+        assert_close(normals[0][0], origin);
+        assert_close(normals[1][0], origin);
+        // corners no other triangle touches keep their face normal
+        assert_close(normals[0][1], Vec3::unit_z());
+        assert_close(normals[1][2], Vec3::unit_y());
+
+        let mesh = split_normals(
+            &vertices,
+            &triangles,
+            NormalMode::Smooth {
+                crease_angle: std::f32::consts::PI,
+            },
+        );
+        assert_eq!(mesh.vertices.len(), 5);
+    }
+
+    #[test]
+    fn creases_keep_face_normals() {
+        let (vertices, triangles) = fold();
+        let mode = NormalMode::Smooth {
+            crease_angle: 60_f32.to_radians(),
+        };
+        let normals = corner_normals(&vertices, &triangles, mode);
+        assert_close(normals[0][0], Vec3::unit_z());
+        assert_close(normals[1][0], Vec3::unit_y());
+        assert_eq!(split_normals(&vertices, &triangles, mode).vertices.len(), 6);
+    }
+
+    #[test]
+    fn coplanar_triangles_smooth_with_zero_crease_angle() {
+        let vertices = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+        ]
+        .map(|p| vertex(p, [0.0; 2], [0.0; 3]));
+        let mesh = split_normals(
+            &vertices,
+            &[[0, 1, 2], [0, 2, 3]],
+            NormalMode::Smooth { crease_angle: 0.0 },
+        );
+        assert_eq!(mesh.vertices.len(), 4);
+    }
+
+    /// Two quads side by side in the xy plane, facing +z, whose texture coordinates mirror at x = 0.
+    /// The vertices on the seam are shared
+    fn mirrored_quad() -> (Vec<Vertex>, Vec<TriIndex>) {
+        let n = [0.0, 0.0, 1.0];
+        let vertices = [-1.0_f32, 0.0, 1.0]
+            .into_iter()
+            .flat_map(|x| {
+                let u = 1.0 - x.abs();
+                [
+                    vertex([x, 0.0, 0.0], [u, 0.0], n),
+                    vertex([x, 1.0, 0.0], [u, 1.0], n),
+                ]
+            })
+            .collect();
+        // columns at x = -1, 0, 1 hold vertices (0, 1), (2, 3), (4, 5)
+        let triangles = vec![[0, 2, 3], [0, 3, 1], [2, 4, 5], [2, 5, 3]];
+        (vertices, triangles)
+    }
+
+    #[test]
+    fn tangents_follow_increasing_u() {
+        let (vertices, triangles) = mirrored_quad();
+        let corners = corner_tangents(&vertices, &triangles);
+        for (t, tangents) in corners.iter().enumerate() {
+            let (expected, handedness) = if t < 2 {
+                (Vec3::unit_x(), 1.0)
+            } else {
+                (-Vec3::unit_x(), -1.0)
+            };
+            for tangent in tangents {
+                assert_close(tangent.xyz(), expected);
+                assert_eq!(tangent.w, handedness);
+                // V increases along +y on both sides
+                assert_close(bitangent(Vec3::unit_z(), *tangent), Vec3::unit_y());
+            }
+        }
+    }
+
+    #[test]
+    fn mirror_seams_split_vertices() {
+        let (vertices, triangles) = mirrored_quad();
+        let (mesh, tangents) = split_tangents(&vertices, &triangles);
+        // each seam vertex once per side
+        assert_eq!(mesh.vertices.len(), 8);
+        assert_eq!(tangents.len(), 8);
+        for triangle in mesh.triangles() {
+            let handedness = triangle.map(|i| tangents[i].w);
+            assert!(handedness.iter().all(|w| *w == handedness[0]));
+        }
+
+        // without a mirror nothing is split
+        let (mesh, _) = split_tangents(&vertices, &triangles[..2]);
+        assert_eq!(mesh.vertices.len(), 4);
+    }
+
+    #[test]
+    fn tangents_without_texture_mapping_are_perpendicular() {
+        let (vertices, triangles) = mirrored_quad();
+        let untextured: Vec<Vertex> = vertices
+            .iter()
+            .map(|v| vertex([v[0], v[1], v[2]], [0.0; 2], [0.0, 0.0, 1.0]))
+            .collect();
+        for tangent in corner_tangents(&untextured, &triangles).iter().flatten() {
+            assert!(tangent.xyz().dot(Vec3::unit_z()).abs() < 1e-6);
+            assert!((tangent.xyz().mag() - 1.0).abs() < 1e-6);
+        }
+    }
+}
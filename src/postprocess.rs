@@ -0,0 +1,567 @@
+use std::path::Path;
+
+use gl::types::GLint;
+use ultraviolet::{IVec2, Vec2};
+
+use crate::{
+    Application,
+    error::{Error, Result},
+    framebuffers::Framebuffer,
+    gl_objects::VertexArray,
+    screenshot::RgbaImage,
+    shaders::ShaderProgram,
+    textures::{self, TexScaleOp, TexWrapBehaviour, Texture, TextureFormat, TextureType},
+};
+
+/// Vertex shader for fullscreen passes. Passes `vTexCoords` spanning 0-1 over the screen
+pub const FULLSCREEN_VERT: &str = include_str!("../shaders/post/fullscreen.vert");
+
+/// Draws a triangle covering the whole viewport, with no vertex buffer
+pub struct FullscreenTriangle(pub VertexArray);
+impl FullscreenTriangle {
+    pub fn new() -> Result<Self> {
+        Ok(Self(VertexArray::new()?))
+    }
+
+    /// Draws with the active program, which should use `FULLSCREEN_VERT` as its vertex shader
+    pub fn draw(&self) {
+        self.0.bind();
+        unsafe { gl::DrawArrays(gl::TRIANGLES, 0, 3) }
+        VertexArray::clear_bind();
+    }
+}
+
+/// A fragment shader run over every pixel of its output, sampling its input as `input_image` on texture unit 0
+pub struct FullscreenPass {
+    pub program: ShaderProgram,
+}
+
+impl FullscreenPass {
+    /// Compiles `frag` against `FULLSCREEN_VERT`
+    pub fn new(frag: &str) -> Result<Self> {
+        Ok(Self {
+            program: ShaderProgram::from_string(FULLSCREEN_VERT, None, None, None, frag)?,
+        })
+    }
+
+    /// Compiles the fragment shader at `path` against `FULLSCREEN_VERT`
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let frag = std::fs::read_to_string(&path).map_err(Error::io(&path))?;
+        Self::new(&frag)
+    }
+
+    /// Makes the program active and binds `inputs` to texture units 0 onward, setting the sampler uniform named alongside each.
+    /// Also sets `texel_size` to the size of one pixel of the output, in shaders that declare it to sample neighbours
+    pub fn bind(&self, inputs: &[(&str, &Texture)], size: IVec2) {
+        self.program.use_program();
+        for (slot, (name, texture)) in inputs.iter().enumerate() {
+            textures::set_texture_slot(slot as u32);
+            texture.bind(TextureType::Tex2d);
+            self.program.set_uniform(name, slot as GLint);
+        }
+        textures::set_texture_slot(0);
+        if self.program.has_uniform("texel_size") {
+            let texel = Vec2::one() / Vec2::new(size.x as f32, size.y as f32);
+            self.program.set_uniform("texel_size", texel);
+        }
+    }
+}
+
+/// Runs the pass with no uniforms but its input
+impl PostEffect for FullscreenPass {
+    fn render(
+        &mut self,
+        input: &Texture,
+        size: IVec2,
+        triangle: &FullscreenTriangle,
+    ) -> Result<()> {
+        self.bind(&[("input_image", input)], size);
+        triangle.draw();
+        Ok(())
+    }
+}
+
+/// A step of the post-processing stack
+pub trait PostEffect {
+    /// Draws the effect of `input` into the bound framebuffer, whose viewport is already set to `size`.
+    /// Effects that render into their own framebuffers first must rebind the output, e.g. with a `FramebufferBinding`
+    fn render(&mut self, input: &Texture, size: IVec2, triangle: &FullscreenTriangle)
+    -> Result<()>;
+
+    /// Called with the size of the stack's targets when the effect is added and whenever they're resized
+    fn resize(&mut self, size: IVec2) -> Result<()> {
+        let _ = size;
+        Ok(())
+    }
+}
+
+/// Curves that map HDR colour into the 0-1 range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    /// `c / (c + 1)`. Never clips, but washes out bright colours
+    Reinhard = 0,
+    /// The filmic curve of the Academy Color Encoding System
+    Aces = 1,
+}
+
+/// Maps the HDR scene into displayable colours
+pub struct ToneMapping {
+    pub pass: FullscreenPass,
+    /// Multiplies the colour before mapping
+    pub exposure: f32,
+    pub operator: ToneMapOperator,
+}
+
+impl ToneMapping {
+    pub fn new(operator: ToneMapOperator) -> Result<Self> {
+        Ok(Self {
+            pass: FullscreenPass::new(include_str!("../shaders/post/tonemap.frag"))?,
+            exposure: 1.0,
+            operator,
+        })
+    }
+}
+
+impl PostEffect for ToneMapping {
+    fn render(
+        &mut self,
+        input: &Texture,
+        size: IVec2,
+        triangle: &FullscreenTriangle,
+    ) -> Result<()> {
+        self.pass.bind(&[("input_image", input)], size);
+        self.pass.program.set_uniform("exposure", self.exposure);
+        self.pass
+            .program
+            .set_uniform("operator", self.operator as GLint);
+        triangle.draw();
+        Ok(())
+    }
+}
+
+/// Encodes linear colour for display. Not needed when the output is an sRGB framebuffer
+pub struct GammaCorrection {
+    pub pass: FullscreenPass,
+    pub gamma: f32,
+}
+
+impl GammaCorrection {
+    /// Corrects for a gamma of 2.2
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            pass: FullscreenPass::new(include_str!("../shaders/post/gamma.frag"))?,
+            gamma: 2.2,
+        })
+    }
+}
+
+impl PostEffect for GammaCorrection {
+    fn render(
+        &mut self,
+        input: &Texture,
+        size: IVec2,
+        triangle: &FullscreenTriangle,
+    ) -> Result<()> {
+        self.pass.bind(&[("input_image", input)], size);
+        self.pass.program.set_uniform("gamma", self.gamma);
+        triangle.draw();
+        Ok(())
+    }
+}
+
+/// Fast approximate anti-aliasing. Works on displayable colour, so goes after tone mapping
+pub struct Fxaa {
+    pub pass: FullscreenPass,
+}
+
+impl Fxaa {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            pass: FullscreenPass::new(include_str!("../shaders/post/fxaa.frag"))?,
+        })
+    }
+}
+
+impl PostEffect for Fxaa {
+    fn render(
+        &mut self,
+        input: &Texture,
+        size: IVec2,
+        triangle: &FullscreenTriangle,
+    ) -> Result<()> {
+        self.pass.render(input, size, triangle)
+    }
+}
+
+/// Makes bright areas glow: extracts colours above `threshold`, blurs them at half resolution and adds them back.
+/// Works on HDR colour, so goes before tone mapping
+pub struct Bloom {
+    /// Brightness above which colour starts to glow
+    pub threshold: f32,
+    /// How strongly the glow is added back
+    pub intensity: f32,
+    /// Horizontal + vertical blur rounds. More spreads the glow further
+    pub blur_passes: u32,
+    extract: FullscreenPass,
+    blur: FullscreenPass,
+    composite: FullscreenPass,
+    targets: Vec<Framebuffer>,
+}
+
+impl Bloom {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            threshold: 1.0,
+            intensity: 0.5,
+            blur_passes: 4,
+            extract: FullscreenPass::new(include_str!("../shaders/post/bloom_extract.frag"))?,
+            blur: FullscreenPass::new(include_str!("../shaders/post/blur.frag"))?,
+            composite: FullscreenPass::new(include_str!("../shaders/post/bloom_composite.frag"))?,
+            targets: Vec::new(),
+        })
+    }
+}
+
+impl PostEffect for Bloom {
+    fn render(
+        &mut self,
+        input: &Texture,
+        size: IVec2,
+        triangle: &FullscreenTriangle,
+    ) -> Result<()> {
+        let [a, b] = &self.targets[..] else {
+            return Err(Error::InvalidParameter(
+                "Bloom must be resized before rendering".to_owned(),
+            ));
+        };
+        let glow = a
+            .color_texture(0)
+            .expect("bloom targets have a colour texture");
+        {
+            let _output = a.bind_scoped();
+            self.extract.bind(&[("input_image", input)], a.size());
+            self.extract
+                .program
+                .set_uniform("threshold", self.threshold);
+            triangle.draw();
+
+            let blurred = b
+                .color_texture(0)
+                .expect("bloom targets have a colour texture");
+            for _ in 0..self.blur_passes {
+                for (target, source, direction) in
+                    [(b, glow, Vec2::unit_x()), (a, blurred, Vec2::unit_y())]
+                {
+                    target.bind();
+                    self.blur.bind(&[("input_image", source)], target.size());
+                    self.blur.program.set_uniform("direction", direction);
+                    triangle.draw();
+                }
+            }
+        }
+        self.composite
+            .bind(&[("input_image", input), ("bloom_image", glow)], size);
+        self.composite
+            .program
+            .set_uniform("intensity", self.intensity);
+        triangle.draw();
+        Ok(())
+    }
+
+    fn resize(&mut self, size: IVec2) -> Result<()> {
+        let half = IVec2::new((size.x / 2).max(1), (size.y / 2).max(1));
+        if self.targets.is_empty() {
+            for _ in 0..2 {
+                let mut target = Framebuffer::new(half)?;
+                target.attach_color(TextureFormat::Rgba16F)?;
+                target.check()?;
+                self.targets.push(target);
+            }
+            Ok(())
+        } else {
+            self.targets.iter_mut().try_for_each(|t| t.resize(half))
+        }
+    }
+}
+
+/// Darkens the edges of the image
+pub struct Vignette {
+    pub pass: FullscreenPass,
+    /// How dark the corners get, 0 to 1
+    pub strength: f32,
+    /// Distance from the centre where darkening starts, in texture coordinates
+    pub radius: f32,
+    /// Distance over which the darkening fades in
+    pub softness: f32,
+}
+
+impl Vignette {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            pass: FullscreenPass::new(include_str!("../shaders/post/vignette.frag"))?,
+            strength: 0.5,
+            radius: 0.4,
+            softness: 0.4,
+        })
+    }
+}
+
+impl PostEffect for Vignette {
+    fn render(
+        &mut self,
+        input: &Texture,
+        size: IVec2,
+        triangle: &FullscreenTriangle,
+    ) -> Result<()> {
+        self.pass.bind(&[("input_image", input)], size);
+        self.pass.program.set_uniform("strength", self.strength);
+        self.pass.program.set_uniform("radius", self.radius);
+        self.pass.program.set_uniform("softness", self.softness);
+        triangle.draw();
+        Ok(())
+    }
+}
+
+/// Remaps colours through a lookup table, as exported by image editors.
+/// The LUT is a horizontal strip of `lut_size` square slices: red increases to the right within a slice, green downwards, and blue from slice to slice.
+/// Works on displayable colour, so goes after tone mapping
+pub struct ColorGrading {
+    pub pass: FullscreenPass,
+    pub lut: Texture,
+    /// Entries per channel, which is also the height of the LUT image
+    pub lut_size: u32,
+    /// 0 leaves the image untouched, 1 applies the LUT fully
+    pub amount: f32,
+}
+
+impl ColorGrading {
+    /// Uses `lut`, which must be `lut_size` * `lut_size` wide and `lut_size` tall
+    pub fn new(lut: &RgbaImage) -> Result<Self> {
+        if lut.width != lut.height * lut.height || lut.height < 2 {
+            return Err(Error::InvalidParameter(format!(
+                "a {}x{} image is not a colour grading LUT, which should be N*N x N",
+                lut.width, lut.height
+            )));
+        }
+        let texture = Texture::new()?;
+        texture.bind(TextureType::Tex2d);
+        Texture::fill_image(lut);
+        Texture::set_dual_wrap_behaviour(TextureType::Tex2d, TexWrapBehaviour::ClampToEdge);
+        Texture::set_dual_scale_behaviour(TextureType::Tex2d, TexScaleOp::Linear)?;
+        Ok(Self {
+            pass: FullscreenPass::new(include_str!("../shaders/post/color_grading.frag"))?,
+            lut: texture,
+            lut_size: lut.height,
+            amount: 1.0,
+        })
+    }
+
+    /// Loads the LUT from a PNG, BMP or netpbm image
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(&RgbaImage::load(&path).map_err(Error::io(&path))?)
+    }
+
+    /// A LUT that maps every colour to itself, as a starting point for grading in an image editor
+    pub fn identity_lut(size: u32) -> RgbaImage {
+        let mut lut = RgbaImage::new(size * size, size, [0, 0, 0, 255]);
+        let level = |i: u32| (i * 255 / (size - 1).max(1)) as u8;
+        for (i, pixel) in lut.pixels.iter_mut().enumerate() {
+            let (x, y) = (i as u32 % (size * size), i as u32 / (size * size));
+            *pixel = [level(x % size), level(y), level(x / size), 255];
+        }
+        lut
+    }
+}
+
+impl PostEffect for ColorGrading {
+    fn render(
+        &mut self,
+        input: &Texture,
+        size: IVec2,
+        triangle: &FullscreenTriangle,
+    ) -> Result<()> {
+        self.pass
+            .bind(&[("input_image", input), ("lut", &self.lut)], size);
+        self.pass
+            .program
+            .set_uniform("lut_size", self.lut_size as f32);
+        self.pass.program.set_uniform("amount", self.amount);
+        triangle.draw();
+        Ok(())
+    }
+}
+
+/// An ordered list of effects applied to the scene.
+/// The scene is drawn into an HDR colour + depth/stencil framebuffer, then each effect reads the previous one's output
+/// from one of two ping-pong targets. The last effect draws straight into the final output.
+/// Render the scene between `begin` and `finish`
+pub struct PostProcessStack {
+    pub effects: Vec<Box<dyn PostEffect>>,
+    /// What the scene is rendered into between `begin` and `finish`
+    pub scene: Framebuffer,
+    targets: [Framebuffer; 2],
+    copy: FullscreenPass,
+    triangle: FullscreenTriangle,
+}
+
+impl PostProcessStack {
+    /// Creates an empty stack whose targets are `size` pixels, storing colour as `format`
+    pub fn new(size: IVec2, format: TextureFormat) -> Result<Self> {
+        let target = || -> Result<Framebuffer> {
+            let mut target = Framebuffer::new(size)?;
+            target.attach_color(format)?;
+            Ok(target)
+        };
+        let mut scene = target()?;
+        scene.attach_depth_renderbuffer(TextureFormat::Depth24Stencil8)?;
+        scene.check()?;
+        scene.set_label("post-process scene")?;
+        let targets = [target()?, target()?];
+        for (i, target) in targets.iter().enumerate() {
+            target.check()?;
+            target.set_label(&format!("post-process target {}", i))?;
+        }
+        Ok(Self {
+            effects: Vec::new(),
+            scene,
+            targets,
+            copy: FullscreenPass::new(include_str!("../shaders/post/copy.frag"))?,
+            triangle: FullscreenTriangle::new()?,
+        })
+    }
+
+    /// The usual chain for an HDR scene: bloom, ACES tone mapping, FXAA, vignette and gamma correction, into `Rgba16F` targets
+    pub fn standard(size: IVec2) -> Result<Self> {
+        let mut stack = Self::new(size, TextureFormat::Rgba16F)?;
+        stack.push(Bloom::new()?)?;
+        stack.push(ToneMapping::new(ToneMapOperator::Aces)?)?;
+        stack.push(Fxaa::new()?)?;
+        stack.push(Vignette::new()?)?;
+        stack.push(GammaCorrection::new()?)?;
+        Ok(stack)
+    }
+
+    /// Adds an effect to the end of the stack
+    pub fn push(&mut self, mut effect: impl PostEffect + 'static) -> Result<()> {
+        effect.resize(self.scene.size())?;
+        self.effects.push(Box::new(effect));
+        Ok(())
+    }
+
+    /// Size of the targets in pixels
+    pub fn size(&self) -> IVec2 {
+        self.scene.size()
+    }
+
+    /// Resizes every target and effect. Does nothing if the size hasn't changed
+    pub fn resize(&mut self, size: IVec2) -> Result<()> {
+        if size == self.scene.size() {
+            return Ok(());
+        }
+        self.scene.resize(size)?;
+        for target in &mut self.targets {
+            target.resize(size)?;
+        }
+        for effect in &mut self.effects {
+            effect.resize(size)?;
+        }
+        Ok(())
+    }
+
+    /// Follows `size`, e.g. `Application::render_size`, then binds the scene framebuffer to draw into
+    pub fn begin(&mut self, size: IVec2) -> Result<()> {
+        self.resize(size)?;
+        self.scene.bind();
+        Ok(())
+    }
+
+    /// Runs the effects over the scene, drawing the result into the application's render target
+    pub fn finish(&mut self, app: &Application) -> Result<()> {
+        let size = app.render_size();
+        self.run(size, || {
+            app.bind_render_target();
+            unsafe { gl::Viewport(0, 0, size.x, size.y) }
+        })
+    }
+
+    /// Runs the effects over the scene, drawing the result into `output`
+    pub fn finish_into(&mut self, output: &Framebuffer) -> Result<()> {
+        self.run(output.size(), || output.bind())
+    }
+
+    /// The last effect draws at `output_size`, which needn't match the targets
+    fn run(&mut self, output_size: IVec2, bind_output: impl Fn()) -> Result<()> {
+        let depth_test = unsafe { gl::IsEnabled(gl::DEPTH_TEST) } == gl::TRUE;
+        unsafe { gl::Disable(gl::DEPTH_TEST) }
+
+        let size = self.scene.size();
+        let mut input = self
+            .scene
+            .color_texture(0)
+            .expect("scene has a colour texture");
+        let result = if self.effects.is_empty() {
+            bind_output();
+            self.copy.render(input, output_size, &self.triangle)
+        } else {
+            let last = self.effects.len() - 1;
+            self.effects
+                .iter_mut()
+                .enumerate()
+                .try_for_each(|(i, effect)| {
+                    let target = &self.targets[i % 2];
+                    if i == last {
+                        bind_output();
+                        effect.render(input, output_size, &self.triangle)?;
+                    } else {
+                        target.bind();
+                        effect.render(input, size, &self.triangle)?;
+                    }
+                    input = target
+                        .color_texture(0)
+                        .expect("targets have a colour texture");
+                    Ok(())
+                })
+        };
+
+        if depth_test {
+            unsafe { gl::Enable(gl::DEPTH_TEST) }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity_lut_spans_every_colour() {
+        let lut = ColorGrading::identity_lut(4);
+        assert_eq!((lut.width, lut.height), (16, 4));
+        // red within a slice, green downwards, blue across slices
+        assert_eq!(lut.get(0, 0), Some([0, 0, 0, 255]));
+        assert_eq!(lut.get(3, 0), Some([255, 0, 0, 255]));
+        assert_eq!(lut.get(0, 3), Some([0, 255, 0, 255]));
+        assert_eq!(lut.get(12, 0), Some([0, 0, 255, 255]));
+        assert_eq!(lut.get(15, 3), Some([255, 255, 255, 255]));
+        assert_eq!(lut.get(5, 2), Some([85, 170, 85, 255]));
+
+        let mut colours = lut.pixels.clone();
+        colours.sort();
+        colours.dedup();
+        assert_eq!(colours.len(), 4 * 4 * 4);
+    }
+
+    #[test]
+    fn luts_must_be_square_slices() {
+        for (width, height) in [(16, 3), (4, 4), (1, 1), (0, 0)] {
+            let lut = RgbaImage::new(width, height, [0, 0, 0, 255]);
+            assert!(
+                matches!(ColorGrading::new(&lut), Err(Error::InvalidParameter(_))),
+                "{}x{} was accepted",
+                width,
+                height
+            );
+        }
+    }
+}
@@ -0,0 +1,587 @@
+use std::fmt;
+
+use gl::types::{GLchar, GLenum, GLint, GLsizei, GLuint};
+
+use crate::{
+    error::{Error, Result},
+    gl_objects::{AttributeFormat, VertexAttribute},
+    uniforms::{self, UniformValue},
+};
+
+/// The interface of a linked program, as returned by `ShaderProgram::reflect`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgramInfo {
+    /// Vertex shader inputs, including built-ins such as `gl_VertexID`
+    pub attributes: Vec<AttributeInfo>,
+    /// Uniforms, both loose ones and members of uniform blocks
+    pub uniforms: Vec<UniformInfo>,
+    pub uniform_blocks: Vec<BlockInfo>,
+    /// Shader storage blocks. Always empty before GL 4.3
+    pub storage_blocks: Vec<BlockInfo>,
+}
+
+/// An active vertex shader input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeInfo {
+    pub name: String,
+    /// -1 for built-ins
+    pub location: GLint,
+    /// GL type of the input, e.g. `GL_FLOAT_VEC3`
+    pub ty: GLenum,
+    /// Number of array elements, 1 for non-arrays
+    pub array_size: usize,
+}
+
+/// An active uniform
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UniformInfo {
+    /// Arrays are named by their first element, e.g. `lights[0]`
+    pub name: String,
+    /// -1 for members of uniform blocks
+    pub location: GLint,
+    /// GL type of the uniform, e.g. `GL_FLOAT_VEC3` or `GL_SAMPLER_2D`
+    pub ty: GLenum,
+    /// Number of array elements, 1 for non-arrays
+    pub array_size: usize,
+    /// Index into `ProgramInfo::uniform_blocks` of the block this is a member of
+    pub block: Option<usize>,
+    /// Byte offset within the block
+    pub offset: Option<usize>,
+}
+
+impl UniformInfo {
+    /// Whether this is a sampler, set to the texture unit it reads from
+    pub fn is_sampler(&self) -> bool {
+        uniforms::glsl_type_name(self.ty).contains("sampler")
+    }
+}
+
+/// An active uniform block or shader storage block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockInfo {
+    pub name: String,
+    /// Binding point the block reads its buffer from
+    pub binding: GLuint,
+    /// Minimum size in bytes of the buffer bound to it. For storage blocks ending in an unsized array, the size without it
+    pub data_size: usize,
+    /// Names of the variables in the block
+    pub members: Vec<String>,
+}
+
+impl ProgramInfo {
+    /// Reads the interface of the linked `program`. Uses `glGetProgramResource*` where available, otherwise the
+    /// older `glGetActive*` calls, which can't see shader storage blocks
+    pub fn query(program: GLuint) -> Self {
+        if gl::GetProgramInterfaceiv::is_loaded() {
+            query_resources(program)
+        } else {
+            query_active(program)
+        }
+    }
+
+    /// Looks up a uniform by name. Array elements are matched by their array's name, with or without `[0]`
+    pub fn uniform(&self, name: &str) -> Option<&UniformInfo> {
+        self.uniforms
+            .iter()
+            .find(|u| u.name == name || u.name.strip_suffix("[0]") == Some(name))
+    }
+
+    /// Samplers among the uniforms
+    pub fn samplers(&self) -> impl Iterator<Item = &UniformInfo> {
+        self.uniforms.iter().filter(|u| u.is_sampler())
+    }
+
+    /// Describes why a uniform called `name` can't be set from a `T` through `ShaderProgram::set_uniform`, if it can't
+    pub fn uniform_mismatch<T: UniformValue>(&self, name: &str) -> Option<String> {
+        match self.uniform(name) {
+            None => Some(format!(
+                "uniform \"{}\" is not active, it may be misspelled or optimised out",
+                name
+            )),
+            Some(u) if u.block.is_some() => Some(format!(
+                "uniform \"{}\" is in a uniform block, set it through the block's buffer",
+                name
+            )),
+            Some(u) if !T::accepts(u.ty) => Some(format!(
+                "uniform \"{}\" is a {}, which can't be set from {}",
+                name,
+                uniforms::glsl_type_name(u.ty),
+                std::any::type_name::<T>()
+            )),
+            Some(_) => None,
+        }
+    }
+
+    /// Checks that `layout` feeds every vertex shader input, with floats going to float inputs and
+    /// `VertexAttribute::integer` attributes to int/uint ones. Inputs the layout has but the shader doesn't use are fine
+    pub fn check_attributes(&self, layout: &[VertexAttribute]) -> Result<()> {
+        let mut problems = Vec::new();
+        for input in self.attributes.iter().filter(|a| a.location >= 0) {
+            let type_name = uniforms::glsl_type_name(input.ty);
+            let integer = ["int", "ivec", "uint", "uvec"]
+                .iter()
+                .any(|prefix| type_name.starts_with(prefix));
+            for location in input.location as u32..input.location as u32 + locations(input) {
+                match layout.iter().find(|a| a.location == location) {
+                    None => problems.push(format!(
+                        "vertex input {} {} at location {} is not in the layout",
+                        type_name, input.name, location
+                    )),
+                    Some(attribute)
+                        if integer != (attribute.format == AttributeFormat::Integer) =>
+                    {
+                        problems.push(format!(
+                            "vertex input {} {} at location {} is fed {} as {:?}",
+                            type_name, input.name, location, attribute.name, attribute.format
+                        ))
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        problems_to_result(problems)
+    }
+}
+
+/// Fails with every problem found, if there were any
+pub fn problems_to_result(problems: Vec<String>) -> Result<()> {
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::ShaderInterface(problems))
+    }
+}
+
+/// Locations taken by an input: one per column for matrices, times the array size
+fn locations(input: &AttributeInfo) -> u32 {
+    let type_name = uniforms::glsl_type_name(input.ty);
+    let columns = type_name
+        .strip_prefix("mat")
+        .or_else(|| type_name.strip_prefix("dmat"))
+        .and_then(|dims| dims[..1].parse().ok())
+        .unwrap_or(1);
+    columns * input.array_size as u32
+}
+
+impl fmt::Display for ProgramInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // arrays are listed by their first element
+        let array = |name: &str, size: usize| match name.strip_suffix("[0]") {
+            Some(array) if size > 1 => format!("{}[{}]", array, size),
+            _ => name.to_owned(),
+        };
+        writeln!(f, "Attributes:")?;
+        for a in &self.attributes {
+            let type_name = uniforms::glsl_type_name(a.ty);
+            writeln!(
+                f,
+                "  {} {} @ {}",
+                type_name,
+                array(&a.name, a.array_size),
+                a.location
+            )?;
+        }
+        writeln!(f, "Uniforms:")?;
+        for u in self.uniforms.iter().filter(|u| u.block.is_none()) {
+            let type_name = uniforms::glsl_type_name(u.ty);
+            writeln!(
+                f,
+                "  {} {} @ {}",
+                type_name,
+                array(&u.name, u.array_size),
+                u.location
+            )?;
+        }
+        for (kind, blocks) in [
+            ("Uniform blocks", &self.uniform_blocks),
+            ("Storage blocks", &self.storage_blocks),
+        ] {
+            writeln!(f, "{}:", kind)?;
+            for b in blocks {
+                writeln!(
+                    f,
+                    "  {} ({} bytes) @ binding {}",
+                    b.name, b.data_size, b.binding
+                )?;
+                for m in &b.members {
+                    let offset = self.uniform(m).and_then(|u| u.offset);
+                    match offset {
+                        Some(offset) => writeln!(f, "    {} +{}", m, offset)?,
+                        None => writeln!(f, "    {}", m)?,
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads a name written by a `glGet*Name` call into a buffer of `max_len`
+fn read_name(max_len: GLint, get: impl FnOnce(GLsizei, *mut GLsizei, *mut GLchar)) -> String {
+    let mut name = vec![0u8; max_len.max(1) as usize];
+    let mut len = 0;
+    get(name.len() as GLsizei, &mut len, name.as_mut_ptr().cast());
+    name.truncate(len.max(0) as usize);
+    String::from_utf8_lossy(&name).into_owned()
+}
+
+/// A negative offset or block index means "none"
+fn non_negative(value: GLint) -> Option<usize> {
+    usize::try_from(value).ok()
+}
+
+fn query_resources(program: GLuint) -> ProgramInfo {
+    let count = |interface| {
+        let mut count = 0;
+        unsafe { gl::GetProgramInterfaceiv(program, interface, gl::ACTIVE_RESOURCES, &mut count) };
+        count.max(0) as GLuint
+    };
+    let properties = |interface, index, props: &[GLenum]| {
+        let mut values = vec![0; props.len()];
+        unsafe {
+            gl::GetProgramResourceiv(
+                program,
+                interface,
+                index,
+                props.len() as GLsizei,
+                props.as_ptr(),
+                values.len() as GLsizei,
+                std::ptr::null_mut(),
+                values.as_mut_ptr(),
+            )
+        };
+        values
+    };
+    let name = |interface, index| {
+        let len = properties(interface, index, &[gl::NAME_LENGTH])[0];
+        read_name(len, |size, len, buf| unsafe {
+            gl::GetProgramResourceName(program, interface, index, size, len, buf)
+        })
+    };
+    let blocks = |interface, member_interface| {
+        (0..count(interface))
+            .map(|index| {
+                let values = properties(
+                    interface,
+                    index,
+                    &[
+                        gl::BUFFER_BINDING,
+                        gl::BUFFER_DATA_SIZE,
+                        gl::NUM_ACTIVE_VARIABLES,
+                    ],
+                );
+                let mut members = vec![0; values[2].max(0) as usize];
+                if !members.is_empty() {
+                    let prop = gl::ACTIVE_VARIABLES;
+                    unsafe {
+                        gl::GetProgramResourceiv(
+                            program,
+                            interface,
+                            index,
+                            1,
+                            &prop,
+                            members.len() as GLsizei,
+                            std::ptr::null_mut(),
+                            members.as_mut_ptr(),
+                        )
+                    };
+                }
+                BlockInfo {
+                    name: name(interface, index),
+                    binding: values[0] as GLuint,
+                    data_size: values[1].max(0) as usize,
+                    members: members
+                        .into_iter()
+                        .map(|member| name(member_interface, member as GLuint))
+                        .collect(),
+                }
+            })
+            .collect()
+    };
+
+    let attributes = (0..count(gl::PROGRAM_INPUT))
+        .map(|index| {
+            let values = properties(
+                gl::PROGRAM_INPUT,
+                index,
+                &[gl::TYPE, gl::ARRAY_SIZE, gl::LOCATION],
+            );
+            AttributeInfo {
+                name: name(gl::PROGRAM_INPUT, index),
+                location: values[2],
+                ty: values[0] as GLenum,
+                array_size: values[1].max(1) as usize,
+            }
+        })
+        .collect();
+    let uniforms = (0..count(gl::UNIFORM))
+        .map(|index| {
+            let values = properties(
+                gl::UNIFORM,
+                index,
+                &[
+                    gl::TYPE,
+                    gl::ARRAY_SIZE,
+                    gl::LOCATION,
+                    gl::BLOCK_INDEX,
+                    gl::OFFSET,
+                ],
+            );
+            UniformInfo {
+                name: name(gl::UNIFORM, index),
+                location: values[2],
+                ty: values[0] as GLenum,
+                array_size: values[1].max(1) as usize,
+                block: non_negative(values[3]),
+                offset: non_negative(values[3]).and(non_negative(values[4])),
+            }
+        })
+        .collect();
+    let storage_blocks = if gl::ShaderStorageBlockBinding::is_loaded() {
+        blocks(gl::SHADER_STORAGE_BLOCK, gl::BUFFER_VARIABLE)
+    } else {
+        Vec::new()
+    };
+
+    ProgramInfo {
+        attributes,
+        uniforms,
+        uniform_blocks: blocks(gl::UNIFORM_BLOCK, gl::UNIFORM),
+        storage_blocks,
+    }
+}
+
+fn query_active(program: GLuint) -> ProgramInfo {
+    let get = |pname| {
+        let mut value = 0;
+        unsafe { gl::GetProgramiv(program, pname, &mut value) };
+        value
+    };
+
+    let max_len = get(gl::ACTIVE_ATTRIBUTE_MAX_LENGTH);
+    let attributes = (0..get(gl::ACTIVE_ATTRIBUTES) as GLuint)
+        .map(|index| {
+            let (mut size, mut ty) = (0, 0);
+            let name = read_name(max_len, |buf_size, len, buf| unsafe {
+                gl::GetActiveAttrib(program, index, buf_size, len, &mut size, &mut ty, buf)
+            });
+            let c_name = std::ffi::CString::new(name.as_str()).expect("GL names have no nul bytes");
+            AttributeInfo {
+                location: unsafe { gl::GetAttribLocation(program, c_name.as_ptr()) },
+                name,
+                ty,
+                array_size: size.max(1) as usize,
+            }
+        })
+        .collect();
+
+    let max_len = get(gl::ACTIVE_UNIFORM_MAX_LENGTH);
+    let uniforms = (0..get(gl::ACTIVE_UNIFORMS) as GLuint)
+        .map(|index| {
+            let (mut size, mut ty) = (0, 0);
+            let name = read_name(max_len, |buf_size, len, buf| unsafe {
+                gl::GetActiveUniform(program, index, buf_size, len, &mut size, &mut ty, buf)
+            });
+            let property = |pname| {
+                let mut value = 0;
+                unsafe { gl::GetActiveUniformsiv(program, 1, &index, pname, &mut value) };
+                value
+            };
+            let block = non_negative(property(gl::UNIFORM_BLOCK_INDEX));
+            let c_name = std::ffi::CString::new(name.as_str()).expect("GL names have no nul bytes");
+            UniformInfo {
+                location: unsafe { gl::GetUniformLocation(program, c_name.as_ptr()) },
+                name,
+                ty,
+                array_size: size.max(1) as usize,
+                block,
+                offset: block.and(non_negative(property(gl::UNIFORM_OFFSET))),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let max_len = get(gl::ACTIVE_UNIFORM_BLOCK_MAX_NAME_LENGTH);
+    let uniform_blocks = (0..get(gl::ACTIVE_UNIFORM_BLOCKS) as GLuint)
+        .map(|index| {
+            let property = |pname| {
+                let mut value = 0;
+                unsafe { gl::GetActiveUniformBlockiv(program, index, pname, &mut value) };
+                value
+            };
+            let mut members = vec![0; property(gl::UNIFORM_BLOCK_ACTIVE_UNIFORMS).max(0) as usize];
+            if !members.is_empty() {
+                unsafe {
+                    gl::GetActiveUniformBlockiv(
+                        program,
+                        index,
+                        gl::UNIFORM_BLOCK_ACTIVE_UNIFORM_INDICES,
+                        members.as_mut_ptr(),
+                    )
+                };
+            }
+            BlockInfo {
+                name: read_name(max_len, |buf_size, len, buf| unsafe {
+                    gl::GetActiveUniformBlockName(program, index, buf_size, len, buf)
+                }),
+                binding: property(gl::UNIFORM_BLOCK_BINDING) as GLuint,
+                data_size: property(gl::UNIFORM_BLOCK_DATA_SIZE).max(0) as usize,
+                members: members
+                    .into_iter()
+                    .filter_map(|member| uniforms.get(member as usize))
+                    .map(|u: &UniformInfo| u.name.clone())
+                    .collect(),
+            }
+        })
+        .collect();
+
+    ProgramInfo {
+        attributes,
+        uniforms,
+        uniform_blocks,
+        storage_blocks: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ultraviolet::{Mat4, Vec3, Vec4};
+
+    use crate::gl_objects::ComponentType;
+
+    use super::*;
+
+    fn uniform(name: &str, ty: GLenum, array_size: usize, block: Option<usize>) -> UniformInfo {
+        UniformInfo {
+            name: name.to_owned(),
+            location: if block.is_some() { -1 } else { 0 },
+            ty,
+            array_size,
+            block,
+            offset: block.map(|_| 0),
+        }
+    }
+
+    fn attribute(name: &str, location: GLint, ty: GLenum, array_size: usize) -> AttributeInfo {
+        AttributeInfo {
+            name: name.to_owned(),
+            location,
+            ty,
+            array_size,
+        }
+    }
+
+    fn program() -> ProgramInfo {
+        ProgramInfo {
+            attributes: vec![
+                attribute("gl_VertexID", -1, gl::INT, 1),
+                attribute("position", 0, gl::FLOAT_VEC3, 1),
+                attribute("material", 1, gl::UNSIGNED_INT, 1),
+                attribute("model", 2, gl::FLOAT_MAT4, 1),
+            ],
+            uniforms: vec![
+                uniform("tint", gl::FLOAT_VEC4, 1, None),
+                uniform("lights[0]", gl::FLOAT_VEC3, 4, None),
+                uniform("albedo", gl::SAMPLER_2D, 1, None),
+                uniform("shadows", gl::SAMPLER_2D_ARRAY_SHADOW, 1, None),
+                uniform("frame", gl::IMAGE_2D, 1, None),
+                uniform("view", gl::FLOAT_MAT4, 1, Some(0)),
+            ],
+            uniform_blocks: vec![BlockInfo {
+                name: "Camera".to_owned(),
+                binding: 0,
+                data_size: 64,
+                members: vec!["view".to_owned()],
+            }],
+            storage_blocks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn looks_up_arrays_by_their_name() {
+        let program = program();
+        assert_eq!(program.uniform("lights").unwrap().array_size, 4);
+        assert_eq!(program.uniform("lights[0]").unwrap().ty, gl::FLOAT_VEC3);
+        assert!(program.uniform("light").is_none());
+    }
+
+    #[test]
+    fn samplers_exclude_images() {
+        let names: Vec<_> = program().samplers().map(|u| u.name.clone()).collect();
+        assert_eq!(names, ["albedo", "shadows"]);
+    }
+
+    #[test]
+    fn reports_uniform_mismatches() {
+        let program = program();
+        assert_eq!(program.uniform_mismatch::<Vec4>("tint"), None);
+        assert_eq!(program.uniform_mismatch::<Vec3>("lights"), None);
+        assert_eq!(program.uniform_mismatch::<i32>("albedo"), None);
+        assert!(
+            program
+                .uniform_mismatch::<Vec3>("tint")
+                .unwrap()
+                .starts_with("uniform \"tint\" is a vec4, which can't be set from ")
+        );
+        assert!(
+            program
+                .uniform_mismatch::<u32>("albedo")
+                .unwrap()
+                .contains("is a sampler2D")
+        );
+        assert!(
+            program
+                .uniform_mismatch::<Mat4>("view")
+                .unwrap()
+                .contains("uniform block")
+        );
+        assert!(
+            program
+                .uniform_mismatch::<f32>("missing")
+                .unwrap()
+                .contains("not active")
+        );
+    }
+
+    #[test]
+    fn matrices_take_a_location_per_column() {
+        assert_eq!(locations(&attribute("m", 0, gl::FLOAT_MAT4, 1)), 4);
+        assert_eq!(locations(&attribute("m", 0, gl::FLOAT_MAT3x2, 2)), 6);
+        assert_eq!(locations(&attribute("m", 0, gl::DOUBLE_MAT2, 1)), 2);
+        assert_eq!(locations(&attribute("v", 0, gl::FLOAT_VEC4, 3)), 3);
+    }
+
+    #[test]
+    fn checks_attributes_against_inputs() {
+        let position = VertexAttribute::new("position", 0, 3, ComponentType::F32, 0);
+        let material = VertexAttribute::new("material", 1, 1, ComponentType::U32, 12).integer();
+        let model = VertexAttribute::new("model", 2, 4, ComponentType::F32, 16).columns(4, 16);
+        let layout: Vec<_> = [position, material].into_iter().chain(model).collect();
+        program().check_attributes(&layout).unwrap();
+
+        // the last matrix column is missing and the integer input is fed floats
+        let float_material = VertexAttribute::new("material", 1, 1, ComponentType::F32, 12);
+        let broken = [position, float_material]
+            .into_iter()
+            .chain(layout[2..5].iter().copied())
+            .collect::<Vec<_>>();
+        match program().check_attributes(&broken) {
+            Err(Error::ShaderInterface(problems)) => assert_eq!(
+                problems,
+                [
+                    "vertex input uint material at location 1 is fed material as Float",
+                    "vertex input mat4 model at location 5 is not in the layout",
+                ]
+            ),
+            other => panic!("expected interface problems, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn lists_arrays_and_block_offsets() {
+        let text = program().to_string();
+        assert!(text.contains("  vec3 lights[4] @ 0\n"));
+        assert!(text.contains("  mat4 model @ 2\n"));
+        assert!(!text.contains("mat4 view"));
+        assert!(text.contains("  Camera (64 bytes) @ binding 0\n    view +0\n"));
+    }
+}
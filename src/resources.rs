@@ -0,0 +1,268 @@
+#[cfg(feature = "track-resources")]
+use std::{
+    backtrace::Backtrace,
+    collections::HashMap,
+    fmt,
+    sync::{LazyLock, Mutex},
+};
+
+/// Kinds of GL object the registry tells apart. Objects are only recorded with the `track-resources` feature
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ResourceKind {
+    Buffer,
+    VertexArray,
+    Texture,
+    Shader,
+    Program,
+    Framebuffer,
+    Renderbuffer,
+}
+
+#[cfg(feature = "track-resources")]
+struct Record {
+    label: Option<String>,
+    backtrace: Backtrace,
+    /// Target the object was first bound to, which fixes its type for good
+    target: Option<u32>,
+}
+
+#[cfg(feature = "track-resources")]
+static REGISTRY: LazyLock<Mutex<HashMap<(ResourceKind, u32), Record>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[cfg(feature = "track-resources")]
+fn registry() -> std::sync::MutexGuard<'static, HashMap<(ResourceKind, u32), Record>> {
+    // a panic while holding the lock leaves the map itself intact
+    REGISTRY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Records a wrapper taking ownership of an object
+#[inline]
+pub(crate) fn created(kind: ResourceKind, id: u32) {
+    #[cfg(feature = "track-resources")]
+    registry().insert(
+        (kind, id),
+        Record {
+            label: None,
+            backtrace: Backtrace::force_capture(),
+            target: None,
+        },
+    );
+    #[cfg(not(feature = "track-resources"))]
+    let _ = (kind, id);
+}
+
+/// Records a wrapper deleting or giving up an object
+#[inline]
+pub(crate) fn released(kind: ResourceKind, id: u32) {
+    #[cfg(feature = "track-resources")]
+    registry().remove(&(kind, id));
+    #[cfg(not(feature = "track-resources"))]
+    let _ = (kind, id);
+}
+
+/// Records the target an object is bound to, so it can be sized without `glGetTextureParameteriv`
+#[inline]
+pub(crate) fn bound(kind: ResourceKind, id: u32, target: u32) {
+    #[cfg(feature = "track-resources")]
+    if let Some(record) = registry().get_mut(&(kind, id)) {
+        record.target.get_or_insert(target);
+    }
+    #[cfg(not(feature = "track-resources"))]
+    let _ = (kind, id, target);
+}
+
+/// Names an object in dumps
+#[inline]
+pub(crate) fn set_label(kind: ResourceKind, id: u32, label: &str) {
+    #[cfg(feature = "track-resources")]
+    if let Some(record) = registry().get_mut(&(kind, id)) {
+        record.label = Some(label.to_owned());
+    }
+    #[cfg(not(feature = "track-resources"))]
+    let _ = (kind, id, label);
+}
+
+/// An object still alive, as reported by `live`
+#[cfg(feature = "track-resources")]
+#[derive(Debug, Clone)]
+pub struct LiveResource {
+    pub kind: ResourceKind,
+    pub id: u32,
+    pub label: Option<String>,
+    /// Where the object was created
+    pub backtrace: String,
+    /// GPU memory the object takes, as far as GL lets on. Only buffers, textures, renderbuffers and program binaries are counted
+    pub estimated_bytes: usize,
+}
+
+#[cfg(feature = "track-resources")]
+impl fmt::Display for LiveResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} {}", self.kind, self.id)?;
+        if let Some(label) = &self.label {
+            write!(f, " \"{}\"", label)?;
+        }
+        writeln!(f, ", ~{} bytes, created at:", self.estimated_bytes)?;
+        write!(f, "{}", self.backtrace)
+    }
+}
+
+/// Lists every tracked object that is still alive, sorted by kind and id. Needs the GL context to estimate memory
+#[cfg(feature = "track-resources")]
+pub fn live() -> Vec<LiveResource> {
+    let registry = registry();
+    let mut live: Vec<LiveResource> = registry
+        .iter()
+        .map(|(&(kind, id), record)| LiveResource {
+            kind,
+            id,
+            label: record.label.clone(),
+            backtrace: record.backtrace.to_string(),
+            estimated_bytes: estimate_bytes(kind, id, record.target),
+        })
+        .collect();
+    live.sort_by_key(|r| (r.kind, r.id));
+    live
+}
+
+/// Prints every live object and the total estimated GPU memory they take
+#[cfg(feature = "track-resources")]
+pub fn dump() {
+    let live = live();
+    let total: usize = live.iter().map(|r| r.estimated_bytes).sum();
+    eprintln!(
+        "{} live GL objects, ~{} bytes of GPU memory",
+        live.len(),
+        total
+    );
+    for resource in &live {
+        eprintln!("{}", resource);
+    }
+}
+
+#[cfg(feature = "track-resources")]
+fn estimate_bytes(kind: ResourceKind, id: u32, target: Option<u32>) -> usize {
+    unsafe {
+        match kind {
+            ResourceKind::Buffer => {
+                let mut previous = 0;
+                let mut size = 0;
+                gl::GetIntegerv(gl::COPY_READ_BUFFER_BINDING, &mut previous);
+                gl::BindBuffer(gl::COPY_READ_BUFFER, id);
+                gl::GetBufferParameteri64v(gl::COPY_READ_BUFFER, gl::BUFFER_SIZE, &mut size);
+                gl::BindBuffer(gl::COPY_READ_BUFFER, previous as u32);
+                size as usize
+            }
+            ResourceKind::Texture => {
+                // binding a texture to a target it wasn't created for is an error, so only known targets are sized
+                let Some((target, binding)) = texture_target(id, target) else {
+                    return 0;
+                };
+                let (level_target, faces) = match target {
+                    gl::TEXTURE_CUBE_MAP => (gl::TEXTURE_CUBE_MAP_POSITIVE_X, 6),
+                    _ => (target, 1),
+                };
+                let mut previous = 0;
+                gl::GetIntegerv(binding, &mut previous);
+                gl::BindTexture(target, id);
+                let bytes: usize = (0..)
+                    .map(|level| texture_level_bytes(level_target, level))
+                    .take_while(|&bytes| bytes > 0)
+                    .sum();
+                gl::BindTexture(target, previous as u32);
+                bytes * faces
+            }
+            ResourceKind::Program => {
+                let mut length = 0;
+                gl::GetProgramiv(id, gl::PROGRAM_BINARY_LENGTH, &mut length);
+                length as usize
+            }
+            ResourceKind::Renderbuffer => {
+                let mut previous = 0;
+                gl::GetIntegerv(gl::RENDERBUFFER_BINDING, &mut previous);
+                gl::BindRenderbuffer(gl::RENDERBUFFER, id);
+                let parameter = |name| {
+                    let mut value = 0;
+                    gl::GetRenderbufferParameteriv(gl::RENDERBUFFER, name, &mut value);
+                    value.max(0) as usize
+                };
+                let bits: usize = [
+                    gl::RENDERBUFFER_RED_SIZE,
+                    gl::RENDERBUFFER_GREEN_SIZE,
+                    gl::RENDERBUFFER_BLUE_SIZE,
+                    gl::RENDERBUFFER_ALPHA_SIZE,
+                    gl::RENDERBUFFER_DEPTH_SIZE,
+                    gl::RENDERBUFFER_STENCIL_SIZE,
+                ]
+                .into_iter()
+                .map(parameter)
+                .sum();
+                let bytes = parameter(gl::RENDERBUFFER_WIDTH)
+                    * parameter(gl::RENDERBUFFER_HEIGHT)
+                    * parameter(gl::RENDERBUFFER_SAMPLES).max(1)
+                    * bits.div_ceil(8);
+                gl::BindRenderbuffer(gl::RENDERBUFFER, previous as u32);
+                bytes
+            }
+            ResourceKind::VertexArray | ResourceKind::Shader | ResourceKind::Framebuffer => 0,
+        }
+    }
+}
+
+/// The target a texture was created for and the binding that queries it. Takes the target the wrapper recorded, or asks
+/// GL 4.5's `glGetTextureParameteriv` for textures only ever bound outside the wrapper.
+/// `None` for targets this module doesn't size and for names never bound
+#[cfg(feature = "track-resources")]
+unsafe fn texture_target(
+    id: u32,
+    recorded: Option<u32>,
+) -> Option<(gl::types::GLenum, gl::types::GLenum)> {
+    unsafe {
+        if gl::IsTexture(id) == gl::FALSE {
+            return None;
+        }
+        let target = match recorded {
+            Some(target) => target,
+            None if gl::GetTextureParameteriv::is_loaded() => {
+                let mut target = 0;
+                gl::GetTextureParameteriv(id, gl::TEXTURE_TARGET, &mut target);
+                target as gl::types::GLenum
+            }
+            None => return None,
+        };
+        match target {
+            gl::TEXTURE_1D => Some((gl::TEXTURE_1D, gl::TEXTURE_BINDING_1D)),
+            gl::TEXTURE_2D => Some((gl::TEXTURE_2D, gl::TEXTURE_BINDING_2D)),
+            gl::TEXTURE_2D_ARRAY => Some((gl::TEXTURE_2D_ARRAY, gl::TEXTURE_BINDING_2D_ARRAY)),
+            gl::TEXTURE_3D => Some((gl::TEXTURE_3D, gl::TEXTURE_BINDING_3D)),
+            gl::TEXTURE_CUBE_MAP => Some((gl::TEXTURE_CUBE_MAP, gl::TEXTURE_BINDING_CUBE_MAP)),
+            _ => None,
+        }
+    }
+}
+
+/// Size of one mip level of the bound texture, from its dimensions and the bits of each of its components
+#[cfg(feature = "track-resources")]
+unsafe fn texture_level_bytes(target: gl::types::GLenum, level: i32) -> usize {
+    let parameter = |name| {
+        let mut value = 0;
+        unsafe { gl::GetTexLevelParameteriv(target, level, name, &mut value) };
+        value.max(0) as usize
+    };
+    let texels = parameter(gl::TEXTURE_WIDTH)
+        * parameter(gl::TEXTURE_HEIGHT)
+        * parameter(gl::TEXTURE_DEPTH).max(1);
+    let bits: usize = [
+        gl::TEXTURE_RED_SIZE,
+        gl::TEXTURE_GREEN_SIZE,
+        gl::TEXTURE_BLUE_SIZE,
+        gl::TEXTURE_ALPHA_SIZE,
+        gl::TEXTURE_DEPTH_SIZE,
+        gl::TEXTURE_STENCIL_SIZE,
+    ]
+    .into_iter()
+    .map(parameter)
+    .sum();
+    texels * bits.div_ceil(8)
+}
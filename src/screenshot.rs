@@ -0,0 +1,385 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+/// An 8-bit RGBA image stored top row first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[u8; 4]>,
+}
+impl RgbaImage {
+    /// Creates an image of the given size filled with `fill`
+    pub fn new(width: u32, height: u32, fill: [u8; 4]) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![fill; width as usize * height as usize],
+        }
+    }
+
+    /// Gets the pixel at `x`, `y` counted from the top left corner
+    pub fn get(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+        if x < self.width && y < self.height {
+            self.pixels
+                .get(y as usize * self.width as usize + x as usize)
+                .copied()
+        } else {
+            None
+        }
+    }
+
+    /// Reads a PNG, BMP or netpbm image, converting it to 8-bit RGBA
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        if let Some(image) = decode_png(&bytes) {
+            return Ok(image);
+        }
+        // BMP and netpbm pixels come back unchanged in imagine's default sRGB format
+        let bitmap: imagine::Bitmap = imagine::try_bitmap_rgba(&bytes, true)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))?;
+        Ok(Self {
+            width: bitmap.width,
+            height: bitmap.height,
+            pixels: bitmap.pixels.iter().map(|p| [p.r, p.g, p.b, p.a]).collect(),
+        })
+    }
+
+    /// Writes this image as an 8-bit RGBA PNG
+    pub fn save_png(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(b"\x89PNG\r\n\x1a\n")?;
+        let mut header = Vec::with_capacity(13);
+        header.extend(self.width.to_be_bytes());
+        header.extend(self.height.to_be_bytes());
+        // 8 bits per channel, RGBA, deflate, adaptive filtering, not interlaced
+        header.extend([8, 6, 0, 0, 0]);
+        write_chunk(&mut file, b"IHDR", &header)?;
+        // every row starts with the filter it was written with, 0 for none
+        let mut rows = Vec::with_capacity(self.pixels.len() * 4 + self.height as usize);
+        for row in self.pixels.chunks_exact(self.width.max(1) as usize) {
+            rows.push(0);
+            rows.extend_from_slice(bytemuck::cast_slice(row));
+        }
+        write_chunk(
+            &mut file,
+            b"IDAT",
+            &miniz_oxide::deflate::compress_to_vec_zlib(&rows, 6),
+        )?;
+        write_chunk(&mut file, b"IEND", &[])?;
+        file.flush()
+    }
+
+    /// Writes this image as a binary PPM (P6). Alpha is discarded
+    pub fn save_ppm(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        write!(file, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for pixel in &self.pixels {
+            file.write_all(&pixel[..3])?;
+        }
+        file.flush()
+    }
+}
+
+/// Decodes a PNG of any colour type & bit depth to 8-bit RGBA as stored, without imagine's gamma & alpha premultiplication
+fn decode_png(bytes: &[u8]) -> Option<RgbaImage> {
+    use imagine::png::{
+        PngColorType, png_get_header, png_get_idat, png_get_palette, png_get_transparency,
+    };
+
+    let header = png_get_header(bytes)?;
+    let mut data = vec![0; header.get_zlib_decompression_requirement()];
+    miniz_oxide::inflate::decompress_slice_iter_to_slice(
+        &mut data,
+        png_get_idat(bytes),
+        true,
+        true,
+    )
+    .ok()?;
+    let palette = png_get_palette(bytes).unwrap_or(&[]);
+    let transparency = png_get_transparency(bytes);
+    let alphas = transparency.map(|t| t.to_alphas()).unwrap_or(&[]);
+    let transparent_y = transparency.and_then(|t| t.try_to_grayscale());
+    let transparent_rgb = transparency.and_then(|t| t.try_to_rgb());
+
+    // the `i`th sample of a pixel scaled to 8 bits, and as stored, which is what transparent colours are given as
+    let depth = header.bit_depth;
+    let sample = |data: &[u8], i: usize| match depth {
+        16 => (
+            data[i * 2],
+            u16::from_be_bytes([data[i * 2], data[i * 2 + 1]]),
+        ),
+        8 => (data[i], data[i] as u16),
+        _ => (
+            (data[i] as u32 * 255 / ((1 << depth) - 1)) as u8,
+            data[i] as u16,
+        ),
+    };
+    let opacity = |transparent: bool| if transparent { 0 } else { 255 };
+
+    let mut image = RgbaImage::new(header.width, header.height, [0, 0, 0, 255]);
+    let width = header.width as usize;
+    header
+        .unfilter_decompressed_data(&mut data, |x, y, data| {
+            let pixel = match header.color_type {
+                PngColorType::Y => {
+                    let (v, stored) = sample(data, 0);
+                    [v, v, v, opacity(Some(stored) == transparent_y)]
+                }
+                PngColorType::YA => {
+                    let v = sample(data, 0).0;
+                    [v, v, v, sample(data, 1).0]
+                }
+                PngColorType::RGB => {
+                    let [(r, sr), (g, sg), (b, sb)] = [0, 1, 2].map(|i| sample(data, i));
+                    [r, g, b, opacity(Some([sr, sg, sb]) == transparent_rgb)]
+                }
+                PngColorType::RGBA => [0, 1, 2, 3].map(|i| sample(data, i).0),
+                PngColorType::Index => {
+                    let index = data[0] as usize;
+                    let [r, g, b] = palette.get(index).copied().unwrap_or_default();
+                    [r, g, b, alphas.get(index).copied().unwrap_or(255)]
+                }
+            };
+            if let Some(p) = image.pixels.get_mut(y as usize * width + x as usize) {
+                *p = pixel;
+            }
+        })
+        .ok()?;
+    Some(image)
+}
+
+/// Writes a PNG chunk: its length, type, data and the CRC-32 of its type and data
+fn write_chunk(file: &mut impl Write, ty: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let length = u32::try_from(data.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "PNG chunk over 4GiB"))?;
+    file.write_all(&length.to_be_bytes())?;
+    file.write_all(ty)?;
+    file.write_all(data)?;
+    file.write_all(&crc32(ty.iter().chain(data)).to_be_bytes())
+}
+
+/// CRC-32 as PNG checksums chunks with
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    let mut crc = !0_u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// A depth image with values in 0-1, stored top row first
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepthImage {
+    pub width: u32,
+    pub height: u32,
+    pub depths: Vec<f32>,
+}
+impl DepthImage {
+    /// Converts to a greyscale image, stretching the closest and farthest depths present to black and white
+    pub fn to_rgba(&self) -> RgbaImage {
+        let (min, max) = self
+            .depths
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(min, max), d| {
+                (min.min(*d), max.max(*d))
+            });
+        let range = if max > min { max - min } else { 1.0 };
+        RgbaImage {
+            width: self.width,
+            height: self.height,
+            pixels: self
+                .depths
+                .iter()
+                .map(|d| {
+                    let v = ((d - min) / range * 255.0).round() as u8;
+                    [v, v, v, 255]
+                })
+                .collect(),
+        }
+    }
+
+    /// Writes this image as a greyscale PNG. See `DepthImage::to_rgba`
+    pub fn save_png(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.to_rgba().save_png(path)
+    }
+}
+
+/// Reads a rectangle of colour from the bound read framebuffer, with `x`, `y` as its bottom left corner in GL window coordinates
+pub fn read_color(x: i32, y: i32, width: u32, height: u32) -> RgbaImage {
+    let mut pixels = vec![[0_u8; 4]; width as usize * height as usize];
+    unsafe {
+        let mut previous = 0;
+        gl::GetIntegerv(gl::PACK_ALIGNMENT, &mut previous);
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(
+            x,
+            y,
+            width.try_into().unwrap(),
+            height.try_into().unwrap(),
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            pixels.as_mut_ptr().cast(),
+        );
+        gl::PixelStorei(gl::PACK_ALIGNMENT, previous);
+    }
+    RgbaImage {
+        width,
+        height,
+        pixels: flip_rows(pixels, width),
+    }
+}
+
+/// Reads a rectangle of depth from the bound read framebuffer, with `x`, `y` as its bottom left corner in GL window coordinates
+pub fn read_depth(x: i32, y: i32, width: u32, height: u32) -> DepthImage {
+    let mut depths = vec![0_f32; width as usize * height as usize];
+    unsafe {
+        let mut previous = 0;
+        gl::GetIntegerv(gl::PACK_ALIGNMENT, &mut previous);
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 4);
+        gl::ReadPixels(
+            x,
+            y,
+            width.try_into().unwrap(),
+            height.try_into().unwrap(),
+            gl::DEPTH_COMPONENT,
+            gl::FLOAT,
+            depths.as_mut_ptr().cast(),
+        );
+        gl::PixelStorei(gl::PACK_ALIGNMENT, previous);
+    }
+    DepthImage {
+        width,
+        height,
+        depths: flip_rows(depths, width),
+    }
+}
+
+/// GL returns the bottom row first, images store the top row first
+fn flip_rows<T: Copy>(pixels: Vec<T>, width: u32) -> Vec<T> {
+    if width == 0 {
+        return pixels;
+    }
+    pixels
+        .chunks_exact(width as usize)
+        .rev()
+        .flatten()
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("magiebleue-{}-{}", std::process::id(), name))
+    }
+
+    /// A 3x2 image with distinct pixels, some translucent
+    fn sample_image() -> RgbaImage {
+        RgbaImage {
+            width: 3,
+            height: 2,
+            pixels: vec![
+                [255, 0, 0, 255],
+                [0, 255, 0, 128],
+                [0, 0, 255, 0],
+                [1, 2, 3, 4],
+                [250, 251, 252, 253],
+                [14, 14, 14, 255],
+            ],
+        }
+    }
+
+    #[test]
+    fn flips_rows() {
+        assert_eq!(flip_rows(vec![1, 2, 3, 4, 5, 6], 3), vec![4, 5, 6, 1, 2, 3]);
+        assert_eq!(flip_rows(vec![1, 2, 3], 1), vec![3, 2, 1]);
+        assert_eq!(flip_rows(Vec::<u8>::new(), 0), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn gets_pixels_from_the_top_left() {
+        let image = sample_image();
+        assert_eq!(image.get(0, 0), Some([255, 0, 0, 255]));
+        assert_eq!(image.get(2, 1), Some([14, 14, 14, 255]));
+        assert_eq!(image.get(3, 0), None);
+        assert_eq!(image.get(0, 2), None);
+    }
+
+    #[test]
+    fn saves_ppm_without_alpha() {
+        let path = temp_path("image.ppm");
+        sample_image().save_ppm(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        let mut expected = b"P6\n3 2\n255\n".to_vec();
+        for pixel in &sample_image().pixels {
+            expected.extend(&pixel[..3]);
+        }
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn png_round_trips() {
+        let path = temp_path("image.png");
+        let image = sample_image();
+        image.save_png(&path).unwrap();
+        let loaded = RgbaImage::load(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(loaded.unwrap(), image);
+    }
+
+    #[test]
+    fn png_chunks_have_valid_checksums() {
+        // the standard CRC-32 check value
+        assert_eq!(crc32(b"123456789".iter()), 0xCBF4_3926);
+
+        let path = temp_path("chunks.png");
+        sample_image().save_png(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(&bytes[..8], b"\x89PNG\r\n\x1a\n");
+        let mut rest = &bytes[8..];
+        let mut types = Vec::new();
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (body, crc) = rest[4..].split_at(4 + length);
+            assert_eq!(crc32(body.iter()).to_be_bytes(), crc[..4]);
+            types.push(body[..4].to_vec());
+            rest = &crc[4..];
+        }
+        assert_eq!(types, [b"IHDR", b"IDAT", b"IEND"]);
+    }
+
+    #[test]
+    fn depth_images_stretch_to_black_and_white() {
+        let depth = DepthImage {
+            width: 3,
+            height: 1,
+            depths: vec![0.5, 0.75, 1.0],
+        };
+        let values: Vec<_> = depth.to_rgba().pixels.iter().map(|p| p[0]).collect();
+        assert_eq!(values, [0, 128, 255]);
+
+        let flat = DepthImage {
+            width: 2,
+            height: 1,
+            depths: vec![0.3, 0.3],
+        };
+        assert_eq!(flat.to_rgba().pixels, [[0, 0, 0, 255]; 2]);
+    }
+}
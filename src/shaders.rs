@@ -1,15 +1,42 @@
-use std::{ffi::CString, fs};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    ffi::CString,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
 
 use gl::types::{self, GLenum, GLuint};
 use ultraviolet::Mat4;
 
-use crate::functions::get_error;
+use crate::{
+    debug,
+    error::{Error, Result},
+    gl_objects::VertexLayout,
+    reflection::ProgramInfo,
+    resources::{self, ResourceKind},
+    uniform_blocks,
+    uniforms::{self, ActiveUniform, Uniform, UniformValue},
+};
+
+/// Holds a program object and the locations of its uniforms, looked up once it links
+pub struct ShaderProgram(GLuint, RefCell<UniformCache>);
+
+#[derive(Default)]
+struct UniformCache {
+    active: HashMap<String, ActiveUniform>,
+    /// Names already warned about, so per-frame calls don't flood the output
+    warned: HashSet<String>,
+}
 
-pub struct ShaderProgram(pub GLuint);
 impl ShaderProgram {
-    pub fn new() -> Option<Self> {
+    pub fn new() -> Result<Self> {
         let prog = unsafe { gl::CreateProgram() };
-        if prog != 0 { Some(Self(prog)) } else { None }
+        if prog != 0 {
+            Ok(Self::from_raw(prog))
+        } else {
+            Err(Error::gl("glCreateProgram"))
+        }
     }
 
     /// Attaches a shader to this program
@@ -17,9 +44,50 @@ impl ShaderProgram {
         unsafe { gl::AttachShader(self.0, shader.0) }
     }
 
-    /// Links all attached shaders to this program
+    /// Links all attached shaders to this program, then caches its uniforms
     pub fn link_program(&self) {
         unsafe { gl::LinkProgram(self.0) };
+        self.load_uniforms();
+    }
+
+    /// Lists the active uniforms of the linked program. Array elements are listed individually as well as under the array's name
+    fn load_uniforms(&self) {
+        let mut cache = self.1.borrow_mut();
+        *cache = UniformCache::default();
+        if !self.link_success() {
+            return;
+        }
+        let location = |name: &str| unsafe {
+            let name = CString::new(name).expect("GL names have no nul bytes");
+            gl::GetUniformLocation(self.0, name.as_ptr())
+        };
+        for info in ProgramInfo::query(self.0).uniforms {
+            let (name, ty, size) = (info.name, info.ty, info.array_size);
+            // arrays are reported by their first element, e.g. `lights[0]`
+            if let Some(array) = name.strip_suffix("[0]") {
+                for element in 1..size {
+                    let element_name = format!("{}[{}]", array, element);
+                    let uniform = ActiveUniform {
+                        location: location(&element_name),
+                        ty,
+                        size: size - element,
+                    };
+                    cache.active.insert(element_name, uniform);
+                }
+                let uniform = ActiveUniform {
+                    location: info.location,
+                    ty,
+                    size,
+                };
+                cache.active.insert(array.to_owned(), uniform);
+            }
+            let uniform = ActiveUniform {
+                location: info.location,
+                ty,
+                size,
+            };
+            cache.active.insert(name, uniform);
+        }
     }
 
     /// Checks if linking was successful
@@ -56,51 +124,142 @@ impl ShaderProgram {
         }
     }
 
-    /// Marks the program for deletion. Will delete as soon as it becomes inactive
-    pub fn delete(self) {
-        unsafe {
-            gl::DeleteProgram(self.0);
-        }
+    /// The GL name of the program
+    pub fn id(&self) -> GLuint {
+        self.0
+    }
+
+    /// Takes ownership of an existing program, which will be deleted when dropped
+    pub fn from_raw(id: GLuint) -> Self {
+        resources::created(ResourceKind::Program, id);
+        let prog = Self(id, RefCell::default());
+        prog.load_uniforms();
+        prog
     }
 
-    /// Gets the location of a uniform in this shader.
+    /// Gives up ownership of the program without deleting it
+    pub fn into_raw(self) -> GLuint {
+        resources::released(ResourceKind::Program, self.0);
+        let prog = std::mem::ManuallyDrop::new(self);
+        prog.1.take();
+        prog.0
+    }
+
+    /// Names the program in debug messages, debugging tools and resource dumps
+    pub fn set_label(&self, label: &str) -> Result<()> {
+        debug::label_object(ResourceKind::Program, self.0, label)
+    }
+
+    /// Gets the location of a uniform in this shader, or -1 if it has none. Looked up once after linking
     pub fn get_uniform_location(&self, uniform: &str) -> types::GLint {
-        let loc = unsafe {
-            gl::GetUniformLocation(
-                self.0,
-                CString::new(uniform)
-                    .expect("should be a valid cstring")
-                    .as_ptr(),
-            )
+        self.active_uniform(uniform).map_or(-1, |u| u.location)
+    }
+
+    /// Looks up an active uniform of the linked program by name, e.g. `material.diffuse`, `lights` or `lights[2]`
+    pub fn active_uniform(&self, uniform: &str) -> Option<ActiveUniform> {
+        self.1.borrow().active.get(uniform).copied()
+    }
+
+    /// Whether the linked program has an active uniform called `uniform`. The compiler removes uniforms that don't affect the output
+    pub fn has_uniform(&self, uniform: &str) -> bool {
+        self.1.borrow().active.contains_key(uniform)
+    }
+
+    /// Sets the uniform called `uniform` on this program, which must be active. Arrays are set from their first element,
+    /// or from the named one, e.g. `lights[2]`.
+    /// Warns once per name if the uniform doesn't exist or is declared as a type `value` doesn't fit
+    pub fn set_uniform<U: Uniform>(&self, uniform: &str, value: U) {
+        let Some(active) = self.active_uniform(uniform) else {
+            self.warn_once(uniform, || {
+                format!(
+                    "is not active in program {}, it may be misspelled or optimised out",
+                    self.0
+                )
+            });
+            return;
         };
-        get_error(Some("ShaderProgram::get_uniform_location"));
-        loc
+        if !U::Value::accepts(active.ty) {
+            self.warn_once(uniform, || {
+                format!(
+                    "is a {}, which can't be set from {}",
+                    uniforms::glsl_type_name(active.ty),
+                    std::any::type_name::<U::Value>()
+                )
+            });
+            return;
+        }
+        if active.location < 0 {
+            self.warn_once(uniform, || {
+                "is in a uniform block, set it through the block's buffer".to_owned()
+            });
+            return;
+        }
+        let mut values = value.values();
+        if values.len() > active.size {
+            self.warn_once(uniform, || {
+                format!(
+                    "has {} elements left, ignoring the other {} values",
+                    active.size,
+                    values.len() - active.size
+                )
+            });
+            values = &values[..active.size];
+        }
+        if !values.is_empty() {
+            U::Value::upload(active.location, values);
+        }
     }
 
-    /// Sets MVP matrices in shader uniforms. Uses uniform names `model`, `view`, and `proj`.
-    pub fn set_matrix_uniforms(&self, model: &Mat4, view: &Mat4, proj: &Mat4) {
-        unsafe {
-            gl::UniformMatrix4fv(
-                self.get_uniform_location("model"),
-                1,
-                gl::FALSE,
-                model.as_array().as_ptr(),
-            );
+    fn warn_once(&self, uniform: &str, message: impl FnOnce() -> String) {
+        if self.1.borrow_mut().warned.insert(uniform.to_owned()) {
+            eprintln!("Uniform \"{}\" {}", uniform, message());
+        }
+    }
 
-            gl::UniformMatrix4fv(
-                self.get_uniform_location("view"),
-                1,
-                gl::FALSE,
-                view.as_array().as_ptr(),
-            );
+    /// Makes the uniform block called `block` read from the buffer bound to uniform buffer binding point `binding`
+    pub fn bind_uniform_block(&self, block: &str, binding: u32) -> Result<()> {
+        let name = CString::new(block).map_err(|_| {
+            Error::InvalidParameter(format!(
+                "uniform block name {:?} contains a nul byte",
+                block
+            ))
+        })?;
+        let index = unsafe { gl::GetUniformBlockIndex(self.0, name.as_ptr()) };
+        if index == gl::INVALID_INDEX {
+            return Err(Error::InvalidParameter(format!(
+                "program {} has no active uniform block called \"{}\"",
+                self.0, block
+            )));
+        }
+        unsafe { gl::UniformBlockBinding(self.0, index, binding) };
+        Ok(())
+    }
 
-            gl::UniformMatrix4fv(
-                self.get_uniform_location("proj"),
-                1,
-                gl::FALSE,
-                proj.as_array().as_ptr(),
-            );
+    /// Binds each of the program's uniform blocks to the binding point shared by blocks of its name,
+    /// so one `UniformBuffer::shared` buffer feeds the same block in every program. Must be redone after relinking
+    pub fn bind_shared_blocks(&self) -> Result<()> {
+        for block in self.reflect().uniform_blocks {
+            let binding = uniform_blocks::binding_point(&block.name);
+            self.bind_uniform_block(&block.name, binding)?;
         }
+        Ok(())
+    }
+
+    /// Lists the attributes, uniforms, uniform blocks and shader storage blocks of the linked program
+    pub fn reflect(&self) -> ProgramInfo {
+        ProgramInfo::query(self.0)
+    }
+
+    /// Checks that vertices of type `T` feed every input of the program's vertex shader. See `ProgramInfo::check_attributes`
+    pub fn check_layout<T: VertexLayout>(&self) -> Result<()> {
+        self.reflect().check_attributes(&T::attributes())
+    }
+
+    /// Sets MVP matrices in shader uniforms. Uses uniform names `model`, `view`, and `proj`.
+    pub fn set_matrix_uniforms(&self, model: &Mat4, view: &Mat4, proj: &Mat4) {
+        self.set_uniform("model", model);
+        self.set_uniform("view", view);
+        self.set_uniform("proj", proj);
     }
 
     /// Compiles a complete shader program from mandatory vertex & fragment and optional tessellation control/evaluation & geometry shader sources.
@@ -110,119 +269,80 @@ impl ShaderProgram {
         tese: Option<&str>,
         geom: Option<&str>,
         frag: &str,
-    ) -> Result<Self, String> {
-        let prog = Self::new().ok_or_else(|| "Couldn't allocate a shader program".to_string())?;
-        let vsh = Shader::from_source(ShaderType::Vertex, vert)
-            .map_err(|e| format!("Vertex compile error: {}", e))?;
-        let fsh = Shader::from_source(ShaderType::Fragment, frag)
-            .map_err(|e| format!("Fragment compile error: {}", e))?;
-        let tesc_sh: Option<Shader>;
-        if tesc.is_some() {
-            tesc_sh = Some(
-                Shader::from_source(
-                    ShaderType::TessellationControl,
-                    tesc.expect("tesc source should exist"),
-                )
-                .map_err(|e| format!("Tessellation control compile error: {}", e))?,
-            );
-            prog.attach_shader(tesc_sh.as_ref().expect("tesc shader should exist"));
-        } else {
-            tesc_sh = None;
-        }
-        let tese_sh: Option<Shader>;
-        if tese.is_some() {
-            tese_sh = Some(
-                Shader::from_source(
-                    ShaderType::TessellationEvaluation,
-                    tese.expect("tese source should exist"),
-                )
-                .map_err(|e| format!("Tessellation evaluation compile error: {}", e))?,
-            );
-            prog.attach_shader(tese_sh.as_ref().expect("tese shader should exist"));
-        } else {
-            tese_sh = None;
-        }
-        let geom_sh: Option<Shader>;
-        if geom.is_some() {
-            geom_sh = Some(
-                Shader::from_source(
-                    ShaderType::TessellationControl,
-                    geom.expect("geom source should exist"),
-                )
-                .map_err(|e| format!("Geometry compile error: {}", e))?,
-            );
-            prog.attach_shader(geom_sh.as_ref().expect("geom shader should exist"));
-        } else {
-            geom_sh = None;
-        }
-        prog.attach_shader(&vsh);
-        prog.attach_shader(&fsh);
-        prog.link_program();
-        vsh.delete();
-        fsh.delete();
-        if let Some(tesc) = tesc_sh {
-            tesc.delete();
-        }
-        if let Some(tese) = tese_sh {
-            tese.delete();
-        }
-        if let Some(geom) = geom_sh {
-            geom.delete();
-        }
-
-        if prog.link_success() {
-            Ok(prog)
-        } else {
-            let out = format!("Linking error: {}", prog.info_log());
-            prog.delete();
-            Err(out)
+    ) -> Result<Self> {
+        let mut shaders = Vec::new();
+        for (ty, source) in stages(vert, tesc, tese, geom, frag) {
+            shaders.push(Shader::from_source(ty, source)?);
         }
+        Self::from_shaders(shaders)
     }
 
     /// Compiles a complete shader program from a mandatory vertex & fragment and optional tessellation control/evaluation & geometry shader filepaths.
+    /// Sources go through a default `Preprocessor`, so they can `#include` other files
     pub fn from_filepath(
         vert: &str,
         tesc: Option<&str>,
         tese: Option<&str>,
         geom: Option<&str>,
         frag: &str,
-    ) -> Result<Self, String> {
-        let tesc_src: Result<Option<String>, String> = if let Some(tesc) = tesc {
-            Ok(Some(fs::read_to_string(tesc).map_err(|e| {
-                format!("Tessellation control compile error: {}", e)
-            })?))
-        } else {
-            Ok(None)
-        };
-        let tese_src: Result<Option<String>, String> = if let Some(tese) = tese {
-            Ok(Some(fs::read_to_string(tese).map_err(|e| {
-                format!("Tessellation evaluation compile error: {}", e)
-            })?))
-        } else {
-            Ok(None)
-        };
-        let geom_src: Result<Option<String>, String> = if let Some(geom) = geom {
-            Ok(Some(
-                fs::read_to_string(geom).map_err(|e| format!("Geometry compile error: {}", e))?,
-            ))
+    ) -> Result<Self> {
+        Self::from_filepath_with(&Preprocessor::default(), vert, tesc, tese, geom, frag)
+    }
+
+    /// Like `from_filepath`, with `preprocessor` supplying the `#version` line and `#define`s
+    pub fn from_filepath_with(
+        preprocessor: &Preprocessor,
+        vert: &str,
+        tesc: Option<&str>,
+        tese: Option<&str>,
+        geom: Option<&str>,
+        frag: &str,
+    ) -> Result<Self> {
+        let mut sources = Vec::new();
+        for (ty, path) in stages(vert, tesc, tese, geom, frag) {
+            sources.push((ty, preprocessor.process_file(path)?));
+        }
+        Self::from_preprocessed(&sources)
+    }
+
+    /// Compiles and links preprocessed stages. Compile errors point at the file and line they're in
+    pub fn from_preprocessed(stages: &[(ShaderType, PreprocessedSource)]) -> Result<Self> {
+        let mut shaders = Vec::new();
+        for (ty, source) in stages {
+            shaders.push(Shader::from_preprocessed(*ty, source)?);
+        }
+        Self::from_shaders(shaders)
+    }
+
+    /// Links compiled shaders into a new program
+    fn from_shaders(shaders: Vec<Shader>) -> Result<Self> {
+        let prog = Self::new()?;
+        for shader in &shaders {
+            prog.attach_shader(shader);
+        }
+        prog.link_program();
+        // dropping the shaders only flags them, they live on with the program
+        drop(shaders);
+
+        if prog.link_success() {
+            Ok(prog)
         } else {
-            Ok(None)
-        };
+            Err(Error::Link {
+                log: prog.info_log(),
+            })
+        }
+    }
+}
 
-        ShaderProgram::from_string(
-            fs::read_to_string(vert)
-                .map_err(|e| format!("Vertex compile error: {}", e))?
-                .as_str(),
-            tesc_src?.as_deref(),
-            tese_src?.as_deref(),
-            geom_src?.as_deref(),
-            fs::read_to_string(frag)
-                .map_err(|e| format!("Fragment compile error: {}", e))?
-                .as_str(),
-        )
+/// Marks the program for deletion. It goes as soon as it becomes inactive
+impl Drop for ShaderProgram {
+    fn drop(&mut self) {
+        resources::released(ResourceKind::Program, self.0);
+        unsafe { gl::DeleteProgram(self.0) }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ShaderType {
     Vertex = gl::VERTEX_SHADER as _,
     Fragment = gl::FRAGMENT_SHADER as _,
@@ -231,15 +351,34 @@ pub enum ShaderType {
     TessellationEvaluation = gl::TESS_EVALUATION_SHADER as _,
 }
 
-pub struct Shader(pub GLuint);
+/// Pairs the stages a program has with their shader type, in pipeline order
+pub(crate) fn stages<T>(
+    vert: T,
+    tesc: Option<T>,
+    tese: Option<T>,
+    geom: Option<T>,
+    frag: T,
+) -> impl Iterator<Item = (ShaderType, T)> {
+    [
+        (ShaderType::Vertex, Some(vert)),
+        (ShaderType::TessellationControl, tesc),
+        (ShaderType::TessellationEvaluation, tese),
+        (ShaderType::Geometry, geom),
+        (ShaderType::Fragment, Some(frag)),
+    ]
+    .into_iter()
+    .filter_map(|(ty, stage)| Some((ty, stage?)))
+}
+
+pub struct Shader(GLuint);
 impl Shader {
     /// Creates a new shader
-    pub fn new(ty: ShaderType) -> Option<Self> {
+    pub fn new(ty: ShaderType) -> Result<Self> {
         let shader = unsafe { gl::CreateShader(ty as GLenum) };
         if shader != 0 {
-            Some(Self(shader))
+            Ok(Self::from_raw(shader))
         } else {
-            None
+            Err(Error::gl("glCreateShader"))
         }
     }
 
@@ -285,22 +424,647 @@ impl Shader {
         String::from_utf8_lossy(&v).into_owned()
     }
 
-    /// Marks a shader for deletion. Does not delete immediately
-    pub fn delete(self) {
-        unsafe { gl::DeleteShader(self.0) };
+    /// The GL name of the shader
+    pub fn id(&self) -> GLuint {
+        self.0
     }
 
-    /// Compiles a full shader from source & type, providing the compiled shader ID or an error message
-    pub fn from_source(ty: ShaderType, source: &str) -> Result<Self, String> {
-        let id = Self::new(ty).ok_or_else(|| "Couldn't allocate new shader".to_string())?;
+    /// Takes ownership of an existing shader, which will be deleted when dropped
+    pub fn from_raw(id: GLuint) -> Self {
+        resources::created(ResourceKind::Shader, id);
+        Self(id)
+    }
+
+    /// Gives up ownership of the shader without deleting it
+    pub fn into_raw(self) -> GLuint {
+        resources::released(ResourceKind::Shader, self.0);
+        std::mem::ManuallyDrop::new(self).0
+    }
+
+    /// Names the shader in debug messages, debugging tools and resource dumps
+    pub fn set_label(&self, label: &str) -> Result<()> {
+        debug::label_object(ResourceKind::Shader, self.0, label)
+    }
+
+    /// Compiles a full shader from source & type, providing the compiled shader or the compile log
+    pub fn from_source(ty: ShaderType, source: &str) -> Result<Self> {
+        let id = Self::new(ty)?;
         id.set_source(source);
         id.compile();
         if id.compile_success() {
             Ok(id)
         } else {
-            let out = id.info_log();
-            id.delete();
-            Err(out)
+            Err(Error::ShaderCompile {
+                stage: ty,
+                log: ShaderLogLine::parse_log(&id.info_log()),
+            })
+        }
+    }
+
+    /// Compiles preprocessed source, mapping compile log lines back to the file and line they came from
+    pub fn from_preprocessed(ty: ShaderType, source: &PreprocessedSource) -> Result<Self> {
+        Self::from_source(ty, &source.code).map_err(|e| match e {
+            Error::ShaderCompile { stage, mut log } => {
+                source.map_log(&mut log);
+                Error::ShaderCompile { stage, log }
+            }
+            e => e,
+        })
+    }
+}
+
+/// Marks the shader for deletion. It goes once no program has it attached
+impl Drop for Shader {
+    fn drop(&mut self) {
+        resources::released(ResourceKind::Shader, self.0);
+        unsafe { gl::DeleteShader(self.0) }
+    }
+}
+
+/// Resolves `#include "file"` relative to the including file, and injects a `#version` line and `#define`s supplied from Rust.
+/// Each file is included at most once per shader, like with `#pragma once`, and a file including itself is an error.
+/// Other directives are left to the GLSL compiler
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Preprocessor {
+    /// Replaces the shader's own `#version` line, e.g. `430 core`
+    pub version: Option<String>,
+    /// `#define name value` lines inserted after the `#version` line, in order
+    pub defines: Vec<(String, String)>,
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compiles every shader as `#version version`
+    pub fn version(mut self, version: &str) -> Self {
+        self.version = Some(version.to_owned());
+        self
+    }
+
+    /// Adds `#define name value`. `value` may be empty to just define the name.
+    /// It's written with `Display`, so floats without a fraction come out as ints: pass `"3.0"` rather than `3.0`
+    pub fn define(mut self, name: &str, value: impl fmt::Display) -> Self {
+        self.defines.push((name.to_owned(), value.to_string()));
+        self
+    }
+
+    /// Reads and preprocesses the shader at `path`
+    pub fn process_file(&self, path: impl AsRef<Path>) -> Result<PreprocessedSource> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(Error::io(path))?;
+        self.process(&source, path)
+    }
+
+    /// Preprocesses `source`, resolving its includes relative to `path`
+    pub fn process(&self, source: &str, path: &Path) -> Result<PreprocessedSource> {
+        let mut expansion = Expansion::default();
+        expansion.add_file(path);
+        expansion.expand(source, 0)?;
+        let Expansion {
+            files, mut lines, ..
+        } = expansion;
+
+        // `#version` must come first, with the defines straight after it
+        let version_line = lines
+            .iter()
+            .position(|(text, _)| directive(text, "version").is_some());
+        let mut defines_at = version_line.map_or(0, |line| line + 1);
+        if let Some(version) = &self.version {
+            let text = format!("#version {}", version);
+            match version_line {
+                Some(line) => lines[line].0 = text,
+                None => {
+                    lines.insert(0, (text, None));
+                    defines_at = 1;
+                }
+            }
+        }
+        for (i, (name, value)) in self.defines.iter().enumerate() {
+            let text = format!("#define {} {}", name, value).trim_end().to_owned();
+            lines.insert(defines_at + i, (text, None));
+        }
+
+        let mut code = String::new();
+        for (text, _) in &lines {
+            code.push_str(text);
+            code.push('\n');
+        }
+        Ok(PreprocessedSource {
+            code,
+            files,
+            lines: lines.into_iter().map(|(_, origin)| origin).collect(),
+        })
+    }
+}
+
+/// State of a `Preprocessor` run
+#[derive(Default)]
+struct Expansion {
+    files: Vec<PathBuf>,
+    /// Canonical form of each file, to recognise one reached through different paths
+    canonical: Vec<PathBuf>,
+    /// Files being expanded, outermost first
+    stack: Vec<usize>,
+    /// Output lines, with the file index and line they came from
+    lines: Vec<(String, Option<(usize, u32)>)>,
+}
+
+impl Expansion {
+    fn add_file(&mut self, path: &Path) -> usize {
+        self.files.push(path.to_path_buf());
+        self.canonical
+            .push(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()));
+        self.files.len() - 1
+    }
+
+    fn expand(&mut self, source: &str, file: usize) -> Result<()> {
+        self.stack.push(file);
+        for (number, text) in (1..).zip(source.lines()) {
+            let error = |message: String| Error::Preprocess {
+                path: self.files[file].clone(),
+                line: number,
+                message,
+            };
+            if let Some(rest) = directive(text, "include") {
+                let Some((target, _)) = rest.strip_prefix('"').and_then(|r| r.split_once('"'))
+                else {
+                    return Err(error(format!(
+                        "expected #include \"file\", found {}",
+                        text.trim()
+                    )));
+                };
+                let dir = self.files[file].parent().unwrap_or(Path::new(""));
+                let path = dir.join(target);
+                let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+                if let Some(included) = self.canonical.iter().position(|c| *c == canonical) {
+                    if self.stack.contains(&included) {
+                        let mut chain: Vec<String> = self.stack
+                            [self.stack.iter().position(|&f| f == included).unwrap_or(0)..]
+                            .iter()
+                            .map(|&f| self.files[f].display().to_string())
+                            .collect();
+                        chain.push(path.display().to_string());
+                        return Err(error(format!("include cycle: {}", chain.join(" -> "))));
+                    }
+                    // already included, the implicit guard skips it
+                    continue;
+                }
+                let included_source = fs::read_to_string(&path)
+                    .map_err(|e| error(format!("can't include {}: {}", path.display(), e)))?;
+                let included = self.add_file(&path);
+                self.expand(&included_source, included)?;
+            } else if file != 0 && directive(text, "version").is_some() {
+                // included files may carry a `#version` for editors, the shader's own is the one kept
+                continue;
+            } else {
+                self.lines.push((text.to_owned(), Some((file, number))));
+            }
+        }
+        self.stack.pop();
+        Ok(())
+    }
+}
+
+/// The arguments of a `#name` directive line, e.g. `"file"` for `#include "file"`
+fn directive<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let rest = line
+        .trim_start()
+        .strip_prefix('#')?
+        .trim_start()
+        .strip_prefix(name)?;
+    if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+        Some(rest.trim())
+    } else {
+        None
+    }
+}
+
+/// Shader source produced by a `Preprocessor`, with where each of its lines came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreprocessedSource {
+    pub code: String,
+    /// Every file read, the shader's own first
+    pub files: Vec<PathBuf>,
+    /// File index & line of each line of `code`, `None` for injected ones
+    lines: Vec<Option<(usize, u32)>>,
+}
+
+impl PreprocessedSource {
+    /// The file & line that line `line` (1-based) of `code` came from, `None` for injected lines
+    pub fn origin(&self, line: u32) -> Option<(&Path, u32)> {
+        let (file, line) = (*self.lines.get(line.checked_sub(1)? as usize)?)?;
+        Some((&self.files[file], line))
+    }
+
+    /// Points log lines at the file & line they refer to. Lines about injected code keep their position in `code`
+    pub fn map_log(&self, log: &mut [ShaderLogLine]) {
+        for entry in log {
+            if let Some((file, line)) = entry.line.and_then(|line| self.origin(line)) {
+                entry.file = Some(file.to_path_buf());
+                entry.line = Some(line);
+            }
+        }
+    }
+}
+
+/// How serious a line of a compile log is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogSeverity {
+    Error,
+    Warning,
+    /// Anything else, including lines the parser couldn't make sense of
+    Info,
+}
+
+/// One line of a shader compile log, picked apart from the formats Mesa, NVIDIA and AMD drivers use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderLogLine {
+    pub severity: LogSeverity,
+    /// Source string number, 0 for shaders made from a single source
+    pub source: Option<u32>,
+    /// 1-based line within the source, or within `file` once mapped back through the preprocessor
+    pub line: Option<u32>,
+    /// File the line is in, for shaders that went through a `Preprocessor`
+    pub file: Option<PathBuf>,
+    pub message: String,
+}
+impl ShaderLogLine {
+    /// Splits a compile log into its non-empty lines
+    pub fn parse_log(log: &str) -> Vec<Self> {
+        log.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(Self::parse)
+            .collect()
+    }
+
+    /// Parses `0:12(5): error: ...` (Mesa), `0(12) : error C0000: ...` (NVIDIA) or `ERROR: 0:12: ...` (AMD)
+    pub fn parse(text: &str) -> Self {
+        let mut severity = None;
+        let mut rest = text.trim();
+        for (prefix, prefix_severity) in [
+            ("ERROR:", LogSeverity::Error),
+            ("WARNING:", LogSeverity::Warning),
+        ] {
+            if let Some(stripped) = rest.strip_prefix(prefix) {
+                severity = Some(prefix_severity);
+                rest = stripped.trim_start();
+            }
+        }
+
+        let (source, line) = match parse_location(rest) {
+            Some((source, line, after)) => {
+                rest = after.trim_start();
+                (Some(source), Some(line))
+            }
+            None => (None, None),
+        };
+
+        // a severity word, possibly followed by an error code, before the message
+        if let Some((head, message)) = rest.split_once(':') {
+            let head = head.trim().to_ascii_lowercase();
+            let word_severity = if head.starts_with("error") {
+                Some(LogSeverity::Error)
+            } else if head.starts_with("warning") {
+                Some(LogSeverity::Warning)
+            } else if head.starts_with("info") {
+                Some(LogSeverity::Info)
+            } else {
+                None
+            };
+            if word_severity.is_some() {
+                severity = severity.or(word_severity);
+                rest = message.trim_start();
+            }
+        }
+
+        Self {
+            severity: severity.unwrap_or(LogSeverity::Info),
+            source,
+            line,
+            file: None,
+            message: rest.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for ShaderLogLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            LogSeverity::Error => "error",
+            LogSeverity::Warning => "warning",
+            LogSeverity::Info => "info",
+        };
+        match (&self.file, self.source, self.line) {
+            (Some(file), _, Some(line)) => {
+                write!(
+                    f,
+                    "{}:{}: {}: {}",
+                    file.display(),
+                    line,
+                    severity,
+                    self.message
+                )
+            }
+            (None, Some(source), Some(line)) => {
+                write!(f, "{}:{}: {}: {}", source, line, severity, self.message)
+            }
+            _ => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
+
+/// Reads a `source:line`, `source:line(column)` or `source(line)` location ending in `:`, returning what follows
+fn parse_location(text: &str) -> Option<(u32, u32, &str)> {
+    fn digits(s: &str) -> Option<(u32, &str)> {
+        let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        Some((s[..end].parse().ok()?, &s[end..]))
+    }
+    let (source, rest) = digits(text)?;
+    let (line, rest) = if let Some(rest) = rest.strip_prefix(':') {
+        let (line, rest) = digits(rest)?;
+        // Mesa adds the column in brackets
+        let rest = match rest.strip_prefix('(') {
+            Some(column) => column.split_once(')')?.1,
+            None => rest,
+        };
+        (line, rest)
+    } else {
+        let (line, rest) = digits(rest.strip_prefix('(')?)?;
+        (line, rest.strip_prefix(')')?)
+    };
+    let rest = rest.trim_start().strip_prefix(':')?;
+    Some((source, line, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `files` into a fresh directory under the system temp directory and returns it
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("magiebleue-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (name, contents) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    fn preprocess_error(result: Result<PreprocessedSource>) -> (PathBuf, u32, String) {
+        match result {
+            Err(Error::Preprocess {
+                path,
+                line,
+                message,
+            }) => (path, line, message),
+            other => panic!("expected a preprocessor error, got {:?}", other),
         }
     }
+
+    #[test]
+    fn includes_nest_and_are_read_once() {
+        let dir = write_files(
+            "nested-includes",
+            &[
+                (
+                    "main.frag",
+                    "#version 330 core\n#include \"a.glsl\"\n#include \"b.glsl\"\nvoid main() {}\n",
+                ),
+                ("a.glsl", "#include \"lib/common.glsl\"\nfloat a;\n"),
+                // the second include of common.glsl is skipped, as if it had an include guard
+                ("b.glsl", "#include \"lib/common.glsl\"\nfloat b;\n"),
+                // relative to lib/, and its own #version is dropped
+                (
+                    "lib/common.glsl",
+                    "#version 330 core\n#include \"util.glsl\"\nfloat common;\n",
+                ),
+                ("lib/util.glsl", "float util;\n"),
+            ],
+        );
+        let source = Preprocessor::new()
+            .process_file(dir.join("main.frag"))
+            .unwrap();
+        assert_eq!(
+            source.code,
+            "#version 330 core\nfloat util;\nfloat common;\nfloat a;\nfloat b;\nvoid main() {}\n"
+        );
+        assert_eq!(
+            source.files,
+            vec![
+                dir.join("main.frag"),
+                dir.join("a.glsl"),
+                dir.join("lib/common.glsl"),
+                dir.join("lib/util.glsl"),
+                dir.join("b.glsl"),
+            ]
+        );
+        let lib = dir.join("lib");
+        assert_eq!(source.origin(1), Some((dir.join("main.frag").as_path(), 1)));
+        assert_eq!(source.origin(2), Some((lib.join("util.glsl").as_path(), 1)));
+        assert_eq!(
+            source.origin(3),
+            Some((lib.join("common.glsl").as_path(), 3))
+        );
+        assert_eq!(source.origin(5), Some((dir.join("b.glsl").as_path(), 2)));
+        assert_eq!(source.origin(6), Some((dir.join("main.frag").as_path(), 4)));
+        assert_eq!(source.origin(7), None);
+        assert_eq!(source.origin(0), None);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn include_cycles_report_the_chain() {
+        let dir = write_files(
+            "include-cycle",
+            &[
+                ("main.frag", "#include \"a.glsl\"\n"),
+                ("a.glsl", "float a;\n#include \"b.glsl\"\n"),
+                ("b.glsl", "\n\n#include \"a.glsl\"\n"),
+            ],
+        );
+        let (path, line, message) =
+            preprocess_error(Preprocessor::new().process_file(dir.join("main.frag")));
+        assert_eq!(path, dir.join("b.glsl"));
+        assert_eq!(line, 3);
+        assert_eq!(
+            message,
+            format!(
+                "include cycle: {} -> {} -> {}",
+                dir.join("a.glsl").display(),
+                dir.join("b.glsl").display(),
+                dir.join("a.glsl").display()
+            )
+        );
+        let _ = fs::remove_dir_all(dir);
+
+        let dir = write_files("self-include", &[("main.frag", "#include \"main.frag\"\n")]);
+        let (_, line, message) =
+            preprocess_error(Preprocessor::new().process_file(dir.join("main.frag")));
+        assert_eq!(line, 1);
+        assert!(message.starts_with("include cycle: "), "{}", message);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn bad_includes_point_at_their_line() {
+        let path = Path::new("shader.frag");
+        let (error_path, line, message) =
+            preprocess_error(Preprocessor::new().process("float x;\n#include <file.glsl>\n", path));
+        assert_eq!((error_path.as_path(), line), (path, 2));
+        assert_eq!(
+            message,
+            "expected #include \"file\", found #include <file.glsl>"
+        );
+
+        let (_, line, message) = preprocess_error(
+            Preprocessor::new().process("#include \"magiebleue-missing.glsl\"\n", path),
+        );
+        assert_eq!(line, 1);
+        assert!(message.starts_with("can't include "), "{}", message);
+    }
+
+    #[test]
+    fn version_is_replaced_and_defines_follow_it() {
+        let path = Path::new("shader.frag");
+        let preprocessor = Preprocessor::new()
+            .version("430 core")
+            .define("LIGHTS", 4)
+            .define("SHADOWS", "");
+        let source = preprocessor
+            .process("// header\n#version 330 core\nvoid main() {}\n", path)
+            .unwrap();
+        assert_eq!(
+            source.code,
+            "// header\n#version 430 core\n#define LIGHTS 4\n#define SHADOWS\nvoid main() {}\n"
+        );
+        // the replaced #version keeps its origin, injected defines have none
+        assert_eq!(source.origin(2), Some((path, 2)));
+        assert_eq!(source.origin(3), None);
+        assert_eq!(source.origin(4), None);
+        assert_eq!(source.origin(5), Some((path, 3)));
+    }
+
+    #[test]
+    fn version_is_inserted_when_missing() {
+        let path = Path::new("shader.frag");
+        let source = Preprocessor::new()
+            .version("450")
+            .define("A", 1)
+            .process("void main() {}\n", path)
+            .unwrap();
+        assert_eq!(source.code, "#version 450\n#define A 1\nvoid main() {}\n");
+        assert_eq!(source.origin(1), None);
+        assert_eq!(source.origin(3), Some((path, 1)));
+
+        // without a version to follow, defines go first
+        let source = Preprocessor::new()
+            .define("A", 1)
+            .process("void main() {}\n", path)
+            .unwrap();
+        assert_eq!(source.code, "#define A 1\nvoid main() {}\n");
+
+        // and with one in the source but none given, after the source's
+        let source = Preprocessor::new()
+            .define("A", 1)
+            .process("#version 330 core\nvoid main() {}\n", path)
+            .unwrap();
+        assert_eq!(
+            source.code,
+            "#version 330 core\n#define A 1\nvoid main() {}\n"
+        );
+    }
+
+    #[test]
+    fn parses_mesa_nvidia_and_amd_logs() {
+        let mesa = ShaderLogLine::parse("0:12(5): error: `x' undeclared");
+        assert_eq!(
+            mesa,
+            ShaderLogLine {
+                severity: LogSeverity::Error,
+                source: Some(0),
+                line: Some(12),
+                file: None,
+                message: "`x' undeclared".to_owned(),
+            }
+        );
+        let mesa = ShaderLogLine::parse("0:3(1): warning: unused variable");
+        assert_eq!((mesa.severity, mesa.line), (LogSeverity::Warning, Some(3)));
+
+        let nvidia = ShaderLogLine::parse("0(12) : error C1008: undefined variable \"x\"");
+        assert_eq!(
+            (nvidia.severity, nvidia.source, nvidia.line),
+            (LogSeverity::Error, Some(0), Some(12))
+        );
+        assert_eq!(nvidia.message, "undefined variable \"x\"");
+
+        let amd = ShaderLogLine::parse("ERROR: 0:12: 'x' : undeclared identifier");
+        assert_eq!(
+            (amd.severity, amd.source, amd.line),
+            (LogSeverity::Error, Some(0), Some(12))
+        );
+        assert_eq!(amd.message, "'x' : undeclared identifier");
+
+        let summary = ShaderLogLine::parse("ERROR: 1 compilation errors.  No code generated.");
+        assert_eq!((summary.severity, summary.line), (LogSeverity::Error, None));
+        assert_eq!(summary.message, "1 compilation errors.  No code generated.");
+
+        let other = ShaderLogLine::parse("something else entirely");
+        assert_eq!((other.severity, other.line), (LogSeverity::Info, None));
+
+        assert_eq!(
+            ShaderLogLine::parse_log("\n0:1(1): error: a\n\n  0:2(1): error: b  \n").len(),
+            2
+        );
+    }
+
+    #[test]
+    fn map_log_points_at_included_files() {
+        let dir = write_files(
+            "map-log",
+            &[
+                (
+                    "main.frag",
+                    "#version 330 core\n#include \"lib.glsl\"\nvoid main() {}\n",
+                ),
+                ("lib.glsl", "float a;\nfloat b = x;\n"),
+            ],
+        );
+        let source = Preprocessor::new()
+            .define("A", 1)
+            .process_file(dir.join("main.frag"))
+            .unwrap();
+        // line 1 #version, 2 the define, 3-4 lib.glsl, 5 main()
+        let mut log = ShaderLogLine::parse_log(
+            "0:4(11): error: `x' undeclared\n\
+             0(5) : warning C7050: something\n\
+             ERROR: 0:2: 'A' : redefinition\n\
+             ERROR: 3 compilation errors.  No code generated.",
+        );
+        source.map_log(&mut log);
+
+        assert_eq!(log[0].file.as_deref(), Some(dir.join("lib.glsl").as_path()));
+        assert_eq!(log[0].line, Some(2));
+        assert_eq!(
+            log[0].to_string(),
+            format!(
+                "{}:2: error: `x' undeclared",
+                dir.join("lib.glsl").display()
+            )
+        );
+        assert_eq!(
+            log[1].file.as_deref(),
+            Some(dir.join("main.frag").as_path())
+        );
+        assert_eq!(log[1].line, Some(3));
+        // the injected define keeps its position in the code
+        assert_eq!((log[2].file.as_ref(), log[2].line), (None, Some(2)));
+        assert_eq!(log[2].to_string(), "0:2: error: 'A' : redefinition");
+        assert_eq!((log[3].file.as_ref(), log[3].line), (None, None));
+        let _ = fs::remove_dir_all(dir);
+    }
 }
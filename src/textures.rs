@@ -1,29 +1,117 @@
-use gl::types::GLuint;
-use noise::
-    utils::NoiseMap
-;
-use ultraviolet::Vec4;
+use std::path::Path;
 
-use crate::functions::get_error;
+use gl::types::{GLenum, GLuint};
+use noise::utils::NoiseMap;
+use ultraviolet::{IVec2, Vec4};
+
+use crate::{
+    debug,
+    error::{Error, Result},
+    functions::get_error,
+    resources::{self, ResourceKind},
+    screenshot::RgbaImage,
+};
 
 /// Holds a texture object
-pub struct Texture(pub GLuint);
+pub struct Texture(GLuint);
 impl Texture {
     /// Tries to generate a new texture object
-    pub fn new() -> Option<Self> {
+    pub fn new() -> Result<Self> {
         let mut tex = 0;
         unsafe { gl::GenTextures(1, &mut tex) }
-        if tex != 0 { Some(Self(tex)) } else { None }
+        if tex != 0 {
+            Ok(Self::from_raw(tex))
+        } else {
+            Err(Error::gl("glGenTextures"))
+        }
     }
 
     /// Binds this texture to the given target
     pub fn bind(&self, ty: TextureType) {
         unsafe { gl::BindTexture(ty as _, self.0) }
+        resources::bound(ResourceKind::Texture, self.0, ty as _);
+    }
+
+    /// Loads a PNG, BMP or netpbm image into a new 2D texture with mipmaps, repeat wrapping and trilinear filtering.
+    /// Leaves the texture bound
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Texture::load_with(path.as_ref(), Texture::fill_image)
+    }
+
+    /// Like `Texture::from_file`, but for colour images stored in sRGB, such as diffuse and emissive maps
+    pub fn from_file_srgb(path: impl AsRef<Path>) -> Result<Self> {
+        Texture::load_with(path.as_ref(), Texture::fill_image_srgb)
+    }
+
+    fn load_with(path: &Path, fill: fn(&RgbaImage)) -> Result<Self> {
+        let image = RgbaImage::load(path).map_err(Error::io(path))?;
+        let tex = Texture::new()?;
+        tex.bind(TextureType::Tex2d);
+        fill(&image);
+        Texture::gen_mipmap(TextureType::Tex2d);
+        Texture::set_dual_wrap_behaviour(TextureType::Tex2d, TexWrapBehaviour::Repeat);
+        Texture::set_scale_behaviour(
+            TextureType::Tex2d,
+            TexScaleType::Minify,
+            TexScaleOp::LinearMipmapLinear,
+        )?;
+        Texture::set_scale_behaviour(
+            TextureType::Tex2d,
+            TexScaleType::Magnify,
+            TexScaleOp::Linear,
+        )?;
+        Ok(tex)
+    }
+
+    /// Creates a 2D texture of `size` with uninitialised contents, for rendering into.
+    /// Filters linearly and clamps to the edge. Leaves the texture bound
+    pub fn empty(format: TextureFormat, size: IVec2) -> Result<Self> {
+        let tex = Texture::new()?;
+        tex.bind(TextureType::Tex2d);
+        Texture::allocate(format, size);
+        Texture::set_dual_wrap_behaviour(TextureType::Tex2d, TexWrapBehaviour::ClampToEdge);
+        Texture::set_dual_scale_behaviour(TextureType::Tex2d, TexScaleOp::Linear)?;
+        Ok(tex)
+    }
+
+    /// (Re)allocates the active Tex2d's storage as `size` texels of `format`, discarding its contents
+    pub fn allocate(format: TextureFormat, size: IVec2) {
+        let (pixel_format, pixel_type) = format.pixel_transfer();
+        unsafe {
+            gl::TexImage2D(
+                TextureType::Tex2d as _,
+                0,
+                format.internal_format() as _,
+                size.x,
+                size.y,
+                0,
+                pixel_format,
+                pixel_type,
+                std::ptr::null(),
+            )
+        };
+    }
+
+    /// The GL name of the texture
+    pub fn id(&self) -> GLuint {
+        self.0
     }
 
-    /// Deletes this texture
-    pub fn delete(self) {
-        unsafe { gl::DeleteTextures(1, self.0 as _) }
+    /// Takes ownership of an existing texture, which will be deleted when dropped
+    pub fn from_raw(id: GLuint) -> Self {
+        resources::created(ResourceKind::Texture, id);
+        Self(id)
+    }
+
+    /// Gives up ownership of the texture without deleting it
+    pub fn into_raw(self) -> GLuint {
+        resources::released(ResourceKind::Texture, self.0);
+        std::mem::ManuallyDrop::new(self).0
+    }
+
+    /// Names the texture in debug messages, debugging tools and resource dumps
+    pub fn set_label(&self, label: &str) -> Result<()> {
+        debug::label_object(ResourceKind::Texture, self.0, label)
     }
 
     /// Generate mipmaps
@@ -43,22 +131,27 @@ impl Texture {
     }
 
     /// Sets texture scale behaviour for the given target
-    pub fn set_scale_behaviour(ty: TextureType, scale: TexScaleType, behaviour: TexScaleOp) {
+    pub fn set_scale_behaviour(
+        ty: TextureType,
+        scale: TexScaleType,
+        behaviour: TexScaleOp,
+    ) -> Result<()> {
         if scale == TexScaleType::Magnify
             && !(behaviour == TexScaleOp::Nearest || behaviour == TexScaleOp::Linear)
         {
-            return println!(
-                "{:?} is not a valid magnification scaling behaviour. operation aborted",
+            return Err(Error::InvalidParameter(format!(
+                "{:?} is not a valid magnification scaling behaviour",
                 behaviour
-            );
+            )));
         }
         unsafe { gl::TexParameteri(ty as _, scale as _, behaviour as _) }
+        Ok(())
     }
 
     /// Shortcut for setting scale behaviour in both up- and down-scales
-    pub fn set_dual_scale_behaviour(ty: TextureType, behaviour: TexScaleOp) {
-        Texture::set_scale_behaviour(ty, TexScaleType::Minify, behaviour);
-        Texture::set_scale_behaviour(ty, TexScaleType::Magnify, behaviour);
+    pub fn set_dual_scale_behaviour(ty: TextureType, behaviour: TexScaleOp) -> Result<()> {
+        Texture::set_scale_behaviour(ty, TexScaleType::Minify, behaviour)?;
+        Texture::set_scale_behaviour(ty, TexScaleType::Magnify, behaviour)
     }
 
     /// Sets border colour for TexWrapBehaviour.ClampToBorder
@@ -67,7 +160,7 @@ impl Texture {
     }
 
     /// Fills the active Tex2d with noise
-    pub fn fill_noise(size: usize, mut map: NoiseMap) {
+    pub fn fill_noise(size: usize, mut map: NoiseMap) -> Result<()> {
         let mut minv = f64::MAX;
         let mut maxv = f64::MIN;
 
@@ -81,8 +174,6 @@ impl Texture {
 
         let t_size = size as i32;
 
-        get_error(Some("pre-teximage2d"));
-
         unsafe {
             gl::TexImage2D(
                 TextureType::Tex2d as _,
@@ -97,7 +188,49 @@ impl Texture {
             )
         };
 
-        get_error(Some("post-teximage2d"));
+        get_error("glTexImage2D")
+    }
+
+    /// Fills the active Tex2d with an RGBA8 image. The image's bottom row lands at texture coordinate t = 0
+    pub fn fill_image(image: &RgbaImage) {
+        Texture::fill_image_as(image, gl::RGBA8);
+    }
+
+    /// Like `Texture::fill_image`, but marks the colour channels as sRGB so sampling returns linear values
+    pub fn fill_image_srgb(image: &RgbaImage) {
+        Texture::fill_image_as(image, gl::SRGB8_ALPHA8);
+    }
+
+    fn fill_image_as(image: &RgbaImage, internal_format: GLenum) {
+        let rows: Vec<[u8; 4]> = image
+            .pixels
+            .chunks_exact(image.width.max(1) as usize)
+            .rev()
+            .flatten()
+            .copied()
+            .collect();
+        unsafe {
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexImage2D(
+                TextureType::Tex2d as _,
+                0,
+                internal_format as _,
+                image.width.try_into().unwrap(),
+                image.height.try_into().unwrap(),
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                rows.as_ptr().cast(),
+            )
+        };
+    }
+}
+
+/// Deletes the texture
+impl Drop for Texture {
+    fn drop(&mut self) {
+        resources::released(ResourceKind::Texture, self.0);
+        unsafe { gl::DeleteTextures(1, &self.0) }
     }
 }
 
@@ -166,3 +299,66 @@ pub enum TexScaleOp {
     /// only available for minification operations.
     LinearMipmapLinear = gl::LINEAR_MIPMAP_LINEAR as isize,
 }
+
+/// Storage formats for textures and renderbuffers rendered into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureFormat {
+    /// 8-bit RGBA, the usual colour target
+    Rgba8,
+    /// 8-bit RGBA in sRGB space, encoded when written and decoded when sampled
+    Srgb8Alpha8,
+    /// Half float RGBA, for HDR colour and G-buffer normals
+    Rgba16F,
+    /// Full float RGBA, for G-buffer positions
+    Rgba32F,
+    /// Half float RG
+    Rg16F,
+    /// Single float channel
+    R32F,
+    /// 24-bit depth
+    Depth24,
+    /// Float depth, for shadow maps
+    Depth32F,
+    /// 24-bit depth with 8-bit stencil
+    Depth24Stencil8,
+}
+
+impl TextureFormat {
+    /// The sized internal format GL stores this as
+    pub fn internal_format(self) -> GLenum {
+        match self {
+            Self::Rgba8 => gl::RGBA8,
+            Self::Srgb8Alpha8 => gl::SRGB8_ALPHA8,
+            Self::Rgba16F => gl::RGBA16F,
+            Self::Rgba32F => gl::RGBA32F,
+            Self::Rg16F => gl::RG16F,
+            Self::R32F => gl::R32F,
+            Self::Depth24 => gl::DEPTH_COMPONENT24,
+            Self::Depth32F => gl::DEPTH_COMPONENT32F,
+            Self::Depth24Stencil8 => gl::DEPTH24_STENCIL8,
+        }
+    }
+
+    /// Pixel format & type to pass alongside a null pointer when allocating storage
+    fn pixel_transfer(self) -> (GLenum, GLenum) {
+        match self {
+            Self::Rgba8 | Self::Srgb8Alpha8 => (gl::RGBA, gl::UNSIGNED_BYTE),
+            Self::Rgba16F | Self::Rgba32F => (gl::RGBA, gl::FLOAT),
+            Self::Rg16F => (gl::RG, gl::FLOAT),
+            Self::R32F => (gl::RED, gl::FLOAT),
+            Self::Depth24 => (gl::DEPTH_COMPONENT, gl::UNSIGNED_INT),
+            Self::Depth32F => (gl::DEPTH_COMPONENT, gl::FLOAT),
+            Self::Depth24Stencil8 => (gl::DEPTH_STENCIL, gl::UNSIGNED_INT_24_8),
+        }
+    }
+
+    /// Whether this is a depth or depth/stencil format
+    pub fn is_depth(self) -> bool {
+        matches!(self, Self::Depth24 | Self::Depth32F | Self::Depth24Stencil8)
+    }
+
+    /// Whether this format has a stencil component
+    pub fn has_stencil(self) -> bool {
+        self == Self::Depth24Stencil8
+    }
+}
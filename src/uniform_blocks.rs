@@ -0,0 +1,406 @@
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    sync::{Mutex, OnceLock},
+};
+
+use ultraviolet::{IVec2, IVec3, IVec4, Mat2, Mat3, Mat4, UVec2, UVec3, UVec4, Vec2, Vec3, Vec4};
+
+use crate::{
+    error::Result,
+    gl_objects::{self, Buffer, BufferType},
+};
+
+pub use magiebleue_derive::ShaderBlock;
+
+/// Memory layouts of interface blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockLayout {
+    /// `layout(std140)`, the layout uniform blocks can rely on. Arrays and structs are aligned to 16 bytes
+    Std140,
+    /// `layout(std430)`, the tighter layout of shader storage blocks
+    Std430,
+}
+
+impl BlockLayout {
+    /// Alignment of array elements whose type aligns to `align`
+    pub fn array_align(self, align: usize) -> usize {
+        match self {
+            BlockLayout::Std140 => align.next_multiple_of(16),
+            BlockLayout::Std430 => align,
+        }
+    }
+
+    /// Alignment of a struct whose most aligned member aligns to `align`
+    pub fn struct_align(self, align: usize) -> usize {
+        self.array_align(align)
+    }
+}
+
+/// A Rust type with a GLSL counterpart in interface blocks.
+/// Derive it for structs whose fields are declared in the same order in GLSL
+pub trait ShaderBlock {
+    /// Alignment of the type's base offset in bytes
+    fn align(layout: BlockLayout) -> usize;
+
+    /// Writes the value at the writer's current offset, which is already aligned
+    fn write(&self, writer: &mut BlockWriter);
+
+    /// Encodes the value as a whole block
+    fn to_bytes(&self, layout: BlockLayout) -> Vec<u8> {
+        let mut writer = BlockWriter::new(layout);
+        writer.push(self);
+        writer.into_bytes()
+    }
+}
+
+/// Builds the bytes of a block member by member, padding each to its alignment
+#[derive(Debug, Clone)]
+pub struct BlockWriter {
+    layout: BlockLayout,
+    bytes: Vec<u8>,
+}
+
+impl BlockWriter {
+    pub fn new(layout: BlockLayout) -> Self {
+        Self {
+            layout,
+            bytes: Vec::new(),
+        }
+    }
+
+    pub fn layout(&self) -> BlockLayout {
+        self.layout
+    }
+
+    /// Offset of the next byte written
+    pub fn offset(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Pads with zeros up to a multiple of `align`
+    pub fn align_to(&mut self, align: usize) {
+        let offset = self.offset().next_multiple_of(align);
+        self.bytes.resize(offset, 0);
+    }
+
+    /// Appends `bytes` without padding
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// Aligns to `value`'s type and writes it, as the next member of the block
+    pub fn push<T: ShaderBlock + ?Sized>(&mut self, value: &T) {
+        self.align_to(T::align(self.layout));
+        value.write(self);
+    }
+
+    /// Writes each element at the array stride of the layout
+    pub fn push_array<T: ShaderBlock>(&mut self, values: &[T]) {
+        let align = self.layout.array_align(T::align(self.layout));
+        for value in values {
+            self.align_to(align);
+            value.write(self);
+        }
+        self.align_to(align);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+macro_rules! scalar_block {
+    ($($ty:ty => $align:expr),* $(,)?) => {
+        $(
+            impl ShaderBlock for $ty {
+                fn align(_layout: BlockLayout) -> usize {
+                    $align
+                }
+
+                fn write(&self, writer: &mut BlockWriter) {
+                    writer.write_bytes(bytemuck::bytes_of(self));
+                }
+            }
+        )*
+    };
+}
+
+scalar_block!(
+    f32 => 4,
+    i32 => 4,
+    u32 => 4,
+    Vec2 => 8,
+    IVec2 => 8,
+    UVec2 => 8,
+    // vec3s align like vec4s but are only 12 bytes, a following scalar fits in the gap
+    Vec3 => 16,
+    IVec3 => 16,
+    UVec3 => 16,
+    Vec4 => 16,
+    IVec4 => 16,
+    UVec4 => 16,
+);
+
+/// GLSL bools are 4 bytes in blocks
+impl ShaderBlock for bool {
+    fn align(_layout: BlockLayout) -> usize {
+        4
+    }
+
+    fn write(&self, writer: &mut BlockWriter) {
+        u32::from(*self).write(writer);
+    }
+}
+
+/// Matrices are stored as arrays of their columns
+macro_rules! matrix_block {
+    ($($ty:ty => $column:ty),* $(,)?) => {
+        $(
+            impl ShaderBlock for $ty {
+                fn align(layout: BlockLayout) -> usize {
+                    layout.array_align(<$column>::align(layout))
+                }
+
+                fn write(&self, writer: &mut BlockWriter) {
+                    writer.push_array(&self.cols);
+                }
+            }
+        )*
+    };
+}
+
+matrix_block!(Mat2 => Vec2, Mat3 => Vec3, Mat4 => Vec4);
+
+impl<T: ShaderBlock, const N: usize> ShaderBlock for [T; N] {
+    fn align(layout: BlockLayout) -> usize {
+        layout.array_align(T::align(layout))
+    }
+
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.push_array(self);
+    }
+}
+
+/// For the unsized array that may end a shader storage block
+impl<T: ShaderBlock> ShaderBlock for [T] {
+    fn align(layout: BlockLayout) -> usize {
+        layout.array_align(T::align(layout))
+    }
+
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.push_array(self);
+    }
+}
+
+impl<T: ShaderBlock> ShaderBlock for Vec<T> {
+    fn align(layout: BlockLayout) -> usize {
+        <[T]>::align(layout)
+    }
+
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.push_array(self);
+    }
+}
+
+/// Binding points handed out to block names by `binding_point`
+fn binding_points() -> &'static Mutex<HashMap<String, u32>> {
+    static POINTS: OnceLock<Mutex<HashMap<String, u32>>> = OnceLock::new();
+    POINTS.get_or_init(Mutex::default)
+}
+
+/// The uniform buffer binding point shared by every block called `block`, assigned from 0 the first time it's asked for.
+/// Programs bind their blocks to these with `ShaderProgram::bind_shared_blocks`, buffers with `UniformBuffer::shared`
+pub fn binding_point(block: &str) -> u32 {
+    let mut points = binding_points()
+        .lock()
+        .expect("binding points aren't poisoned");
+    let next = points.len() as u32;
+    *points.entry(block.to_owned()).or_insert(next)
+}
+
+/// A uniform buffer holding one std140-encoded `T`, bound to a binding point
+pub struct UniformBuffer<T: ShaderBlock> {
+    pub buffer: Buffer,
+    pub binding: u32,
+    value: PhantomData<T>,
+}
+
+impl<T: ShaderBlock> UniformBuffer<T> {
+    /// Uploads `value` into a new buffer bound to binding point `binding`
+    pub fn new(binding: u32, value: &T) -> Result<Self> {
+        let buffer = Buffer::new()?;
+        buffer.bind(BufferType::Uniform);
+        gl_objects::buffer_data(
+            BufferType::Uniform,
+            &value.to_bytes(BlockLayout::Std140),
+            gl::DYNAMIC_DRAW,
+        );
+        let uniform_buffer = Self {
+            buffer,
+            binding,
+            value: PhantomData,
+        };
+        uniform_buffer.bind();
+        Ok(uniform_buffer)
+    }
+
+    /// Uploads `value` into a new buffer bound to the shared binding point of blocks called `block`
+    pub fn shared(block: &str, value: &T) -> Result<Self> {
+        let buffer = Self::new(binding_point(block), value)?;
+        buffer.buffer.set_label(block)?;
+        Ok(buffer)
+    }
+
+    /// Replaces the buffer's contents with `value`, reallocating if its encoded size changed
+    pub fn update(&self, value: &T) {
+        let bytes = value.to_bytes(BlockLayout::Std140);
+        if self.buffer.size(BufferType::Uniform) == bytes.len() {
+            gl_objects::buffer_sub_data(BufferType::Uniform, 0, &bytes);
+        } else {
+            gl_objects::buffer_data(BufferType::Uniform, &bytes, gl::DYNAMIC_DRAW);
+        }
+    }
+
+    /// Binds the buffer to its binding point again, if another buffer took it
+    pub fn bind(&self) {
+        self.buffer.bind_base(BufferType::Uniform, self.binding);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(ShaderBlock)]
+    struct Vec3ThenFloat {
+        v: Vec3,
+        f: f32,
+    }
+
+    #[derive(ShaderBlock)]
+    struct ArrayThenFloat {
+        a: [f32; 3],
+        f: f32,
+    }
+
+    #[derive(ShaderBlock)]
+    struct Inner {
+        v: Vec3,
+    }
+
+    #[derive(ShaderBlock)]
+    struct Outer {
+        inner: Inner,
+        f: f32,
+    }
+
+    #[derive(ShaderBlock)]
+    struct Scalar {
+        f: f32,
+    }
+
+    #[derive(ShaderBlock)]
+    struct ScalarThenFloat {
+        inner: Scalar,
+        f: f32,
+    }
+
+    /// The f32 at byte `offset`
+    fn float_at(bytes: &[u8], offset: usize) -> f32 {
+        bytemuck::pod_read_unaligned(&bytes[offset..offset + 4])
+    }
+
+    #[test]
+    fn float_fills_the_gap_after_vec3() {
+        let value = Vec3ThenFloat {
+            v: Vec3::new(1.0, 2.0, 3.0),
+            f: 4.0,
+        };
+        for layout in [BlockLayout::Std140, BlockLayout::Std430] {
+            let bytes = value.to_bytes(layout);
+            assert_eq!(bytes.len(), 16);
+            assert_eq!(float_at(&bytes, 8), 3.0);
+            assert_eq!(float_at(&bytes, 12), 4.0);
+        }
+    }
+
+    #[test]
+    fn std140_arrays_stride_16() {
+        let value = ArrayThenFloat {
+            a: [1.0, 2.0, 3.0],
+            f: 4.0,
+        };
+        let bytes = value.to_bytes(BlockLayout::Std140);
+        assert_eq!(bytes.len(), 64);
+        assert_eq!(float_at(&bytes, 16), 2.0);
+        assert_eq!(float_at(&bytes, 32), 3.0);
+        assert_eq!(float_at(&bytes, 48), 4.0);
+
+        let bytes = value.to_bytes(BlockLayout::Std430);
+        assert_eq!(bytes.len(), 16);
+        assert_eq!(float_at(&bytes, 4), 2.0);
+        assert_eq!(float_at(&bytes, 8), 3.0);
+        assert_eq!(float_at(&bytes, 12), 4.0);
+    }
+
+    #[test]
+    fn mat3_columns_are_padded_to_vec4() {
+        let matrix = Mat3::new(
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(4.0, 5.0, 6.0),
+            Vec3::new(7.0, 8.0, 9.0),
+        );
+        for layout in [BlockLayout::Std140, BlockLayout::Std430] {
+            let bytes = matrix.to_bytes(layout);
+            assert_eq!(bytes.len(), 48);
+            assert_eq!(float_at(&bytes, 16), 4.0);
+            assert_eq!(float_at(&bytes, 40), 9.0);
+            assert_eq!(float_at(&bytes, 44), 0.0);
+        }
+    }
+
+    #[test]
+    fn mat2_columns_are_padded_only_in_std140() {
+        let matrix = Mat2::new(Vec2::new(1.0, 2.0), Vec2::new(3.0, 4.0));
+        let bytes = matrix.to_bytes(BlockLayout::Std140);
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(float_at(&bytes, 16), 3.0);
+        assert_eq!(float_at(&bytes, 20), 4.0);
+
+        let bytes = matrix.to_bytes(BlockLayout::Std430);
+        assert_eq!(bytes.len(), 16);
+        assert_eq!(float_at(&bytes, 8), 3.0);
+        assert_eq!(float_at(&bytes, 12), 4.0);
+    }
+
+    #[test]
+    fn nested_structs_are_padded_to_their_alignment() {
+        // unlike a bare vec3, a struct ending in one doesn't leave its tail for the next member
+        let value = Outer {
+            inner: Inner {
+                v: Vec3::new(1.0, 2.0, 3.0),
+            },
+            f: 4.0,
+        };
+        for layout in [BlockLayout::Std140, BlockLayout::Std430] {
+            let bytes = value.to_bytes(layout);
+            assert_eq!(bytes.len(), 32);
+            assert_eq!(float_at(&bytes, 12), 0.0);
+            assert_eq!(float_at(&bytes, 16), 4.0);
+        }
+
+        // std140 rounds struct alignment up to 16, std430 keeps the members' alignment
+        let value = ScalarThenFloat {
+            inner: Scalar { f: 1.0 },
+            f: 2.0,
+        };
+        let bytes = value.to_bytes(BlockLayout::Std140);
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(float_at(&bytes, 16), 2.0);
+        assert_eq!(
+            value.to_bytes(BlockLayout::Std430),
+            bytemuck::bytes_of(&[1.0_f32, 2.0])
+        );
+    }
+}
@@ -0,0 +1,331 @@
+use gl::types::{GLenum, GLint, GLsizei};
+use ultraviolet::{IVec2, IVec3, IVec4, Mat2, Mat3, Mat4, UVec2, UVec3, UVec4, Vec2, Vec3, Vec4};
+
+/// A single value of a uniform, or one element of a uniform array
+pub trait UniformValue: Copy {
+    /// Whether a uniform declared as the GL type `ty` can be set from this type
+    fn accepts(ty: GLenum) -> bool;
+
+    /// Uploads `values` to consecutive array elements from `location` of the active program
+    fn upload(location: GLint, values: &[Self]);
+}
+
+/// Anything `ShaderProgram::set_uniform` takes: a single value, or an array, slice or `Vec` of them
+pub trait Uniform {
+    type Value: UniformValue;
+
+    /// The values to upload, from the first array element
+    fn values(&self) -> &[Self::Value];
+}
+
+macro_rules! uniform_value {
+    ($ty:ty, $accepts:pat, $upload:ident, $component:ty) => {
+        impl UniformValue for $ty {
+            fn accepts(ty: GLenum) -> bool {
+                matches!(ty, $accepts)
+            }
+
+            fn upload(location: GLint, values: &[Self]) {
+                let components: &[$component] = bytemuck::cast_slice(values);
+                unsafe { gl::$upload(location, values.len() as GLsizei, components.as_ptr()) }
+            }
+        }
+        single_uniform!($ty);
+    };
+}
+
+macro_rules! matrix_uniform_value {
+    ($ty:ty, $gl_type:path, $upload:ident) => {
+        impl UniformValue for $ty {
+            fn accepts(ty: GLenum) -> bool {
+                ty == $gl_type
+            }
+
+            fn upload(location: GLint, values: &[Self]) {
+                let components: &[f32] = bytemuck::cast_slice(values);
+                unsafe {
+                    gl::$upload(
+                        location,
+                        values.len() as GLsizei,
+                        gl::FALSE,
+                        components.as_ptr(),
+                    )
+                }
+            }
+        }
+        single_uniform!($ty);
+    };
+}
+
+macro_rules! single_uniform {
+    ($ty:ty) => {
+        impl Uniform for $ty {
+            type Value = Self;
+
+            fn values(&self) -> &[Self] {
+                std::slice::from_ref(self)
+            }
+        }
+    };
+}
+
+uniform_value!(f32, gl::FLOAT | gl::BOOL, Uniform1fv, f32);
+uniform_value!(Vec2, gl::FLOAT_VEC2 | gl::BOOL_VEC2, Uniform2fv, f32);
+uniform_value!(Vec3, gl::FLOAT_VEC3 | gl::BOOL_VEC3, Uniform3fv, f32);
+uniform_value!(Vec4, gl::FLOAT_VEC4 | gl::BOOL_VEC4, Uniform4fv, f32);
+uniform_value!(IVec2, gl::INT_VEC2 | gl::BOOL_VEC2, Uniform2iv, i32);
+uniform_value!(IVec3, gl::INT_VEC3 | gl::BOOL_VEC3, Uniform3iv, i32);
+uniform_value!(IVec4, gl::INT_VEC4 | gl::BOOL_VEC4, Uniform4iv, i32);
+uniform_value!(u32, gl::UNSIGNED_INT | gl::BOOL, Uniform1uiv, u32);
+uniform_value!(
+    UVec2,
+    gl::UNSIGNED_INT_VEC2 | gl::BOOL_VEC2,
+    Uniform2uiv,
+    u32
+);
+uniform_value!(
+    UVec3,
+    gl::UNSIGNED_INT_VEC3 | gl::BOOL_VEC3,
+    Uniform3uiv,
+    u32
+);
+uniform_value!(
+    UVec4,
+    gl::UNSIGNED_INT_VEC4 | gl::BOOL_VEC4,
+    Uniform4uiv,
+    u32
+);
+matrix_uniform_value!(Mat2, gl::FLOAT_MAT2, UniformMatrix2fv);
+matrix_uniform_value!(Mat3, gl::FLOAT_MAT3, UniformMatrix3fv);
+matrix_uniform_value!(Mat4, gl::FLOAT_MAT4, UniformMatrix4fv);
+
+/// Also sets samplers and images, to the texture unit or image unit they read from
+impl UniformValue for i32 {
+    fn accepts(ty: GLenum) -> bool {
+        matches!(ty, gl::INT | gl::BOOL) || is_opaque(ty)
+    }
+
+    fn upload(location: GLint, values: &[Self]) {
+        unsafe { gl::Uniform1iv(location, values.len() as GLsizei, values.as_ptr()) }
+    }
+}
+single_uniform!(i32);
+
+impl UniformValue for bool {
+    fn accepts(ty: GLenum) -> bool {
+        ty == gl::BOOL
+    }
+
+    fn upload(location: GLint, values: &[Self]) {
+        let ints: Vec<GLint> = values.iter().map(|&b| b.into()).collect();
+        i32::upload(location, &ints);
+    }
+}
+single_uniform!(bool);
+
+impl<T: UniformValue, const N: usize> Uniform for [T; N] {
+    type Value = T;
+
+    fn values(&self) -> &[T] {
+        self
+    }
+}
+
+impl<T: UniformValue> Uniform for [T] {
+    type Value = T;
+
+    fn values(&self) -> &[T] {
+        self
+    }
+}
+
+impl<T: UniformValue> Uniform for Vec<T> {
+    type Value = T;
+
+    fn values(&self) -> &[T] {
+        self
+    }
+}
+
+impl<U: Uniform + ?Sized> Uniform for &U {
+    type Value = U::Value;
+
+    fn values(&self) -> &[U::Value] {
+        (**self).values()
+    }
+}
+
+/// A uniform of a linked program, as reported by `glGetActiveUniform`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActiveUniform {
+    /// -1 for uniforms in a uniform block, which are set through its buffer instead
+    pub location: GLint,
+    /// GL type the uniform is declared as, e.g. `GL_FLOAT_VEC3`
+    pub ty: GLenum,
+    /// Number of array elements from this one to the end of the array, 1 for non-arrays
+    pub size: usize,
+}
+
+/// Whether `ty` is a sampler or image type, which are set with the unit they read from
+pub fn is_opaque(ty: GLenum) -> bool {
+    let name = glsl_type_name(ty);
+    [
+        "sampler", "isampler", "usampler", "image", "iimage", "uimage",
+    ]
+    .iter()
+    .any(|prefix| name.starts_with(prefix))
+}
+
+/// The GLSL name of a uniform or attribute type, e.g. `vec3` for `GL_FLOAT_VEC3`
+pub fn glsl_type_name(ty: GLenum) -> &'static str {
+    match ty {
+        gl::FLOAT => "float",
+        gl::FLOAT_VEC2 => "vec2",
+        gl::FLOAT_VEC3 => "vec3",
+        gl::FLOAT_VEC4 => "vec4",
+        gl::DOUBLE => "double",
+        gl::DOUBLE_VEC2 => "dvec2",
+        gl::DOUBLE_VEC3 => "dvec3",
+        gl::DOUBLE_VEC4 => "dvec4",
+        gl::INT => "int",
+        gl::INT_VEC2 => "ivec2",
+        gl::INT_VEC3 => "ivec3",
+        gl::INT_VEC4 => "ivec4",
+        gl::UNSIGNED_INT => "uint",
+        gl::UNSIGNED_INT_VEC2 => "uvec2",
+        gl::UNSIGNED_INT_VEC3 => "uvec3",
+        gl::UNSIGNED_INT_VEC4 => "uvec4",
+        gl::BOOL => "bool",
+        gl::BOOL_VEC2 => "bvec2",
+        gl::BOOL_VEC3 => "bvec3",
+        gl::BOOL_VEC4 => "bvec4",
+        gl::FLOAT_MAT2 => "mat2",
+        gl::FLOAT_MAT3 => "mat3",
+        gl::FLOAT_MAT4 => "mat4",
+        gl::FLOAT_MAT2x3 => "mat2x3",
+        gl::FLOAT_MAT2x4 => "mat2x4",
+        gl::FLOAT_MAT3x2 => "mat3x2",
+        gl::FLOAT_MAT3x4 => "mat3x4",
+        gl::FLOAT_MAT4x2 => "mat4x2",
+        gl::FLOAT_MAT4x3 => "mat4x3",
+        gl::DOUBLE_MAT2 => "dmat2",
+        gl::DOUBLE_MAT3 => "dmat3",
+        gl::DOUBLE_MAT4 => "dmat4",
+        gl::SAMPLER_1D => "sampler1D",
+        gl::SAMPLER_2D => "sampler2D",
+        gl::SAMPLER_3D => "sampler3D",
+        gl::SAMPLER_CUBE => "samplerCube",
+        gl::SAMPLER_1D_SHADOW => "sampler1DShadow",
+        gl::SAMPLER_2D_SHADOW => "sampler2DShadow",
+        gl::SAMPLER_1D_ARRAY => "sampler1DArray",
+        gl::SAMPLER_2D_ARRAY => "sampler2DArray",
+        gl::SAMPLER_1D_ARRAY_SHADOW => "sampler1DArrayShadow",
+        gl::SAMPLER_2D_ARRAY_SHADOW => "sampler2DArrayShadow",
+        gl::SAMPLER_2D_MULTISAMPLE => "sampler2DMS",
+        gl::SAMPLER_2D_MULTISAMPLE_ARRAY => "sampler2DMSArray",
+        gl::SAMPLER_CUBE_SHADOW => "samplerCubeShadow",
+        gl::SAMPLER_CUBE_MAP_ARRAY => "samplerCubeArray",
+        gl::SAMPLER_BUFFER => "samplerBuffer",
+        gl::SAMPLER_2D_RECT => "sampler2DRect",
+        gl::INT_SAMPLER_2D => "isampler2D",
+        gl::INT_SAMPLER_3D => "isampler3D",
+        gl::INT_SAMPLER_CUBE => "isamplerCube",
+        gl::INT_SAMPLER_2D_ARRAY => "isampler2DArray",
+        gl::INT_SAMPLER_BUFFER => "isamplerBuffer",
+        gl::UNSIGNED_INT_SAMPLER_2D => "usampler2D",
+        gl::UNSIGNED_INT_SAMPLER_3D => "usampler3D",
+        gl::UNSIGNED_INT_SAMPLER_CUBE => "usamplerCube",
+        gl::UNSIGNED_INT_SAMPLER_2D_ARRAY => "usampler2DArray",
+        gl::UNSIGNED_INT_SAMPLER_BUFFER => "usamplerBuffer",
+        gl::IMAGE_2D => "image2D",
+        gl::IMAGE_3D => "image3D",
+        gl::IMAGE_CUBE => "imageCube",
+        gl::IMAGE_2D_ARRAY => "image2DArray",
+        gl::IMAGE_BUFFER => "imageBuffer",
+        gl::INT_IMAGE_2D => "iimage2D",
+        gl::INT_IMAGE_3D => "iimage3D",
+        gl::UNSIGNED_INT_IMAGE_2D => "uimage2D",
+        gl::UNSIGNED_INT_IMAGE_3D => "uimage3D",
+        gl::UNSIGNED_INT_ATOMIC_COUNTER => "atomic_uint",
+        _ => "unknown type",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_accept_their_glsl_types() {
+        assert!(f32::accepts(gl::FLOAT));
+        assert!(!f32::accepts(gl::INT));
+        assert!(!f32::accepts(gl::DOUBLE));
+        assert!(Vec3::accepts(gl::FLOAT_VEC3));
+        assert!(!Vec3::accepts(gl::FLOAT_VEC4));
+        assert!(IVec2::accepts(gl::INT_VEC2));
+        assert!(!IVec2::accepts(gl::UNSIGNED_INT_VEC2));
+        assert!(u32::accepts(gl::UNSIGNED_INT));
+        assert!(!u32::accepts(gl::INT));
+        assert!(UVec4::accepts(gl::UNSIGNED_INT_VEC4));
+        assert!(Mat4::accepts(gl::FLOAT_MAT4));
+        assert!(!Mat4::accepts(gl::FLOAT_MAT3));
+        assert!(!Mat3::accepts(gl::FLOAT_MAT3x4));
+    }
+
+    #[test]
+    fn bools_take_any_scalar_type() {
+        assert!(bool::accepts(gl::BOOL));
+        assert!(!bool::accepts(gl::INT));
+        for ty in [gl::BOOL, gl::INT] {
+            assert!(i32::accepts(ty));
+        }
+        assert!(f32::accepts(gl::BOOL));
+        assert!(u32::accepts(gl::BOOL));
+        assert!(Vec4::accepts(gl::BOOL_VEC4));
+        assert!(IVec3::accepts(gl::BOOL_VEC3));
+        assert!(!Vec2::accepts(gl::BOOL_VEC3));
+    }
+
+    #[test]
+    fn samplers_and_images_are_set_with_ints() {
+        for ty in [
+            gl::SAMPLER_2D,
+            gl::SAMPLER_CUBE_SHADOW,
+            gl::INT_SAMPLER_2D,
+            gl::UNSIGNED_INT_SAMPLER_BUFFER,
+            gl::IMAGE_2D,
+            gl::UNSIGNED_INT_IMAGE_3D,
+        ] {
+            assert!(is_opaque(ty), "{}", glsl_type_name(ty));
+            assert!(i32::accepts(ty));
+            assert!(!u32::accepts(ty));
+        }
+        for ty in [gl::INT, gl::FLOAT_MAT4, gl::UNSIGNED_INT_ATOMIC_COUNTER, 0] {
+            assert!(!is_opaque(ty), "{}", glsl_type_name(ty));
+        }
+    }
+
+    #[test]
+    fn names_glsl_types() {
+        assert_eq!(glsl_type_name(gl::FLOAT_VEC3), "vec3");
+        assert_eq!(glsl_type_name(gl::UNSIGNED_INT_VEC2), "uvec2");
+        assert_eq!(glsl_type_name(gl::FLOAT_MAT4x3), "mat4x3");
+        assert_eq!(
+            glsl_type_name(gl::SAMPLER_2D_ARRAY_SHADOW),
+            "sampler2DArrayShadow"
+        );
+        assert_eq!(glsl_type_name(0), "unknown type");
+    }
+
+    #[test]
+    fn arrays_slices_and_vecs_upload_every_element() {
+        fn values<U: Uniform + ?Sized>(uniform: &U) -> &[U::Value] {
+            uniform.values()
+        }
+        assert_eq!(values(&1.5_f32), [1.5]);
+        assert_eq!(values(&[1, 2, 3]), [1, 2, 3]);
+        assert_eq!(values(&[Vec2::one(); 2][..]), [Vec2::one(); 2]);
+        assert_eq!(values(&vec![true, false]), [true, false]);
+        assert_eq!(values(&&[4_u32; 4]), [4; 4]);
+    }
+}
@@ -1,119 +1,1485 @@
-use std::{fs, str::Chars};
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
 
-use ultraviolet::{Vec2, Vec3};
+use ultraviolet::{Vec2, Vec3, Vec4};
+
+use crate::{
+    error::Result,
+    gl_objects::Indices,
+    materials::MaterialLibrary,
+    mesh::{self, NormalMode},
+};
 
 pub type Vertex = [f32; 8];
+/// A `Vertex` followed by its tangent (pos-tex-normal-tangent), see `mesh::interleave_tangents`
+pub type TangentVertex = [f32; 12];
 pub type TriIndex = [usize; 3];
 
-pub fn parse_wavefront(path: &str) -> Result<Option<Vec<Vertex>>, String> {
-    let mut vertex_points = Vec::<Vec3>::new();
-    let mut vertex_texcoords = Vec::<Vec2>::new();
-    let mut vertex_normals = Vec::<Vec3>::new();
-    let mut raw_vertices = Vec::<Vertex>::new();
-
-    let obj_file = fs::read_to_string(path).map_err(|e| format!("Model read error: {}", e))?;
-
-    for line in obj_file.lines() {
-        if line.len() <= 2 {
-            continue;
-        }
-        let mut chars = line.chars();
-        match format!("{}{}", chars.next().unwrap(), chars.next().unwrap()).as_str() {
-            "v " => {
-                vertex_points.push(Vec3 {
-                    x: read_f32(chars.by_ref())?,
-                    y: read_f32(chars.by_ref())?,
-                    z: read_f32(chars.by_ref())?,
-                });
+/// Reads an OBJ file and flattens its faces into a triangle list of vertices (pos-tex-normal).
+/// Normals missing from the file are generated following its smoothing groups
+pub fn parse_wavefront(path: &str, triangulation: Triangulation) -> Result<TriangleList> {
+    let mut obj = Wavefront::from_file(path)?;
+    obj.generate_normals();
+    Ok(obj.triangle_list(triangulation))
+}
+
+/// Reads an OBJ file into a deduplicated vertex buffer (pos-tex-normal) and a triangle index buffer.
+/// Normals missing from the file are generated following its smoothing groups
+pub fn parse_wavefront_indexed(path: &str, triangulation: Triangulation) -> Result<IndexedMesh> {
+    let mut obj = Wavefront::from_file(path)?;
+    obj.generate_normals();
+    Ok(obj.indexed_mesh(triangulation))
+}
+
+/// Reads an OBJ file and the MTL libraries it references into its objects and groups, each split into one indexed submesh per material.
+/// Normals missing from the file are generated following its smoothing groups
+pub fn load_wavefront(path: &str, triangulation: Triangulation) -> Result<WavefrontModel> {
+    let mut obj = Wavefront::from_file(path)?;
+    obj.generate_normals();
+    let materials = obj.load_materials(Path::new(path).parent().unwrap_or(Path::new("")))?;
+    Ok(WavefrontModel {
+        objects: obj.objects(triangulation),
+        materials,
+    })
+}
+
+/// A model split into its objects, with the materials it uses
+#[derive(Debug, Clone, PartialEq)]
+pub struct WavefrontModel {
+    pub objects: Vec<ModelObject>,
+    pub materials: MaterialLibrary,
+}
+impl WavefrontModel {
+    /// Finds an object by the name given to `o`
+    pub fn object(&self, name: &str) -> Option<&ModelObject> {
+        self.objects
+            .iter()
+            .find(|o| o.name.as_deref() == Some(name))
+    }
+
+    /// Iterates over every submesh of every group of every object
+    pub fn submeshes(&self) -> impl Iterator<Item = &Submesh> {
+        self.objects
+            .iter()
+            .flat_map(|o| o.groups.iter())
+            .flat_map(|g| g.submeshes.iter())
+    }
+}
+
+/// The faces declared under one `o` statement
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelObject {
+    /// Name given to `o`, or `None` for faces before any `o`
+    pub name: Option<String>,
+    pub groups: Vec<ModelGroup>,
+}
+impl ModelObject {
+    /// Finds a group that includes `name`
+    pub fn group(&self, name: &str) -> Option<&ModelGroup> {
+        self.groups
+            .iter()
+            .find(|g| g.names.iter().any(|n| n == name))
+    }
+}
+
+/// The faces of an object declared under one `g` statement
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelGroup {
+    /// Names given to `g`. Empty for faces before any `g`
+    pub names: Vec<String>,
+    pub submeshes: Vec<Submesh>,
+}
+
+/// The part of a model drawn with one material
+#[derive(Debug, Clone, PartialEq)]
+pub struct Submesh {
+    /// Name given to `usemtl`, or `None` for faces before any `usemtl`
+    pub material: Option<String>,
+    pub mesh: IndexedMesh,
+}
+
+/// How faces with more than three corners are split into triangles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Triangulation {
+    /// Fans out from the first corner. Cheap, but only correct for convex faces
+    #[default]
+    Fan,
+    /// Repeatedly clips off corners whose triangle contains no other corner. Handles concave faces
+    EarClipping,
+}
+
+/// Vertices ready to be drawn with `Primitive::Triangles`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TriangleList {
+    /// Three vertices (pos-tex-normal) per triangle
+    pub vertices: Vec<Vertex>,
+    /// Triangles created by splitting quads and n-gons, not counting faces that were already triangles
+    pub generated_triangles: usize,
+}
+impl TriangleList {
+    /// Iterates over the triangles as index triples into `vertices`
+    pub fn triangles(&self) -> impl Iterator<Item = TriIndex> + '_ {
+        mesh::list_triangles(self.vertices.len()).into_iter()
+    }
+
+    /// Replaces every vertex's normal. See `mesh::corner_normals`
+    pub fn generate_normals(&mut self, mode: NormalMode) {
+        let triangles = mesh::list_triangles(self.vertices.len());
+        mesh::compute_normals(&mut self.vertices, &triangles, mode);
+    }
+
+    /// Computes a tangent for every vertex. See `mesh::corner_tangents`
+    pub fn tangents(&self) -> Vec<Vec4> {
+        let triangles = mesh::list_triangles(self.vertices.len());
+        mesh::corner_tangents(&self.vertices, &triangles)
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+}
+
+/// Triangles sharing a buffer of unique vertices, ready to be drawn with `Primitive::Triangles` through an element buffer
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedMesh {
+    /// Each distinct combination of position, texture coordinate and normal once
+    pub vertices: Vec<Vertex>,
+    /// Three indices into `vertices` per triangle
+    pub indices: Indices,
+    /// Triangles created by splitting quads and n-gons, not counting faces that were already triangles
+    pub generated_triangles: usize,
+}
+impl IndexedMesh {
+    /// Iterates over the triangles as index triples into `vertices`
+    pub fn triangles(&self) -> impl Iterator<Item = TriIndex> + '_ {
+        (0..self.indices.len() / 3).map(|t| {
+            [0, 1, 2].map(|corner| self.indices.get(t * 3 + corner).unwrap_or_default() as usize)
+        })
+    }
+
+    /// Replaces every vertex's normal, splitting vertices at flat edges and creases. See `mesh::split_normals`
+    pub fn generate_normals(&mut self, mode: NormalMode) {
+        let triangles: Vec<TriIndex> = self.triangles().collect();
+        let generated_triangles = self.generated_triangles;
+        *self = mesh::split_normals(&self.vertices, &triangles, mode);
+        self.generated_triangles = generated_triangles;
+    }
+
+    /// Computes a tangent for every vertex, splitting vertices on UV mirror seams. Returns one tangent per vertex.
+    /// See `mesh::split_tangents`
+    pub fn generate_tangents(&mut self) -> Vec<Vec4> {
+        let triangles: Vec<TriIndex> = self.triangles().collect();
+        let generated_triangles = self.generated_triangles;
+        let (mesh, tangents) = mesh::split_tangents(&self.vertices, &triangles);
+        *self = mesh;
+        self.generated_triangles = generated_triangles;
+        tangents
+    }
+}
+
+/// Geometry read from an OBJ file. All element indices are resolved to 0-based indices into the attribute lists
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Wavefront {
+    /// `v` statements. `w` defaults to 1
+    pub positions: Vec<Vec4>,
+    /// `vt` statements. Missing `v` and `w` default to 0
+    pub texcoords: Vec<Vec3>,
+    /// `vn` statements
+    pub normals: Vec<Vec3>,
+    /// `vp` statements. Missing `v` and `w` default to 0
+    pub parameter_vertices: Vec<Vec3>,
+    /// `f` statements
+    pub faces: Vec<Face>,
+    /// `l` statements
+    pub lines: Vec<Polyline>,
+    /// `p` statements, as indices into `positions`
+    pub points: Vec<Vec<usize>>,
+    /// Files named by `mtllib` statements, relative to the OBJ file
+    pub material_libraries: Vec<String>,
+    /// Names given to `usemtl` statements, in order of first use
+    pub material_names: Vec<String>,
+    /// Names given to `o` statements, in order of first use
+    pub object_names: Vec<String>,
+    /// Names given to each distinct `g` statement, in order of first use
+    pub groups: Vec<Vec<String>>,
+}
+
+/// A polygon with at least three corners
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Face {
+    pub vertices: Vec<FaceVertex>,
+    /// Index into `Wavefront::material_names` of the material active when the face was declared
+    pub material: Option<usize>,
+    /// Index into `Wavefront::object_names` of the object the face belongs to
+    pub object: Option<usize>,
+    /// Index into `Wavefront::groups` of the groups the face belongs to
+    pub group: Option<usize>,
+    /// Smoothing group set by `s`. 0 means `s off`: the face is shaded flat
+    pub smoothing_group: u32,
+}
+
+/// Statements that change how the statements after them are read
+#[derive(Debug, Default)]
+struct ParseState {
+    material: Option<usize>,
+    object: Option<usize>,
+    group: Option<usize>,
+    smoothing_group: u32,
+}
+
+/// A line strip with at least two vertices
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Polyline {
+    pub vertices: Vec<FaceVertex>,
+}
+
+/// One corner of an element, referencing attributes of a `Wavefront` by index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FaceVertex {
+    pub position: usize,
+    pub texcoord: Option<usize>,
+    pub normal: Option<usize>,
+}
+
+impl Wavefront {
+    /// Reads and parses an OBJ file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, WavefrontError> {
+        Self::parse(&fs::read_to_string(path).map_err(WavefrontError::Io)?)
+    }
+
+    /// Parses the geometry statements of OBJ source. Statements this parser doesn't know are skipped
+    pub fn parse(src: &str) -> Result<Self, WavefrontError> {
+        let mut obj = Wavefront::default();
+        let mut state = ParseState::default();
+        for statement in Statements::new(src) {
+            obj.parse_statement(&statement, &mut state)?;
+        }
+        Ok(obj)
+    }
+
+    /// Flattens the faces into a list of vertices (pos-tex-normal), one per face corner, without triangulating
+    pub fn vertices(&self) -> Vec<Vertex> {
+        self.faces
+            .iter()
+            .flat_map(|face| face.vertices.iter())
+            .map(|v| self.vertex(v))
+            .collect()
+    }
+
+    /// Triangulates every face and flattens the result into a triangle list
+    pub fn triangle_list(&self, triangulation: Triangulation) -> TriangleList {
+        let mut list = TriangleList::default();
+        for face in &self.faces {
+            let triangles = self.triangulate(face, triangulation);
+            if face.vertices.len() > 3 {
+                list.generated_triangles += triangles.len();
             }
-            "vn" => {
-                chars.next(); // skip the space after n since we're matching two chars
-                vertex_normals.push(Vec3 {
-                    x: read_f32(chars.by_ref())?,
-                    y: read_f32(chars.by_ref())?,
-                    z: read_f32(chars.by_ref())?,
+            list.vertices.extend(
+                triangles
+                    .iter()
+                    .flat_map(|triangle| triangle.iter())
+                    .map(|v| self.vertex(v)),
+            );
+        }
+        list
+    }
+
+    /// Triangulates every face into a deduplicated vertex buffer and an index buffer.
+    /// Corners referencing the same position, texture coordinate and normal share a vertex
+    pub fn indexed_mesh(&self, triangulation: Triangulation) -> IndexedMesh {
+        self.index_faces(self.faces.iter(), triangulation)
+    }
+
+    /// Splits the faces by material into one indexed mesh each, in order of first use
+    pub fn submeshes(&self, triangulation: Triangulation) -> Vec<Submesh> {
+        self.submeshes_of(&self.faces.iter().collect::<Vec<_>>(), triangulation)
+    }
+
+    /// Splits the faces into objects, then groups, then one indexed mesh per material, each in order of first use
+    pub fn objects(&self, triangulation: Triangulation) -> Vec<ModelObject> {
+        distinct(self.faces.iter().map(|face| face.object))
+            .into_iter()
+            .map(|object| {
+                let object_faces: Vec<&Face> = self
+                    .faces
+                    .iter()
+                    .filter(|face| face.object == object)
+                    .collect();
+                let groups = distinct(object_faces.iter().map(|face| face.group))
+                    .into_iter()
+                    .map(|group| ModelGroup {
+                        names: group
+                            .and_then(|i| self.groups.get(i).cloned())
+                            .unwrap_or_default(),
+                        submeshes: self.submeshes_of(
+                            &object_faces
+                                .iter()
+                                .copied()
+                                .filter(|face| face.group == group)
+                                .collect::<Vec<_>>(),
+                            triangulation,
+                        ),
+                    })
+                    .collect();
+                ModelObject {
+                    name: object.and_then(|i| self.object_names.get(i).cloned()),
+                    groups,
+                }
+            })
+            .collect()
+    }
+
+    /// Gives every face corner without a normal one generated from the surrounding faces.
+    /// Faces in the same smoothing group share averaged normals where they meet, faces with smoothing off are shaded flat
+    pub fn generate_normals(&mut self) {
+        // area weighted, since Newell normals are as long as twice the face's area
+        let face_normals: Vec<Vec3> = self.faces.iter().map(|f| self.face_normal(f)).collect();
+
+        let mut smoothed = HashMap::<(usize, u32), Vec3>::new();
+        for (face, normal) in self.faces.iter().zip(&face_normals) {
+            if face.smoothing_group != 0 {
+                for corner in &face.vertices {
+                    *smoothed
+                        .entry((corner.position, face.smoothing_group))
+                        .or_default() += *normal;
+                }
+            }
+        }
+
+        let normalized = |n: Vec3| {
+            if n.mag_sq() > 0.0 {
+                n.normalized()
+            } else {
+                Vec3::zero()
+            }
+        };
+        let mut smoothed_indices = HashMap::<(usize, u32), usize>::new();
+        for (face, normal) in self.faces.iter_mut().zip(&face_normals) {
+            let mut flat_index = None;
+            for corner in face.vertices.iter_mut().filter(|c| c.normal.is_none()) {
+                let index = if face.smoothing_group == 0 {
+                    *flat_index.get_or_insert_with(|| {
+                        self.normals.push(normalized(*normal));
+                        self.normals.len() - 1
+                    })
+                } else {
+                    let key = (corner.position, face.smoothing_group);
+                    *smoothed_indices.entry(key).or_insert_with(|| {
+                        self.normals.push(normalized(smoothed[&key]));
+                        self.normals.len() - 1
+                    })
+                };
+                corner.normal = Some(index);
+            }
+        }
+    }
+
+    /// Splits `faces` by material into one indexed mesh each, in order of first use
+    fn submeshes_of(&self, faces: &[&Face], triangulation: Triangulation) -> Vec<Submesh> {
+        distinct(faces.iter().map(|face| face.material))
+            .into_iter()
+            .map(|material| Submesh {
+                material: material.and_then(|i| self.material_names.get(i).cloned()),
+                mesh: self.index_faces(
+                    faces
+                        .iter()
+                        .copied()
+                        .filter(|face| face.material == material),
+                    triangulation,
+                ),
+            })
+            .collect()
+    }
+
+    /// Reads every `mtllib` relative to `base_dir` into one library.
+    /// Libraries that don't exist are listed in `MaterialLibrary::missing` and skipped, since OBJ files are often shared without them
+    pub fn load_materials(&self, base_dir: &Path) -> Result<MaterialLibrary, WavefrontError> {
+        let mut library = MaterialLibrary::default();
+        for name in &self.material_libraries {
+            let path = base_dir.join(name);
+            match MaterialLibrary::from_file(&path) {
+                Ok(loaded) => library.merge(loaded),
+                Err(WavefrontError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                    library.missing.push(path);
+                }
+                Err(e) => {
+                    return Err(WavefrontError::Library {
+                        path,
+                        error: Box::new(e),
+                    });
+                }
+            }
+        }
+        Ok(library)
+    }
+
+    fn index_faces<'a>(
+        &self,
+        faces: impl Iterator<Item = &'a Face>,
+        triangulation: Triangulation,
+    ) -> IndexedMesh {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut generated_triangles = 0;
+        let mut seen = HashMap::<FaceVertex, u32>::new();
+
+        for face in faces {
+            let triangles = self.triangulate(face, triangulation);
+            if face.vertices.len() > 3 {
+                generated_triangles += triangles.len();
+            }
+            for corner in triangles.iter().flatten() {
+                let index = *seen.entry(*corner).or_insert_with(|| {
+                    vertices.push(self.vertex(corner));
+                    (vertices.len() - 1) as u32
                 });
+                indices.push(index);
+            }
+        }
+
+        IndexedMesh {
+            vertices,
+            indices: Indices::narrowest(indices),
+            generated_triangles,
+        }
+    }
+
+    /// Splits a face into triangles that keep its winding
+    pub fn triangulate(&self, face: &Face, triangulation: Triangulation) -> Vec<[FaceVertex; 3]> {
+        let corners = &face.vertices;
+        let indices = match triangulation {
+            _ if corners.len() <= 3 => fan(corners.len()),
+            Triangulation::Fan => fan(corners.len()),
+            Triangulation::EarClipping => ear_clip(&self.project_face(face)),
+        };
+        indices
+            .into_iter()
+            .map(|[a, b, c]| [corners[a], corners[b], corners[c]])
+            .collect()
+    }
+
+    /// Unnormalized normal of a face, as long as twice its area. Newell's method copes with slightly non-planar faces
+    pub fn face_normal(&self, face: &Face) -> Vec3 {
+        let positions = self.face_positions(face);
+        let mut normal = Vec3::zero();
+        for (i, current) in positions.iter().enumerate() {
+            let next = positions[(i + 1) % positions.len()];
+            normal.x += (current.y - next.y) * (current.z + next.z);
+            normal.y += (current.z - next.z) * (current.x + next.x);
+            normal.z += (current.x - next.x) * (current.y + next.y);
+        }
+        normal
+    }
+
+    fn face_positions(&self, face: &Face) -> Vec<Vec3> {
+        face.vertices
+            .iter()
+            .map(|v| {
+                self.positions
+                    .get(v.position)
+                    .copied()
+                    .unwrap_or_default()
+                    .xyz()
+            })
+            .collect()
+    }
+
+    /// Flattens a face's corners onto the plane they roughly lie in, dropping the axis the face normal is closest to
+    fn project_face(&self, face: &Face) -> Vec<Vec2> {
+        let normal = self.face_normal(face).abs();
+        self.face_positions(face)
+            .iter()
+            .map(|p| {
+                if normal.x >= normal.y && normal.x >= normal.z {
+                    Vec2::new(p.y, p.z)
+                } else if normal.y >= normal.z {
+                    Vec2::new(p.z, p.x)
+                } else {
+                    Vec2::new(p.x, p.y)
+                }
+            })
+            .collect()
+    }
+
+    /// Builds the pos-tex-normal vertex for one element corner. Attributes it doesn't reference are zeroed
+    pub fn vertex(&self, v: &FaceVertex) -> Vertex {
+        let position = self.positions.get(v.position).copied().unwrap_or_default();
+        let tex_coords = v
+            .texcoord
+            .and_then(|i| self.texcoords.get(i).copied())
+            .unwrap_or_default();
+        let normal = v
+            .normal
+            .and_then(|i| self.normals.get(i).copied())
+            .unwrap_or_default();
+        [
+            position.x,
+            position.y,
+            position.z,
+            tex_coords.x,
+            tex_coords.y,
+            normal.x,
+            normal.y,
+            normal.z,
+        ]
+    }
+
+    fn parse_statement(
+        &mut self,
+        statement: &Statement<'_>,
+        state: &mut ParseState,
+    ) -> Result<(), WavefrontError> {
+        let args = &statement.args;
+        match statement.keyword.text {
+            "v" => {
+                let values = statement.floats(3, 4)?;
+                self.positions.push(Vec4::new(
+                    values[0],
+                    values[1],
+                    values[2],
+                    values.get(3).copied().unwrap_or(1.0),
+                ));
             }
             "vt" => {
-                chars.next(); // skip the space after t
-                vertex_texcoords.push(Vec2 {
-                    x: read_f32(chars.by_ref())?,
-                    y: read_f32(chars.by_ref())?,
+                let values = statement.floats(1, 3)?;
+                self.texcoords.push(Vec3::new(
+                    values[0],
+                    values.get(1).copied().unwrap_or(0.0),
+                    values.get(2).copied().unwrap_or(0.0),
+                ));
+            }
+            "vn" => {
+                let values = statement.floats(3, 3)?;
+                self.normals
+                    .push(Vec3::new(values[0], values[1], values[2]));
+            }
+            "vp" => {
+                let values = statement.floats(1, 3)?;
+                self.parameter_vertices.push(Vec3::new(
+                    values[0],
+                    values.get(1).copied().unwrap_or(0.0),
+                    values.get(2).copied().unwrap_or(0.0),
+                ));
+            }
+            "f" => {
+                statement.expect_count(3)?;
+                let vertices = args
+                    .iter()
+                    .map(|arg| self.parse_face_vertex(arg))
+                    .collect::<Result<_, _>>()?;
+                self.faces.push(Face {
+                    vertices,
+                    material: state.material,
+                    object: state.object,
+                    group: state.group,
+                    smoothing_group: state.smoothing_group,
                 });
             }
-            "f " => {
-                let face_vertices = chars.as_str().split(" ");
-                for vert in face_vertices {
-                    let vert_param_indices = vert.split("/");
-                    let mut vertex: Vertex = Vertex::default();
-
-                    let position = *vertex_points
-                        .get(
-                            vert_param_indices
-                                .clone()
-                                .next()
-                                .expect("face should provide at least position index")
-                                .parse::<usize>()
-                                .expect("position index should be a parseable usize")
-                                - 1,
-                        )
-                        .expect("position should be a valid vector")
-                        .as_array();
-
-                    let tex_coords = *vertex_texcoords
-                        .get(
-                            vert_param_indices
-                                .clone()
-                                .nth(1)
-                                .expect("face should provide at least position index")
-                                .parse::<usize>()
-                                .expect("position index should be a parseable usize")
-                                - 1,
-                        )
-                        .map_or(Vec2::zero().as_array(), |tex_coords| tex_coords.as_array());
-
-                    let normal = *vertex_normals
-                        .get(
-                            vert_param_indices.clone().nth(2).map_or(usize::MAX, |f| {
-                                f.parse::<usize>()
-                                    .or::<usize>(Ok(usize::MAX))
-                                    .expect("Err should not be possible")
-                            }) - 1,
-                        )
-                        .map_or(Vec3::zero().as_array(), |normal| normal.as_array());
-
-                    vertex[0] = position[0];
-                    vertex[1] = position[1];
-                    vertex[2] = position[2];
-                    vertex[3] = tex_coords[0];
-                    vertex[4] = tex_coords[1];
-                    vertex[5] = normal[0];
-                    vertex[6] = normal[1];
-                    vertex[7] = normal[2];
-
-                    raw_vertices.push(vertex);
+            "l" => {
+                statement.expect_count(2)?;
+                let vertices = args
+                    .iter()
+                    .map(|arg| self.parse_face_vertex(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if let Some(arg) = vertices
+                    .iter()
+                    .zip(args)
+                    .find_map(|(v, arg)| v.normal.map(|_| arg))
+                {
+                    return Err(arg.error(ParseErrorKind::UnexpectedNormal));
                 }
+                self.lines.push(Polyline { vertices });
+            }
+            "p" => {
+                statement.expect_count(1)?;
+                let points = args
+                    .iter()
+                    .map(|arg| resolve_index(arg, arg.text, self.positions.len()))
+                    .collect::<Result<_, _>>()?;
+                self.points.push(points);
+            }
+            "mtllib" => {
+                statement.expect_count(1)?;
+                self.material_libraries
+                    .extend(args.iter().map(|arg| arg.text.to_owned()));
+            }
+            "usemtl" => {
+                statement.expect_count(1)?;
+                state.material = Some(index_of_or_push(
+                    &mut self.material_names,
+                    statement.rest(0),
+                ));
+            }
+            "o" => {
+                statement.expect_count(1)?;
+                state.object = Some(index_of_or_push(&mut self.object_names, statement.rest(0)));
+            }
+            "g" => {
+                // a bare `g` returns to the default group
+                state.group = (!args.is_empty()).then(|| {
+                    index_of_or_push(
+                        &mut self.groups,
+                        args.iter().map(|arg| arg.text.to_owned()).collect(),
+                    )
+                });
+            }
+            "s" => {
+                statement.expect_count(1)?;
+                let arg = &args[0];
+                state.smoothing_group = match arg.text {
+                    "off" => 0,
+                    text => text
+                        .parse()
+                        .map_err(|_| arg.error(ParseErrorKind::InvalidNumber(text.to_owned())))?,
+                };
             }
             _ => {}
         }
+        Ok(())
     }
 
-    Ok(match raw_vertices.len() {
-        0 => None,
-        _ => Some(raw_vertices),
-    })
+    /// Parses `v`, `v/vt`, `v//vn` or `v/vt/vn`
+    fn parse_face_vertex(&self, token: &Token<'_>) -> Result<FaceVertex, WavefrontError> {
+        let mut parts = token.text.split('/');
+        let position = resolve_index(
+            token,
+            parts.next().unwrap_or_default(),
+            self.positions.len(),
+        )?;
+        let texcoord = match parts.next() {
+            None | Some("") => None,
+            Some(index) => Some(resolve_index(token, index, self.texcoords.len())?),
+        };
+        let normal = match parts.next() {
+            None => None,
+            Some(index) => Some(resolve_index(token, index, self.normals.len())?),
+        };
+        if parts.next().is_some() {
+            return Err(token.error(ParseErrorKind::InvalidIndex(token.text.to_owned())));
+        }
+        Ok(FaceVertex {
+            position,
+            texcoord,
+            normal,
+        })
+    }
 }
 
-/// Reads a float from a char iterator then consumes the subsequent space.
-fn read_f32(chars: &mut Chars<'_>) -> Result<f32, String> {
-    let float = chars
-        .by_ref()
-        .take_while(|c| !c.is_whitespace())
-        .fold(String::new(), |mut value, char| {
-            value.push(char);
-            value
+/// Finds `item` in `list`, adding it to the end if it isn't there yet
+fn index_of_or_push<T: PartialEq>(list: &mut Vec<T>, item: T) -> usize {
+    match list.iter().position(|i| *i == item) {
+        Some(index) => index,
+        None => {
+            list.push(item);
+            list.len() - 1
+        }
+    }
+}
+
+/// Each distinct item in first-appearance order
+fn distinct<T: PartialEq>(items: impl Iterator<Item = T>) -> Vec<T> {
+    let mut seen = Vec::new();
+    for item in items {
+        if !seen.contains(&item) {
+            seen.push(item);
+        }
+    }
+    seen
+}
+
+/// Triangle fan over `corners` corners, as indices into the corner list
+fn fan(corners: usize) -> Vec<[usize; 3]> {
+    (1..corners.saturating_sub(1))
+        .map(|i| [0, i, i + 1])
+        .collect()
+}
+
+/// Ear clipping triangulation of a simple polygon, as indices into `points`.
+/// Falls back to a fan for whatever is left if the polygon is degenerate or self-intersecting
+fn ear_clip(points: &[Vec2]) -> Vec<[usize; 3]> {
+    let cross = |o: Vec2, a: Vec2, b: Vec2| (a.x - o.x) * (b.y - o.y) - (a.y - o.y) * (b.x - o.x);
+
+    // positive for counter-clockwise polygons
+    let orientation: f32 = (0..points.len())
+        .map(|i| {
+            let (a, b) = (points[i], points[(i + 1) % points.len()]);
+            a.x * b.y - b.x * a.y
         })
+        .sum::<f32>()
+        .signum();
+
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len().saturating_sub(2));
+
+    while remaining.len() > 3 {
+        let len = remaining.len();
+        let ear = (0..len).find(|&i| {
+            let a = remaining[(i + len - 1) % len];
+            let b = remaining[i];
+            let c = remaining[(i + 1) % len];
+            let (pa, pb, pc) = (points[a], points[b], points[c]);
+            // reflex and collinear corners can't be ears
+            if cross(pa, pb, pc) * orientation <= 0.0 {
+                return false;
+            }
+            !remaining.iter().any(|&j| {
+                j != a
+                    && j != b
+                    && j != c
+                    && cross(pa, pb, points[j]) * orientation >= 0.0
+                    && cross(pb, pc, points[j]) * orientation >= 0.0
+                    && cross(pc, pa, points[j]) * orientation >= 0.0
+            })
+        });
+        match ear {
+            Some(i) => {
+                triangles.push([
+                    remaining[(i + len - 1) % len],
+                    remaining[i],
+                    remaining[(i + 1) % len],
+                ]);
+                remaining.remove(i);
+            }
+            None => break,
+        }
+    }
+
+    triangles.extend(
+        fan(remaining.len())
+            .into_iter()
+            .map(|[a, b, c]| [remaining[a], remaining[b], remaining[c]]),
+    );
+    triangles
+}
+
+/// Turns a 1-based or negative (relative to the end) OBJ index into a 0-based index into a list of `len` items
+fn resolve_index(token: &Token<'_>, index: &str, len: usize) -> Result<usize, WavefrontError> {
+    let value: i64 = index
         .parse()
-        .map_err(|e| format!("Float parsing error: {}", e))?;
-    Ok(float)
+        .map_err(|_| token.error(ParseErrorKind::InvalidIndex(index.to_owned())))?;
+    let resolved = match value {
+        0 => return Err(token.error(ParseErrorKind::ZeroIndex)),
+        1.. => usize::try_from(value - 1).ok(),
+        _ => usize::try_from(value.unsigned_abs())
+            .ok()
+            .and_then(|back| len.checked_sub(back)),
+    };
+    resolved
+        .filter(|i| *i < len)
+        .ok_or_else(|| token.error(ParseErrorKind::IndexOutOfRange { index: value, len }))
+}
+
+/// Errors from reading or parsing an OBJ or MTL file
+#[derive(Debug)]
+pub enum WavefrontError {
+    Io(io::Error),
+    /// A material library referenced by an OBJ file failed to load
+    Library {
+        path: PathBuf,
+        error: Box<WavefrontError>,
+    },
+    /// Malformed statement. `line` and `column` are 1-based and point at the offending token
+    Parse {
+        line: usize,
+        column: usize,
+        kind: ParseErrorKind,
+    },
+}
+impl fmt::Display for WavefrontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WavefrontError::Io(e) => write!(f, "Model read error: {}", e),
+            WavefrontError::Library { path, error } => write!(f, "{}: {}", path.display(), error),
+            WavefrontError::Parse { line, column, kind } => {
+                write!(f, "Model parse error at {}:{}: {}", line, column, kind)
+            }
+        }
+    }
+}
+impl std::error::Error for WavefrontError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WavefrontError::Io(e) => Some(e),
+            WavefrontError::Library { error, .. } => Some(error.as_ref()),
+            WavefrontError::Parse { .. } => None,
+        }
+    }
+}
+
+/// The ways an OBJ statement can be malformed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A value that should be a number isn't one
+    InvalidNumber(String),
+    /// A statement has fewer values than it needs
+    TooFewValues { min: usize, found: usize },
+    /// A statement has more values than it allows
+    TooManyValues { max: usize, found: usize },
+    /// An element index isn't an integer, or has too many `/`-separated parts
+    InvalidIndex(String),
+    /// Element indices start at 1. 0 is never valid
+    ZeroIndex,
+    /// An element index points outside the attributes defined so far
+    IndexOutOfRange { index: i64, len: usize },
+    /// `l` elements can't reference normals
+    UnexpectedNormal,
+    /// A material statement comes before any `newmtl`
+    NoMaterial,
+    /// A texture map option this parser doesn't know, so can't tell how many values it takes
+    UnknownOption(String),
+}
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::InvalidNumber(s) => write!(f, "`{}` is not a number", s),
+            ParseErrorKind::TooFewValues { min, found } => {
+                write!(f, "expected at least {} values, found {}", min, found)
+            }
+            ParseErrorKind::TooManyValues { max, found } => {
+                write!(f, "expected at most {} values, found {}", max, found)
+            }
+            ParseErrorKind::InvalidIndex(s) => write!(f, "`{}` is not a valid index", s),
+            ParseErrorKind::ZeroIndex => write!(f, "indices start at 1"),
+            ParseErrorKind::IndexOutOfRange { index, len } => {
+                write!(f, "index {} is out of range for {} items", index, len)
+            }
+            ParseErrorKind::UnexpectedNormal => write!(f, "line elements can't have normals"),
+            ParseErrorKind::NoMaterial => write!(f, "material statement before `newmtl`"),
+            ParseErrorKind::UnknownOption(s) => write!(f, "unknown texture option `{}`", s),
+        }
+    }
+}
+
+/// A whitespace-separated word and where it starts in the file
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Token<'a> {
+    pub(crate) text: &'a str,
+    line: usize,
+    column: usize,
+}
+impl Token<'_> {
+    pub(crate) fn error(&self, kind: ParseErrorKind) -> WavefrontError {
+        WavefrontError::Parse {
+            line: self.line,
+            column: self.column,
+            kind,
+        }
+    }
+}
+
+/// A keyword and its arguments, possibly spread over several lines with `\` continuations
+#[derive(Debug)]
+pub(crate) struct Statement<'a> {
+    pub(crate) keyword: Token<'a>,
+    pub(crate) args: Vec<Token<'a>>,
+    /// Just past the last token, for errors about missing values
+    pub(crate) end: Token<'a>,
+}
+impl Statement<'_> {
+    pub(crate) fn expect_count(&self, min: usize) -> Result<(), WavefrontError> {
+        if self.args.len() < min {
+            Err(self.end.error(ParseErrorKind::TooFewValues {
+                min,
+                found: self.args.len(),
+            }))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Parses between `min` and `max` float arguments
+    pub(crate) fn floats(&self, min: usize, max: usize) -> Result<Vec<f32>, WavefrontError> {
+        self.floats_from(0, min, max)
+    }
+
+    /// Parses between `min` and `max` float arguments, after skipping `skip` arguments
+    pub(crate) fn floats_from(
+        &self,
+        skip: usize,
+        min: usize,
+        max: usize,
+    ) -> Result<Vec<f32>, WavefrontError> {
+        let args = &self.args[skip.min(self.args.len())..];
+        if args.len() < min {
+            return Err(self.end.error(ParseErrorKind::TooFewValues {
+                min,
+                found: args.len(),
+            }));
+        }
+        if let Some(extra) = args.get(max) {
+            return Err(extra.error(ParseErrorKind::TooManyValues {
+                max,
+                found: args.len(),
+            }));
+        }
+        args.iter()
+            .map(|arg| {
+                arg.text
+                    .parse()
+                    .map_err(|_| arg.error(ParseErrorKind::InvalidNumber(arg.text.to_owned())))
+            })
+            .collect()
+    }
+
+    /// The arguments from `skip` on joined by single spaces, for names and paths that may contain spaces
+    pub(crate) fn rest(&self, skip: usize) -> String {
+        self.args
+            .iter()
+            .skip(skip)
+            .map(|arg| arg.text)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Splits OBJ or MTL source into statements, dropping comments and joining continued lines
+pub(crate) struct Statements<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+}
+impl<'a> Statements<'a> {
+    pub(crate) fn new(src: &'a str) -> Self {
+        Self {
+            lines: src.lines().enumerate(),
+        }
+    }
+}
+impl<'a> Iterator for Statements<'a> {
+    type Item = Statement<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut tokens = Vec::new();
+        let mut end = None;
+        for (index, line) in self.lines.by_ref() {
+            let line_number = index + 1;
+            let content = line.split('#').next().unwrap_or_default().trim_end();
+            let (content, continues) = match content.strip_suffix('\\') {
+                Some(content) => (content, true),
+                None => (content, false),
+            };
+
+            let mut column = 1;
+            let mut token_start = None;
+            for (offset, c) in content.char_indices() {
+                match (c.is_whitespace(), token_start) {
+                    (false, None) => token_start = Some((offset, column)),
+                    (true, Some((start, start_column))) => {
+                        tokens.push(Token {
+                            text: &content[start..offset],
+                            line: line_number,
+                            column: start_column,
+                        });
+                        token_start = None;
+                    }
+                    _ => {}
+                }
+                column += 1;
+            }
+            if let Some((start, start_column)) = token_start {
+                tokens.push(Token {
+                    text: &content[start..],
+                    line: line_number,
+                    column: start_column,
+                });
+            }
+            end = Some(Token {
+                text: "",
+                line: line_number,
+                column,
+            });
+
+            if !continues && !tokens.is_empty() {
+                break;
+            }
+        }
+
+        let mut tokens = tokens.into_iter();
+        let keyword = tokens.next()?;
+        Some(Statement {
+            keyword,
+            args: tokens.collect(),
+            end: end.unwrap_or(keyword),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE: &str = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vn 0 0 1
+vn 0 0 -1
+";
+
+    fn corner(position: usize, texcoord: Option<usize>, normal: Option<usize>) -> FaceVertex {
+        FaceVertex {
+            position,
+            texcoord,
+            normal,
+        }
+    }
+
+    fn parse_error(src: &str) -> (usize, usize, ParseErrorKind) {
+        match Wavefront::parse(src) {
+            Err(WavefrontError::Parse { line, column, kind }) => (line, column, kind),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_vertex_attributes() {
+        let obj =
+            Wavefront::parse("v 1 2 3\nv 1 2 3 0.5\nvt 0.25\nvt 0.25 0.5 1\nvn 0 1 0\n").unwrap();
+        assert_eq!(
+            obj.positions,
+            vec![Vec4::new(1.0, 2.0, 3.0, 1.0), Vec4::new(1.0, 2.0, 3.0, 0.5)]
+        );
+        assert_eq!(
+            obj.texcoords,
+            vec![Vec3::new(0.25, 0.0, 0.0), Vec3::new(0.25, 0.5, 1.0)]
+        );
+        assert_eq!(obj.normals, vec![Vec3::new(0.0, 1.0, 0.0)]);
+    }
+
+    #[test]
+    fn parses_every_face_index_form() {
+        let src = format!(
+            "{}f 1 2 3\nf 1/1 2/2 3/3\nf 1//1 2//1 3//2\nf 1/1/1 2/2/1 3/3/2\n",
+            SQUARE
+        );
+        let obj = Wavefront::parse(&src).unwrap();
+        let faces: Vec<_> = obj.faces.iter().map(|f| f.vertices[1]).collect();
+        assert_eq!(
+            faces,
+            vec![
+                corner(1, None, None),
+                corner(1, Some(1), None),
+                corner(1, None, Some(0)),
+                corner(1, Some(1), Some(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_latest_attribute() {
+        let src = format!("{}f -4/-3/-2 -3/-2/-2 -1/-1/-1\n", SQUARE);
+        let obj = Wavefront::parse(&src).unwrap();
+        assert_eq!(
+            obj.faces[0].vertices,
+            vec![
+                corner(0, Some(0), Some(0)),
+                corner(1, Some(1), Some(0)),
+                corner(3, Some(2), Some(1)),
+            ]
+        );
+
+        // relative to the attributes defined before the face, not the whole file
+        let obj = Wavefront::parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\nv 5 5 5\n").unwrap();
+        assert_eq!(
+            obj.faces[0].vertices,
+            vec![
+                corner(0, None, None),
+                corner(1, None, None),
+                corner(2, None, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn out_of_range_indices_point_at_their_token() {
+        let src = format!("{}f 1 2 5\n", SQUARE);
+        assert_eq!(
+            parse_error(&src),
+            (10, 7, ParseErrorKind::IndexOutOfRange { index: 5, len: 4 })
+        );
+
+        let src = format!("{}f 1 2 -5\n", SQUARE);
+        assert_eq!(
+            parse_error(&src),
+            (10, 7, ParseErrorKind::IndexOutOfRange { index: -5, len: 4 })
+        );
+
+        let src = format!("{}f 1 2/4 3\n", SQUARE);
+        assert_eq!(
+            parse_error(&src),
+            (10, 5, ParseErrorKind::IndexOutOfRange { index: 4, len: 3 })
+        );
+
+        let src = format!("{}f 0 1 2\n", SQUARE);
+        assert_eq!(parse_error(&src), (10, 3, ParseErrorKind::ZeroIndex));
+    }
+
+    #[test]
+    fn malformed_values_point_at_their_token() {
+        assert_eq!(
+            parse_error("v 0 0 0\nv 1  x 0\n"),
+            (2, 6, ParseErrorKind::InvalidNumber("x".to_owned()))
+        );
+        assert_eq!(
+            parse_error("v 0 0 0\nf 1 1/a 1\n"),
+            (2, 5, ParseErrorKind::InvalidIndex("a".to_owned()))
+        );
+        assert_eq!(
+            parse_error("v 0 0 0\nvt 0 0\nvn 0 0 1\nf 1 1/1/1/1 1\n"),
+            (4, 5, ParseErrorKind::InvalidIndex("1/1/1/1".to_owned()))
+        );
+        assert_eq!(
+            parse_error("v 0 0 0 1 2\n"),
+            (1, 11, ParseErrorKind::TooManyValues { max: 4, found: 5 })
+        );
+        // missing values are reported just past the end of the statement
+        assert_eq!(
+            parse_error("v 0 0\n"),
+            (1, 6, ParseErrorKind::TooFewValues { min: 3, found: 2 })
+        );
+    }
+
+    #[test]
+    fn accepts_crlf_line_endings() {
+        let src = SQUARE.replace('\n', "\r\n") + "f 1/1/1 2/2/1 3/3/1\r\n";
+        let obj = Wavefront::parse(&src).unwrap();
+        assert_eq!(obj.positions.len(), 4);
+        assert_eq!(obj.faces[0].vertices[2], corner(2, Some(2), Some(0)));
+
+        assert_eq!(
+            parse_error("v 0 0 0\r\nv 0 y 0\r\n"),
+            (2, 5, ParseErrorKind::InvalidNumber("y".to_owned()))
+        );
+    }
+
+    #[test]
+    fn joins_continued_lines() {
+        let src = format!("{}f 1 2 \\\n  3 \\\r\n4 # comment\nf 1 2 3\n", SQUARE);
+        let obj = Wavefront::parse(&src).unwrap();
+        assert_eq!(obj.faces.len(), 2);
+        assert_eq!(
+            obj.faces[0]
+                .vertices
+                .iter()
+                .map(|v| v.position)
+                .collect::<Vec<_>>(),
+            vec![0, 1, 2, 3]
+        );
+
+        // errors on a continuation line point at that line
+        let src = format!("{}f 1 2 \\\n  9\n", SQUARE);
+        assert_eq!(
+            parse_error(&src),
+            (11, 3, ParseErrorKind::IndexOutOfRange { index: 9, len: 4 })
+        );
+    }
+
+    #[test]
+    fn skips_comments_and_unknown_statements() {
+        let obj = Wavefront::parse("# header\n\ncstype bspline\nv 0 0 0 # origin\n").unwrap();
+        assert_eq!(obj.positions, vec![Vec4::new(0.0, 0.0, 0.0, 1.0)]);
+    }
+
+    fn signed_area(points: &[Vec2], [a, b, c]: [usize; 3]) -> f32 {
+        let (a, b, c) = (points[a], points[b], points[c]);
+        ((b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)) / 2.0
+    }
+
+    /// Checks that `triangles` tile the counter-clockwise polygon `points` without flipping any triangle
+    fn assert_tiles(points: &[Vec2], triangles: &[[usize; 3]], area: f32) {
+        assert_eq!(triangles.len(), points.len() - 2);
+        for &triangle in triangles {
+            assert!(
+                signed_area(points, triangle) >= 0.0,
+                "{:?} is flipped",
+                triangle
+            );
+        }
+        let total: f32 = triangles.iter().map(|&t| signed_area(points, t)).sum();
+        assert!((total - area).abs() < 1e-5, "{} != {}", total, area);
+    }
+
+    #[test]
+    fn ear_clips_convex_quad() {
+        let points = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(2.0, 0.0),
+            Vec2::new(2.0, 1.0),
+            Vec2::new(0.0, 1.0),
+        ];
+        assert_tiles(&points, &ear_clip(&points), 2.0);
+    }
+
+    #[test]
+    fn ear_clips_concave_quad() {
+        // the reflex corner at index 1 makes a fan from index 0 fold over
+        let points = [
+            Vec2::new(4.0, 0.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(0.0, 4.0),
+            Vec2::new(0.0, 0.0),
+        ];
+        assert!(signed_area(&points, fan(4)[0]) < 0.0);
+        let triangles = ear_clip(&points);
+        assert_tiles(&points, &triangles, 4.0);
+        assert!(triangles.iter().all(|t| t.contains(&1)));
+    }
+
+    #[test]
+    fn ear_clips_clockwise_polygons() {
+        let mut points = vec![
+            Vec2::new(4.0, 0.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(0.0, 4.0),
+            Vec2::new(0.0, 0.0),
+        ];
+        points.reverse();
+        let triangles = ear_clip(&points);
+        assert_eq!(triangles.len(), 2);
+        for &triangle in &triangles {
+            assert!(signed_area(&points, triangle) <= 0.0);
+        }
+    }
+
+    #[test]
+    fn ear_clips_pentagon() {
+        let points: Vec<Vec2> = (0..5)
+            .map(|i| {
+                let angle = i as f32 * std::f32::consts::TAU / 5.0;
+                Vec2::new(angle.cos(), angle.sin())
+            })
+            .collect();
+        let area = 5.0 / 2.0 * (std::f32::consts::TAU / 5.0).sin();
+        assert_tiles(&points, &ear_clip(&points), area);
+
+        // an arrow head: concave at index 3
+        let points = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(2.0, 0.0),
+            Vec2::new(2.0, 2.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(0.0, 2.0),
+        ];
+        assert_tiles(&points, &ear_clip(&points), 3.0);
+    }
+
+    #[test]
+    fn ear_clipping_skips_collinear_corners() {
+        let points = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(2.0, 0.0),
+            Vec2::new(2.0, 1.0),
+            Vec2::new(0.0, 1.0),
+        ];
+        assert_tiles(&points, &ear_clip(&points), 2.0);
+    }
+
+    #[test]
+    fn degenerate_polygons_fall_back_to_a_fan() {
+        let points = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(2.0, 0.0),
+            Vec2::new(3.0, 0.0),
+        ];
+        assert_eq!(ear_clip(&points), fan(4));
+        assert_eq!(fan(4), vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(fan(3), vec![[0, 1, 2]]);
+        assert!(fan(2).is_empty());
+    }
+
+    #[test]
+    fn triangulates_faces_off_the_xy_plane() {
+        // the concave quad above, lying in the xz plane
+        let src = "v 4 0 0\nv 1 0 1\nv 0 0 4\nv 0 0 0\nf 1 2 3 4\n";
+        let obj = Wavefront::parse(src).unwrap();
+        let face = &obj.faces[0];
+        let fan = obj.triangulate(face, Triangulation::Fan);
+        assert_eq!(fan[0].map(|v| v.position), [0, 1, 2]);
+        let clipped = obj.triangulate(face, Triangulation::EarClipping);
+        assert_eq!(clipped.len(), 2);
+        assert!(clipped.iter().all(|t| t.iter().any(|v| v.position == 1)));
+        // clipping keeps the face's winding
+        for triangle in clipped {
+            let normal = obj.face_normal(&Face {
+                vertices: triangle.to_vec(),
+                ..face.clone()
+            });
+            assert!(normal.dot(obj.face_normal(face)) > 0.0);
+        }
+    }
+
+    #[test]
+    fn indexing_shares_identical_corners() {
+        let src = format!(
+            "{}f 1/1/1 2/2/1 3/3/1 4/1/1\nf 1/1/1 3/3/1 2/2/1\nf 1/1/2 2/2/1 3/3/1\n",
+            SQUARE
+        );
+        let obj = Wavefront::parse(&src).unwrap();
+        let mesh = obj.indexed_mesh(Triangulation::Fan);
+        // 4 corners of the quad, plus corner 1 again with the other normal
+        assert_eq!(mesh.vertices.len(), 5);
+        assert_eq!(mesh.generated_triangles, 2);
+        assert_eq!(
+            mesh.triangles().collect::<Vec<_>>(),
+            vec![[0, 1, 2], [0, 2, 3], [0, 2, 1], [4, 1, 2]]
+        );
+        assert_eq!(mesh.vertices[4][5..], [0.0, 0.0, -1.0]);
+        assert_eq!(
+            mesh.indices,
+            Indices::U8(vec![0, 1, 2, 0, 2, 3, 0, 2, 1, 4, 1, 2])
+        );
+    }
+
+    #[test]
+    fn splits_faces_into_objects_groups_and_materials() {
+        let src = format!(
+            "{}f 1 2 3\no first\ng a\nf 1 2 3\nf 1 3 4\ng b c\nusemtl red\nf 1 2 4\nusemtl blue\nf 2 3 4\no second\ng\nf 1 2 3\ng a\nf 2 3 4\n",
+            SQUARE
+        );
+        let obj = Wavefront::parse(&src).unwrap();
+        assert_eq!(obj.object_names, ["first", "second"]);
+        assert_eq!(
+            obj.groups,
+            [vec!["a".to_owned()], vec!["b".to_owned(), "c".to_owned()]]
+        );
+        let objects = obj.objects(Triangulation::Fan);
+        let names: Vec<_> = objects.iter().map(|o| o.name.as_deref()).collect();
+        assert_eq!(names, [None, Some("first"), Some("second")]);
+        // faces before any `o` or `g` land in an unnamed object and group
+        assert_eq!(objects[0].groups.len(), 1);
+        assert!(objects[0].groups[0].names.is_empty());
+        assert_eq!(triangle_counts(&objects[0].groups[0]), [(None, 1)]);
+
+        let model = WavefrontModel {
+            objects,
+            materials: MaterialLibrary::default(),
+        };
+        let first = model.object("first").unwrap();
+        assert_eq!(first.groups.len(), 2);
+        assert_eq!(triangle_counts(first.group("a").unwrap()), [(None, 2)]);
+        let bc = first.group("c").unwrap();
+        assert_eq!(first.group("b"), Some(bc));
+        assert_eq!(triangle_counts(bc), [(Some("red"), 1), (Some("blue"), 1)]);
+
+        // `a` in another object is a group of its own, and a bare `g` returns to the default group
+        let second = model.object("second").unwrap();
+        let group_names: Vec<_> = second.groups.iter().map(|g| g.names.clone()).collect();
+        assert_eq!(group_names, [vec![], vec!["a".to_owned()]]);
+        assert_eq!(
+            triangle_counts(second.group("a").unwrap()),
+            [(Some("blue"), 1)]
+        );
+        assert!(model.object("third").is_none());
+        assert!(first.group("d").is_none());
+        assert_eq!(model.submeshes().count(), 6);
+    }
+
+    /// Material and triangle count of each submesh of a group
+    fn triangle_counts(group: &ModelGroup) -> Vec<(Option<&str>, usize)> {
+        group
+            .submeshes
+            .iter()
+            .map(|s| (s.material.as_deref(), s.mesh.indices.len() / 3))
+            .collect()
+    }
+
+    /// Two triangles folded along the edge from position 1 to 2, facing +z and +y.
+    /// `faces` holds the statements before each face
+    fn folded(faces: [&str; 2]) -> Wavefront {
+        let src = format!(
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\n{}f 1 2 3\n{}f 2 1 4\n",
+            faces[0], faces[1]
+        );
+        let mut obj = Wavefront::parse(&src).unwrap();
+        obj.generate_normals();
+        obj
+    }
+
+    /// Generated normal of a corner of a face
+    fn corner_normal(obj: &Wavefront, face: usize, corner: usize) -> Vec3 {
+        obj.normals[obj.faces[face].vertices[corner].normal.unwrap()]
+    }
+
+    #[test]
+    fn smoothing_groups_share_normals_at_shared_positions() {
+        let obj = folded(["s 1\n", ""]);
+        // the shared edge: positions 1 and 2
+        for (a, b) in [(0, 1), (1, 0)] {
+            assert_eq!(
+                obj.faces[0].vertices[a].normal,
+                obj.faces[1].vertices[b].normal
+            );
+        }
+        let averaged = Vec3::new(0.0, 1.0, 1.0).normalized();
+        assert!((corner_normal(&obj, 0, 0) - averaged).mag() < 1e-6);
+        // corners only one face touches keep that face's normal
+        assert_eq!(corner_normal(&obj, 0, 2), Vec3::unit_z());
+        assert_eq!(corner_normal(&obj, 1, 2), Vec3::unit_y());
+        assert_eq!(obj.normals.len(), 4);
+    }
+
+    #[test]
+    fn faces_without_smoothing_are_flat() {
+        for statements in [
+            ["", ""],
+            ["s off\n", ""],
+            ["s 1\n", "s off\n"],
+            ["s 0\n", ""],
+        ] {
+            let obj = folded(statements);
+            assert_eq!(obj.faces[1].smoothing_group, 0);
+            // flat faces share one normal between their corners
+            let corners = &obj.faces[1].vertices;
+            assert!(corners.iter().all(|c| c.normal == corners[0].normal));
+            assert_eq!(
+                corner_normal(&obj, 1, 0),
+                Vec3::unit_y(),
+                "{:?}",
+                statements
+            );
+            assert_ne!(corners[0].normal, obj.faces[0].vertices[1].normal);
+        }
+    }
+
+    #[test]
+    fn smoothing_group_boundaries_split_normals() {
+        let obj = folded(["s 1\n", "s 2\n"]);
+        for corner in 0..3 {
+            assert_eq!(corner_normal(&obj, 0, corner), Vec3::unit_z());
+            assert_eq!(corner_normal(&obj, 1, corner), Vec3::unit_y());
+        }
+        assert_ne!(
+            obj.faces[0].vertices[0].normal,
+            obj.faces[1].vertices[1].normal
+        );
+    }
+
+    #[test]
+    fn generated_normals_keep_given_ones() {
+        let mut obj = Wavefront::parse(&format!("{}s 1\nf 1//2 2 3\n", SQUARE)).unwrap();
+        obj.generate_normals();
+        assert_eq!(obj.faces[0].vertices[0].normal, Some(1));
+        assert_eq!(obj.normals.len(), 4);
+        for corner in 1..3 {
+            assert_eq!(corner_normal(&obj, 0, corner), Vec3::unit_z());
+        }
+    }
+
+    #[test]
+    fn missing_material_libraries_are_listed() {
+        let obj = Wavefront::parse("mtllib missing.mtl other.mtl\n").unwrap();
+        let dir = std::env::temp_dir().join("magiebleue-missing-mtllib");
+        let library = obj.load_materials(&dir).unwrap();
+        assert!(library.materials.is_empty());
+        assert_eq!(
+            library.missing,
+            [dir.join("missing.mtl"), dir.join("other.mtl")]
+        );
+    }
 }
@@ -0,0 +1,157 @@
+use std::f32::consts::PI;
+
+use magiebleue::{
+    Application,
+    functions::{gen_patches, set_clear_color},
+    gl_objects::{Primitive, VertexArray},
+    golden::{self, GoldenConfig, Scene},
+    shaders::ShaderProgram,
+    textures::{self, TexScaleOp, TexWrapBehaviour, Texture, TextureType},
+    uniform_blocks::{ShaderBlock, UniformBuffer},
+    wavefront_parser::Vertex,
+};
+use noise::{
+    core::open_simplex::open_simplex_2d, permutationtable::PermutationTable, utils::PlaneMapBuilder,
+};
+use ultraviolet::{Mat4, Vec3, Vec4};
+
+/// The `Camera` uniform block shared by both programs
+#[derive(ShaderBlock)]
+struct Camera {
+    view: Mat4,
+    proj: Mat4,
+}
+
+/// The heightmap example with a fixed noise seed and camera
+#[derive(Default)]
+struct HeightmapScene {
+    camera: Option<UniformBuffer<Camera>>,
+    heightmap_shader: Option<ShaderProgram>,
+    base_shader: Option<ShaderProgram>,
+    heightmap_vao: Option<VertexArray>,
+    plane_vao: Option<VertexArray>,
+    heightmap_texture: Option<Texture>,
+}
+
+impl Scene for HeightmapScene {
+    fn name(&self) -> &str {
+        "heightmap"
+    }
+
+    fn setup(&mut self, _app: &mut Application) {
+        self.heightmap_shader = Some(
+            ShaderProgram::from_filepath(
+                "shaders/heightmap.vert",
+                Some("shaders/heightmap.tesc"),
+                Some("shaders/heightmap.tese"),
+                None,
+                "shaders/heightmap.frag",
+            )
+            .unwrap(),
+        );
+        self.base_shader = Some(
+            ShaderProgram::from_filepath(
+                "shaders/base.vert",
+                None,
+                None,
+                None,
+                "shaders/base.frag",
+            )
+            .unwrap(),
+        );
+
+        let camera = Camera {
+            view: Mat4::identity(),
+            proj: Mat4::identity(),
+        };
+        self.camera = Some(UniformBuffer::shared("Camera", &camera).unwrap());
+        for program in [&self.heightmap_shader, &self.base_shader] {
+            program.as_ref().unwrap().bind_shared_blocks().unwrap();
+        }
+
+        set_clear_color(Vec4::new(0.2, 0.3, 0.3, 1.0));
+
+        let mut vertices: Vec<Vertex> = Vec::new();
+        gen_patches(&mut vertices, 64, 256.0, Vec3::new(-128.0, 0.0, -128.0));
+        let mut heightmap_vao = VertexArray::new().expect("VAO should create");
+        heightmap_vao
+            .attach_vertex(vertices)
+            .expect("vertices should attach");
+        self.heightmap_vao = Some(heightmap_vao);
+
+        let plane_data: [Vertex; 4] = [
+            [-5.0, 0.0, -5.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+            [5.0, 0.0, -5.0, 1.0, 0.0, 0.0, 1.0, 0.0],
+            [5.0, 0.0, 5.0, 1.0, 1.0, 0.0, 1.0, 0.0],
+            [-5.0, 0.0, 5.0, 0.0, 1.0, 0.0, 1.0, 0.0],
+        ];
+        let mut plane_vao = VertexArray::new().expect("VAO should create");
+        plane_vao
+            .attach_vertex(plane_data.to_vec())
+            .expect("vertices should attach");
+        self.plane_vao = Some(plane_vao);
+
+        unsafe {
+            gl::PatchParameteri(gl::PATCH_VERTICES, 4);
+            gl::Enable(gl::DEPTH_TEST);
+        }
+
+        let permtable = PermutationTable::new(0x6d61_6769);
+        let heightmap_texture = Texture::new().expect("texture should create");
+        heightmap_texture.bind(TextureType::Tex2d);
+        let map =
+            PlaneMapBuilder::<_, 2>::new_fn(|point| open_simplex_2d(point.into(), &permtable))
+                .set_size(128, 128)
+                .set_x_bounds(0.0, 5.0)
+                .set_y_bounds(0.0, 5.0)
+                .build();
+        Texture::fill_noise(128, map).expect("noise should upload");
+        Texture::gen_mipmap(TextureType::Tex2d);
+        Texture::set_dual_wrap_behaviour(TextureType::Tex2d, TexWrapBehaviour::ClampToBorder);
+        Texture::set_border_colour(TextureType::Tex2d, Vec4::zero());
+        Texture::set_dual_scale_behaviour(TextureType::Tex2d, TexScaleOp::Linear).unwrap();
+        self.heightmap_texture = Some(heightmap_texture);
+    }
+
+    fn draw(&mut self, _app: &mut Application) {
+        let camera_pos = Vec3::new(-3.0, 6.0, 5.0);
+        let camera_front = Vec3::new(0.8, -0.3, -0.5).normalized();
+        let model = Mat4::identity();
+        let view = Mat4::look_at(camera_pos, camera_pos + camera_front, -Vec3::unit_y());
+        let proj = ultraviolet::projection::perspective_infinite_z_gl(PI / 3.0, 1.0, 0.01);
+
+        let base_shader = self.base_shader.as_ref().unwrap();
+        let heightmap_shader = self.heightmap_shader.as_ref().unwrap();
+
+        self.camera.as_ref().unwrap().update(&Camera { view, proj });
+
+        unsafe { gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT) };
+
+        base_shader.use_program();
+        base_shader.set_uniform("model", model);
+        self.plane_vao
+            .as_ref()
+            .unwrap()
+            .draw(Primitive::TriangleFan)
+            .unwrap();
+
+        textures::set_texture_slot(0);
+        self.heightmap_texture
+            .as_ref()
+            .unwrap()
+            .bind(TextureType::Tex2d);
+
+        heightmap_shader.use_program();
+        heightmap_shader.set_uniform("model", model);
+        self.heightmap_vao
+            .as_ref()
+            .unwrap()
+            .draw(Primitive::Patches)
+            .unwrap();
+    }
+}
+
+#[test]
+fn heightmap_matches_reference() {
+    golden::assert_scene(HeightmapScene::default(), &GoldenConfig::default());
+}
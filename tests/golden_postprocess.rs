@@ -0,0 +1,58 @@
+use magiebleue::{
+    Application,
+    golden::{self, GoldenConfig, Scene},
+    postprocess::{FullscreenPass, FullscreenTriangle, PostProcessStack},
+};
+
+/// HDR test pattern: a dim gradient with discs brighter than 1 for bloom to spread and tone mapping to compress,
+/// and hard diagonal edges for FXAA to smooth
+const PATTERN_FRAG: &str = "#version 330 core
+
+out vec4 final_color;
+in vec2 vTexCoords;
+
+float disc(vec2 centre, float radius) {
+    return step(distance(vTexCoords, centre), radius);
+}
+
+void main() {
+    vec3 colour = mix(vec3(0.05, 0.08, 0.2), vec3(0.4, 0.3, 0.2), vTexCoords.y);
+    colour = mix(colour, vec3(0.9, 0.9, 0.9), step(abs(vTexCoords.x - vTexCoords.y * 0.7 - 0.1), 0.01));
+    colour += disc(vec2(0.3, 0.7), 0.08) * vec3(8.0, 4.0, 1.0);
+    colour += disc(vec2(0.7, 0.3), 0.05) * vec3(1.0, 3.0, 6.0);
+    colour += disc(vec2(0.75, 0.75), 0.12) * vec3(0.6, 0.6, 0.6);
+    final_color = vec4(colour, 1.0);
+}";
+
+/// A procedural HDR image run through `PostProcessStack::standard`
+#[derive(Default)]
+struct PostProcessScene {
+    stack: Option<PostProcessStack>,
+    pattern: Option<FullscreenPass>,
+    triangle: Option<FullscreenTriangle>,
+}
+
+impl Scene for PostProcessScene {
+    fn name(&self) -> &str {
+        "postprocess"
+    }
+
+    fn setup(&mut self, app: &mut Application) {
+        self.stack = Some(PostProcessStack::standard(app.render_size()).unwrap());
+        self.pattern = Some(FullscreenPass::new(PATTERN_FRAG).unwrap());
+        self.triangle = Some(FullscreenTriangle::new().unwrap());
+    }
+
+    fn draw(&mut self, app: &mut Application) {
+        let stack = self.stack.as_mut().unwrap();
+        stack.begin(app.render_size()).unwrap();
+        self.pattern.as_ref().unwrap().program.use_program();
+        self.triangle.as_ref().unwrap().draw();
+        stack.finish(app).unwrap();
+    }
+}
+
+#[test]
+fn standard_stack_matches_reference() {
+    golden::assert_scene(PostProcessScene::default(), &GoldenConfig::default());
+}
@@ -0,0 +1,88 @@
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use magiebleue::{Application, HeadlessBackend, WindowContext, hot_reload::WatchedProgram};
+use ultraviolet::IVec2;
+
+/// Sets the modification time of `path` to `seconds` after the epoch, so changes don't depend on timestamp resolution
+fn touch(path: &Path, seconds: u64) {
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(UNIX_EPOCH + Duration::from_secs(seconds))
+        .unwrap();
+}
+
+#[test]
+fn failed_reloads_keep_the_previous_program() {
+    let app = match Application::start_headless(
+        WindowContext {
+            size: IVec2::new(16, 16),
+            window_title: "Magiebleue - hot reload".to_owned(),
+            window_mode: glfw::WindowMode::Windowed,
+        },
+        HeadlessBackend::Egl,
+    ) {
+        Ok(app) => app,
+        Err(e) => {
+            println!("skipping hot reload test: {}", e);
+            return;
+        }
+    };
+
+    let dir = std::env::temp_dir().join(format!("magiebleue-hot-reload-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let include = dir.join("colour.glsl");
+    let (vert, frag) = (dir.join("main.vert"), dir.join("main.frag"));
+    fs::write(&include, "uniform vec4 tint;\n").unwrap();
+    fs::write(
+        &vert,
+        "#version 330 core\nvoid main() { gl_Position = vec4(0.0); }\n",
+    )
+    .unwrap();
+    fs::write(
+        &frag,
+        "#version 330 core\n#include \"colour.glsl\"\nout vec4 colour;\nvoid main() { colour = tint; }\n",
+    )
+    .unwrap();
+    for path in [&include, &vert, &frag] {
+        touch(path, 1000);
+    }
+
+    let mut program = WatchedProgram::from_filepath(
+        vert.to_str().unwrap(),
+        None,
+        None,
+        None,
+        frag.to_str().unwrap(),
+    )
+    .unwrap();
+    assert!(!program.reload_if_changed());
+
+    // a change to the include alone is picked up
+    fs::write(&include, "uniform vec4 tint;\nuniform float strength;\n").unwrap();
+    touch(&include, 2000);
+    assert!(program.changed());
+    let first = program.id();
+    assert!(program.reload_if_changed());
+    assert_ne!(program.id(), first);
+    assert!(program.has_uniform("tint"));
+
+    // a broken include keeps the last program that compiled
+    fs::write(&include, "uniform vec4 tint\n").unwrap();
+    touch(&include, 3000);
+    let working = program.id();
+    assert!(!program.reload_if_changed());
+    assert_eq!(program.id(), working);
+    assert!(program.has_uniform("tint"));
+    // and isn't retried until the file changes again
+    assert!(!program.changed());
+
+    drop(program);
+    drop(app);
+}